@@ -5,7 +5,6 @@ use std::time::Duration;
 
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{BufMut, BytesMut};
-use chrono::prelude::*;
 use serde_json as json;
 use tokio::codec::{Decoder, Encoder};
 
@@ -19,11 +18,28 @@ pub enum ClientCommand {
     Start(String),
     Pause(String),
     Resume(String),
-    Reload(String),
-    Restart(String),
+    Reload(String, bool),
+    Restart(String, bool),
+    ReloadAll(bool),
     Stop(String),
+    Reset(String, bool),
+    Disable(String),
+    Enable(String),
+    AddService(String, json::Value),
+    RemoveService(String),
+    Notify(String, String, json::Value),
+    Scale(String, u16),
+    OverrideNum(String, u16, bool),
+    Signal(String, String),
     Status(String),
     SPid(String),
+    Counters(String),
+    Failures(String),
+    ResetFailures(String),
+    Diagnose(String),
+    Dump,
+    BumpEpoch,
+    PauseReaping(u32),
     Pid,
     Quit,
     Version,
@@ -56,7 +72,7 @@ pub fn read_response(
                         return Ok(resp);
                     } else {
                         if n == 0 {
-                            return Err(io::Error::new(io::ErrorKind::Other, "closed"));
+                            return Err(io::Error::other("closed"));
                         }
                     }
                 }
@@ -95,7 +111,7 @@ fn try_read_response(
 pub fn run(cmd: ClientCommand, sock: &str) -> bool {
     // create commands listener and also check if service process is running
     let mut buf = BytesMut::new();
-    let mut stream = match UnixStream::connect(&sock) {
+    let mut stream = match UnixStream::connect(sock) {
         Ok(mut conn) => {
             conn.set_read_timeout(Some(Duration::new(1, 0)))
                 .expect("Couldn't set read timeout");
@@ -129,6 +145,19 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
         ClientCommand::SPid(name) => {
             send_command(&mut stream, MasterRequest::SPid(name))
         }
+        ClientCommand::Counters(name) => {
+            send_command(&mut stream, MasterRequest::Counters(name))
+        }
+        ClientCommand::Failures(name) => {
+            send_command(&mut stream, MasterRequest::Failures(name))
+        }
+        ClientCommand::Diagnose(name) => {
+            send_command(&mut stream, MasterRequest::Diagnose(name))
+        }
+        ClientCommand::ResetFailures(name) => {
+            println!("Reset `{}` service failure counters.", name);
+            send_command(&mut stream, MasterRequest::ResetFailures(name))
+        }
         ClientCommand::Pause(name) => {
             println!("Pause `{}` service.", name);
             send_command(&mut stream, MasterRequest::Pause(name))
@@ -138,27 +167,75 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
             send_command(&mut stream, MasterRequest::Resume(name))
         }
         ClientCommand::Start(name) => {
-            print!("Starting `{}` service.", name);
+            println!("Starting `{}` service.", name);
             send_command(&mut stream, MasterRequest::Start(name))
         }
-        ClientCommand::Reload(name) => {
-            print!("Reloading `{}` service.", name);
-            send_command(&mut stream, MasterRequest::Reload(name))
+        ClientCommand::Reload(name, reread) => {
+            println!("Reloading `{}` service.", name);
+            send_command(&mut stream, MasterRequest::Reload(name, reread))
         }
-        ClientCommand::Restart(name) => {
-            print!("Restarting `{}` service", name);
-            send_command(&mut stream, MasterRequest::Restart(name))
+        ClientCommand::Restart(name, reread) => {
+            println!("Restarting `{}` service", name);
+            send_command(&mut stream, MasterRequest::Restart(name, reread))
+        }
+        ClientCommand::ReloadAll(reread) => {
+            println!("Reloading all services.");
+            send_command(&mut stream, MasterRequest::ReloadAll(reread))
         }
         ClientCommand::Stop(name) => {
-            print!("Stopping `{}` service.", name);
+            println!("Stopping `{}` service.", name);
             send_command(&mut stream, MasterRequest::Stop(name))
         }
+        ClientCommand::Reset(name, start) => {
+            println!("Reset `{}` service.", name);
+            send_command(&mut stream, MasterRequest::Reset(name, start))
+        }
+        ClientCommand::Disable(name) => {
+            println!("Disabling `{}` service.", name);
+            send_command(&mut stream, MasterRequest::Disable(name))
+        }
+        ClientCommand::Enable(name) => {
+            println!("Enable `{}` service.", name);
+            send_command(&mut stream, MasterRequest::Enable(name))
+        }
+        ClientCommand::AddService(name, mut options) => {
+            println!("Adding `{}` service.", name);
+            if let Some(obj) = options.as_object_mut() {
+                obj.insert("name".to_owned(), json::Value::from(name));
+            }
+            send_command(&mut stream, MasterRequest::AddService(options))
+        }
+        ClientCommand::RemoveService(name) => {
+            println!("Removing `{}` service.", name);
+            send_command(&mut stream, MasterRequest::RemoveService(name))
+        }
+        ClientCommand::Notify(name, message, data) => {
+            println!("Notify `{}` service with `{}`.", name, message);
+            send_command(&mut stream, MasterRequest::Notify(name, message, data))
+        }
+        ClientCommand::Scale(name, num) => {
+            println!("Scaling `{}` service to {} workers.", name, num);
+            send_command(&mut stream, MasterRequest::Scale(name, num))
+        }
+        ClientCommand::OverrideNum(name, num, revert) => {
+            println!("Overriding `{}` service workers to {}.", name, num);
+            send_command(&mut stream, MasterRequest::OverrideNum(name, num, revert))
+        }
+        ClientCommand::Signal(name, sig) => {
+            println!("Sending {} to `{}` service.", sig, name);
+            send_command(&mut stream, MasterRequest::Signal(name, sig))
+        }
+        ClientCommand::Dump => send_command(&mut stream, MasterRequest::Dump),
+        ClientCommand::BumpEpoch => send_command(&mut stream, MasterRequest::BumpEpoch),
+        ClientCommand::PauseReaping(secs) => {
+            send_command(&mut stream, MasterRequest::PauseReaping(secs))
+        }
         ClientCommand::Pid => send_command(&mut stream, MasterRequest::Pid),
         ClientCommand::Version | ClientCommand::VersionCheck => {
             send_command(&mut stream, MasterRequest::Version)
         }
         ClientCommand::Quit => {
-            print!("Quiting.");
+            println!("Quiting.");
             send_command(&mut stream, MasterRequest::Quit)
         }
     };
@@ -169,21 +246,37 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
         return false;
     }
 
-    // read response
+    // read response, progress dots are terminated with new line
+    let mut progress = false;
     loop {
         match try_read_response(&mut stream, &mut buf) {
             Ok(MasterResponse::Pong) => {
                 print!(".");
                 let _ = io::stdout().flush();
+                progress = true;
             }
             Ok(MasterResponse::Done) => {
-                println!();
+                if progress {
+                    println!();
+                }
                 return true;
             }
             Ok(MasterResponse::Pid(pid)) => {
                 println!("{}", pid);
                 return true;
             }
+            Ok(MasterResponse::Epoch(epoch)) => {
+                println!("Epoch: {}", epoch);
+                return true;
+            }
+            Ok(MasterResponse::ReapingPaused(secs)) => {
+                if secs == 0 {
+                    println!("Reaping is resumed");
+                } else {
+                    println!("Reaping is paused for {} seconds", secs);
+                }
+                return true;
+            }
             Ok(MasterResponse::Version(ver)) => match cmd {
                 ClientCommand::VersionCheck => return ver.ends_with(PKG_INFO.version),
                 _ => {
@@ -191,16 +284,36 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
                     return true;
                 }
             },
+            Ok(MasterResponse::Snapshot(snapshot)) => {
+                match json::to_string_pretty(&snapshot) {
+                    Ok(s) => println!("{}", s),
+                    Err(err) => error!("Can not serialize snapshot: {}", err),
+                }
+                return true;
+            }
             Ok(MasterResponse::ServiceStarted) | Ok(MasterResponse::ServiceStopped) => {
                 println!("done");
                 return true;
             }
             Ok(MasterResponse::ServiceStatus(status)) => {
                 println!("Service status: {}", status.0);
-                for worker in status.1 {
+                if let Some(version) = status.1 {
+                    println!("Service version: {}", version);
+                }
+                println!("Workers: {}/{}", (status.2).1, (status.2).0);
+                if let Some(o) = status.5 {
+                    print!("Workers override: {} (configured {}", o.num, o.configured);
+                    if o.revert_on_reload {
+                        print!(", reverts on reload");
+                    }
+                    println!(")");
+                }
+                for (key, value) in &status.4 {
+                    println!("Label {}: {}", key, value);
+                }
+                for worker in status.3 {
                     for ev in worker.1 {
-                        let dt = Local.timestamp(ev.timestamp as i64, 0);
-                        print!("{} {}: ", worker.0, dt.format("%Y-%m-%d %H:%M:%S"));
+                        print!("{} {}: ", worker.0, ev.time);
                         if let Some(ref pid) = ev.pid {
                             print!("(pid:{}) ", pid)
                         }
@@ -220,6 +333,56 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
                 }
                 return true;
             }
+            Ok(MasterResponse::ServiceWorkerCounters(counters)) => {
+                for (name, pid, received, sent) in counters {
+                    println!("{} (pid:{})", name, pid);
+                    for (msg, count) in received {
+                        println!("  received {}: {}", msg, count);
+                    }
+                    for (cmd, count) in sent {
+                        println!("  sent {}: {}", cmd, count);
+                    }
+                }
+                return true;
+            }
+            Ok(MasterResponse::ServiceFailures(failures)) => {
+                println!("starts: {}", failures.starts);
+                println!("failed starts: {}", failures.failed_starts);
+                println!("heartbeat failures: {}", failures.heartbeat_failures);
+                println!("probe failures: {}", failures.probe_failures);
+                println!("timeouts: {}", failures.timeouts);
+                println!("pipe closed: {}", failures.pipe_closed);
+                println!("protocol desyncs: {}", failures.protocol_desyncs);
+                for (sig, count) in failures.signals {
+                    println!("exit by signal {}: {}", sig, count);
+                }
+                for (code, count) in failures.exit_codes {
+                    println!("exit with code {}: {}", code, count);
+                }
+                return true;
+            }
+            Ok(MasterResponse::ServiceDiagnosis(diagnosis)) => {
+                match json::to_string_pretty(&diagnosis) {
+                    Ok(s) => println!("{}", s),
+                    Err(err) => error!("Can not serialize diagnosis: {}", err),
+                }
+                return true;
+            }
+            Ok(MasterResponse::ServiceWorkerAcks(acks)) => {
+                for (name, pid, ok, detail) in acks {
+                    print!("{} (pid:{}): ", name, pid);
+                    match ok {
+                        Some(true) => print!("ok"),
+                        Some(false) => print!("failed"),
+                        None => print!("no response"),
+                    }
+                    match detail {
+                        Some(detail) => println!(", {}", detail),
+                        None => println!(),
+                    }
+                }
+                return true;
+            }
             Ok(MasterResponse::ServiceFailed) => {
                 println!("failed.");
                 return false;
@@ -244,6 +407,38 @@ pub fn run(cmd: ClientCommand, sock: &str) -> bool {
                 error!("Service is stopping");
                 return false;
             }
+            Ok(MasterResponse::ErrorServiceQuarantined) => {
+                error!("Service is quarantined, use reset command");
+                return false;
+            }
+            Ok(MasterResponse::ErrorServiceDisabled) => {
+                error!("Service is disabled, use enable command");
+                return false;
+            }
+            Ok(MasterResponse::ErrorServiceNoWorkers) => {
+                error!("Service has no workers, use scale command");
+                return false;
+            }
+            Ok(MasterResponse::ErrorServiceExists) => {
+                error!("Service already exists");
+                return false;
+            }
+            Ok(MasterResponse::ErrorNotConfirmed) => {
+                error!("Not all workers confirmed command");
+                return false;
+            }
+            Ok(MasterResponse::ErrorUnknownSignal) => {
+                error!("Signal is unknown");
+                return false;
+            }
+            Ok(MasterResponse::ErrorReloadTimeout) => {
+                error!("Reload timed out, old workers keep running");
+                return false;
+            }
+            Ok(MasterResponse::ErrorConfig(err)) => {
+                error!("Can not re-read configuration: {}", err);
+                return false;
+            }
             Ok(resp) => println!("MSG: {:?}", resp),
             Err(err) => {
                 println!("Error: {:?}", err);
@@ -1,4 +1,5 @@
 use client::ClientCommand;
+use serde_json as json;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -7,11 +8,48 @@ struct Cli {
     #[structopt(long = "sock", short = "m", default_value = "fectld.sock")]
     sock: String,
 
-    /// Run command (Supported commands: status, start, reload, restart, stop)
+    /// Run command (Supported commands: status, start, reload, reload-all, restart,
+    /// stop, reset, disable, enable, add-service, remove-service, notify, signal, scale,
+    /// override-num, failures, reset-failures, diagnose, dump, bump-epoch,
+    /// pause-reaping, resume-reaping)
     command: String,
 
     /// Service name
     name: Option<String>,
+
+    /// Re-read service configuration from master's config file on reload/restart
+    /// or reload-all
+    #[structopt(long = "reread-config")]
+    reread_config: bool,
+
+    /// Start service after reset
+    #[structopt(long = "start")]
+    start: bool,
+
+    /// Name of custom command for notify
+    #[structopt(long = "message")]
+    message: Option<String>,
+
+    /// Data of custom command for notify or service options for add-service,
+    /// in json format
+    #[structopt(long = "data")]
+    data: Option<String>,
+
+    /// Desired number of workers for scale and override-num
+    #[structopt(long = "num")]
+    num: Option<u16>,
+
+    /// Restore configured number of workers on reload with re-read configuration
+    #[structopt(long = "revert-on-reload")]
+    revert_on_reload: bool,
+
+    /// Signal name for signal command, i.e. SIGUSR1
+    #[structopt(long = "signal")]
+    signal: Option<String>,
+
+    /// Seconds to pause reaping for pause-reaping, at most 300
+    #[structopt(long = "seconds", default_value = "60")]
+    seconds: u32,
 }
 
 pub fn load_config() -> Option<(ClientCommand, String)> {
@@ -23,6 +61,15 @@ pub fn load_config() -> Option<(ClientCommand, String)> {
     // check client args
     match cmd.as_str() {
         "pid" => return Some((ClientCommand::Pid, sock)),
+        "dump" => return Some((ClientCommand::Dump, sock)),
+        "reload-all" => {
+            return Some((ClientCommand::ReloadAll(args.reread_config), sock))
+        }
+        "bump-epoch" => return Some((ClientCommand::BumpEpoch, sock)),
+        "pause-reaping" => {
+            return Some((ClientCommand::PauseReaping(args.seconds), sock))
+        }
+        "resume-reaping" => return Some((ClientCommand::PauseReaping(0), sock)),
         "quit" => return Some((ClientCommand::Quit, sock)),
         "version" => return Some((ClientCommand::Version, sock)),
         "version-check" => return Some((ClientCommand::VersionCheck, sock)),
@@ -40,10 +87,77 @@ pub fn load_config() -> Option<(ClientCommand, String)> {
     let cmd = match cmd.as_str() {
         "status" => ClientCommand::Status(name),
         "spid" => ClientCommand::SPid(name),
+        "counters" => ClientCommand::Counters(name),
+        "failures" => ClientCommand::Failures(name),
+        "reset-failures" => ClientCommand::ResetFailures(name),
+        "diagnose" => ClientCommand::Diagnose(name),
         "start" => ClientCommand::Start(name),
         "stop" => ClientCommand::Stop(name),
-        "reload" => ClientCommand::Reload(name),
-        "restart" => ClientCommand::Restart(name),
+        "reset" => ClientCommand::Reset(name, args.start),
+        "disable" => ClientCommand::Disable(name),
+        "enable" => ClientCommand::Enable(name),
+        "add-service" => {
+            let options = match args.data.as_ref().map(|data| json::from_str(data)) {
+                Some(Ok(json::Value::Object(options))) => options,
+                Some(Ok(_)) => {
+                    println!("Service options have to be json object");
+                    return None;
+                }
+                Some(Err(err)) => {
+                    println!("Can not parse service options: {}", err);
+                    return None;
+                }
+                None => {
+                    println!("Service options are required, use --data");
+                    return None;
+                }
+            };
+            ClientCommand::AddService(name, json::Value::Object(options))
+        }
+        "remove-service" => ClientCommand::RemoveService(name),
+        "notify" => {
+            let message = match args.message {
+                Some(ref message) => message.clone(),
+                None => {
+                    println!("Custom command name is required, use --message");
+                    return None;
+                }
+            };
+            let data = match args.data {
+                Some(ref data) => match json::from_str(data) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        println!("Can not parse command data: {}", err);
+                        return None;
+                    }
+                },
+                None => json::Value::Null,
+            };
+            ClientCommand::Notify(name, message, data)
+        }
+        "signal" => match args.signal {
+            Some(ref sig) => ClientCommand::Signal(name, sig.clone()),
+            None => {
+                println!("Signal name is required, use --signal");
+                return None;
+            }
+        },
+        "scale" => match args.num {
+            Some(num) => ClientCommand::Scale(name, num),
+            None => {
+                println!("Number of workers is required, use --num");
+                return None;
+            }
+        },
+        "override-num" => match args.num {
+            Some(num) => ClientCommand::OverrideNum(name, num, args.revert_on_reload),
+            None => {
+                println!("Number of workers is required, use --num");
+                return None;
+            }
+        },
+        "reload" => ClientCommand::Reload(name, args.reread_config),
+        "restart" => ClientCommand::Restart(name, args.reread_config),
         "pause" => ClientCommand::Pause(name),
         "resume" => ClientCommand::Resume(name),
         _ => {
@@ -51,5 +165,5 @@ pub fn load_config() -> Option<(ClientCommand, String)> {
             return None;
         }
     };
-    return Some((cmd, sock));
+    Some((cmd, sock))
 }
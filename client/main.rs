@@ -7,7 +7,6 @@ extern crate serde_json;
 extern crate serde_derive;
 
 extern crate structopt;
-#[macro_use]
 extern crate structopt_derive;
 
 extern crate byteorder;
@@ -17,9 +16,11 @@ extern crate tokio;
 
 mod client;
 mod config;
+#[allow(dead_code)]
 mod version {
     include!(concat!(env!("OUT_DIR"), "/version.rs"));
 }
+#[allow(dead_code)]
 mod event {
     include!("../src/event.rs");
 }
@@ -28,7 +29,7 @@ mod master_types {
 }
 
 fn main() {
-    let _ = env_logger::init();
+    env_logger::init();
 
     let success = match config::load_config() {
         Some((cmd, sock)) => client::run(cmd, &sock),
@@ -3,6 +3,9 @@
 class ConfigurationError(Exception):
     """ Exception raised on config error """
 
+    #: optional error code, reported to master process
+    code = None
+
 
 class UnsupportedWorker(ConfigurationError):
     """ Worker is not supported by application """
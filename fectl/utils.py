@@ -28,7 +28,7 @@ def pack_message(cmd, data=None):
         msg['data'] = data
 
     msg = json.dumps(msg).encode('utf-8')
-    return struct.pack('>h', len(msg)) + msg
+    return struct.pack('>I', len(msg)) + msg
 
 
 CMD_PREPARE = 'prepare'
@@ -100,7 +100,8 @@ class AsyncioWorker(Worker):
             yield from self._run_loop()
         except utils.ConfigurationError as e:
             exc = e
-            self.notify(self.MSG_CFG_ERROR, str(e))
+            self.notify(self.MSG_CFG_ERROR,
+                        {'code': e.code, 'message': str(e)})
         except BaseException as e:
             exc = e
 
@@ -214,6 +215,8 @@ class AsyncioWorker(Worker):
         except:
             pass
 
+        # master does not wait for shutdown timeout
+        self.notify(self.MSG_STOPPED)
         yield from asyncio.sleep(0.1, loop=self._loop)
 
         self._read_task.cancel()
@@ -272,16 +275,20 @@ class AsyncioWorker(Worker):
                 elif cmd == self.CMD_PAUSE:
                     for app in self._apps:
                         yield from app.pause()
+                    self.notify(self.MSG_PAUSED)
 
                 elif cmd == self.CMD_RESUME:
                     for app in self._apps:
                         yield from app.resume()
+                    self.notify(self.MSG_RESUMED)
 
                 elif cmd == self.CMD_START:
                     for app in self._apps:
                         yield from app.start()
 
                 elif cmd == self.CMD_STOP:
+                    self.notify(self.MSG_STOPPING)
+
                     # init closing process
                     self._stopping = asyncio.ensure_future(
                         self._stop(), loop=self._loop)
@@ -339,12 +346,12 @@ class ReadProtocol:
     def data_received(self, data):
         self._buf += data
 
-        if self._read_queue is not None and len(self._buf) >= 2:
-            data = self._buf[:2]
-            size = struct.unpack('>h', data)[0]
+        if self._read_queue is not None and len(self._buf) >= 4:
+            data = self._buf[:4]
+            size = struct.unpack('>I', data)[0]
 
-            if len(self._buf) >= size + 2:
-                data = self._buf[2:size+2]
+            if len(self._buf) >= size + 4:
+                data = self._buf[4:size+4]
                 cmd, data = utils.unpack_message(data)
-                self._buf = self._buf[size+2:]
+                self._buf = self._buf[size+4:]
                 self._read_queue.put_nowait((cmd, data))
@@ -23,6 +23,10 @@ class Worker(object):
     MSG_RESTART = 'restart'
     MSG_HEARTBEAT = 'hb'
     MSG_CFG_ERROR = 'cfgerror'
+    MSG_PAUSED = 'paused'
+    MSG_RESUMED = 'resumed'
+    MSG_STOPPING = 'stopping'
+    MSG_STOPPED = 'stopped'
 
     CMD_PREPARE = 'prepare'
     CMD_START = 'start'
@@ -67,8 +67,8 @@ class GeventWorker(Worker):
 
         while True:
             try:
-                data = f.read(2)
-                size = struct.unpack('>h', data)[0]
+                data = f.read(4)
+                size = struct.unpack('>I', data)[0]
                 data = f.read(size)
                 cmd, data = utils.unpack_message(data)
             except:
@@ -76,6 +76,24 @@ class GeventWorker(Worker):
                 self._alive = False
                 break
 
+            if cmd == self.CMD_HEARTBEAT:
+                continue
+
+            # application pauses and resumes in message handlers
+            for cb in self._on_msg:
+                try:
+                    cb(cmd, data)
+                except:
+                    logging.exception('Exception in message handler')
+
+            if cmd == self.CMD_PAUSE:
+                self.notify(self.MSG_PAUSED)
+            elif cmd == self.CMD_RESUME:
+                self.notify(self.MSG_RESUMED)
+            elif cmd == self.CMD_STOP:
+                self.notify(self.MSG_STOPPING)
+                self._alive = False
+
     def _run(self):
         gevent.spawn(self._read_loop)
         gevent.spawn(self._write_loop)
@@ -103,6 +121,10 @@ class GeventWorker(Worker):
             except BaseException as exc:
                 logging.info("Shutdown callback exception: %s", exc)
 
+        # master does not wait for shutdown timeout
+        self.notify(self.MSG_STOPPED)
+        gevent.sleep(0.1)
+
     def _handle_quit(self, sig, frame):
         # Move this out of the signal handler so we can use blocking calls.
         gevent.spawn(super(GeventWorker, self)._handle_quit, sig, frame)
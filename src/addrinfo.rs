@@ -1,5 +1,17 @@
 // this is copy from https://github.com/keeperofdakeys/dns-lookup
-#![allow(dead_code)]
+// copied code is kept as is, lints are not fixed here
+#![allow(dead_code, deprecated, bare_trait_objects)]
+#![allow(
+    clippy::clone_on_copy,
+    clippy::extra_unused_lifetimes,
+    clippy::io_other_error,
+    clippy::missing_transmute_annotations,
+    clippy::needless_borrow,
+    clippy::transmute_ptr_to_ref,
+    clippy::unnecessary_cast,
+    clippy::upper_case_acronyms,
+    clippy::wrong_self_convention
+)]
 
 use libc;
 use std::error::Error;
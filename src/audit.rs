@@ -0,0 +1,172 @@
+// Audit log of operator commands, separate from operational log
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+
+use serde_json as json;
+
+use disk;
+use event::now_rfc3339;
+use master_types::{MasterRequest, MasterResponse};
+use utils;
+
+/// Credentials of control socket peer
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Principal {
+    pub pid: i32,
+    pub uid: u32,
+}
+
+/// Audited request, action name, service name and request details
+pub type Action = (&'static str, String, Option<String>);
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    time: String,
+    principal: Option<Principal>,
+    action: &'a str,
+    service: &'a str,
+    detail: &'a Option<String>,
+    result: &'a str,
+}
+
+pub struct AuditLog {
+    path: OsString,
+    file: RefCell<File>,
+}
+
+impl AuditLog {
+    /// Open audit log file in append mode
+    pub fn open(path: &OsString) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(AuditLog {
+            path: path.clone(),
+            file: RefCell::new(file),
+        })
+    }
+
+    /// Append record, one json object per line.
+    /// Records are dropped while disk is full.
+    pub fn record(&self, principal: Option<Principal>, action: &Action, result: &str) {
+        let rec = AuditRecord {
+            time: now_rfc3339(),
+            principal,
+            action: action.0,
+            service: &action.1,
+            detail: &action.2,
+            result,
+        };
+        let mut line = json::to_string(&rec).unwrap();
+        line.push('\n');
+
+        let mut file = self.file.borrow_mut();
+        let res = disk::write("audit log", || {
+            file.write_all(line.as_ref()).and_then(|_| file.flush())
+        });
+        if let Err(err) = res {
+            error!("Can not write audit log {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// Audited action of mutating request, read-only requests are not audited
+pub fn action(req: &MasterRequest) -> Option<Action> {
+    let action = match *req {
+        MasterRequest::Start(ref name) => ("start", name.clone(), None),
+        MasterRequest::Pause(ref name) => ("pause", name.clone(), None),
+        MasterRequest::Resume(ref name) => ("resume", name.clone(), None),
+        MasterRequest::Reload(ref name, reread) => {
+            ("reload", name.clone(), Some(format!("reread-config: {}", reread)))
+        }
+        MasterRequest::Restart(ref name, reread) => {
+            ("restart", name.clone(), Some(format!("reread-config: {}", reread)))
+        }
+        MasterRequest::ReloadAll(reread) => (
+            "reload-all",
+            String::new(),
+            Some(format!("reread-config: {}", reread)),
+        ),
+        MasterRequest::Stop(ref name) => ("stop", name.clone(), None),
+        MasterRequest::Reset(ref name, start) => {
+            ("reset", name.clone(), Some(format!("start: {}", start)))
+        }
+        MasterRequest::ResetFailures(ref name) => ("reset-failures", name.clone(), None),
+        MasterRequest::Disable(ref name) => ("disable", name.clone(), None),
+        MasterRequest::Enable(ref name) => ("enable", name.clone(), None),
+        MasterRequest::AddService(ref options) => {
+            let name = options.get("name").and_then(|name| name.as_str());
+            (
+                "add-service",
+                name.unwrap_or_default().to_owned(),
+                Some(format!("options: {}", redact_options(options))),
+            )
+        }
+        MasterRequest::RemoveService(ref name) => {
+            ("remove-service", name.clone(), None)
+        }
+        MasterRequest::Scale(ref name, num) => {
+            ("scale", name.clone(), Some(format!("num: {}", num)))
+        }
+        MasterRequest::OverrideNum(ref name, num, revert) => (
+            "override-num",
+            name.clone(),
+            Some(format!("num: {}, revert-on-reload: {}", num, revert)),
+        ),
+        MasterRequest::Signal(ref name, ref sig) => {
+            ("signal", name.clone(), Some(format!("signal: {}", sig)))
+        }
+        MasterRequest::Notify(ref name, ref cmd, _) => {
+            ("notify", name.clone(), Some(format!("message: {}", cmd)))
+        }
+        MasterRequest::BumpEpoch => ("bump-epoch", String::new(), None),
+        MasterRequest::PauseReaping(secs) => {
+            ("pause-reaping", String::new(), Some(format!("seconds: {}", secs)))
+        }
+        MasterRequest::Quit => ("quit", String::new(), None),
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Service options with secret-looking `env` values hidden
+fn redact_options(options: &json::Value) -> json::Value {
+    let mut options = options.clone();
+    if let Some(env) = options.get_mut("env").and_then(|env| env.as_object_mut()) {
+        for (name, value) in env.iter_mut() {
+            if utils::is_secret_env(name) {
+                *value = json::Value::from(utils::redact_env(name, ""));
+            }
+        }
+    }
+    options
+}
+
+/// Short description of master response
+pub fn result(resp: &MasterResponse) -> String {
+    match *resp {
+        MasterResponse::ServiceWorkerAcks(ref acks) => {
+            format!("ServiceWorkerAcks({})", acks.len())
+        }
+        ref resp => format!("{:?}", resp),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_service_env_redacted() {
+        let options = json::from_str(
+            r#"{"name": "app", "command": "app serve",
+                "env": {"API_TOKEN": "t0ken", "DB_PASSWORD": "pa55", "PORT": "8080"}}"#,
+        ).unwrap();
+        let action = action(&MasterRequest::AddService(options)).unwrap();
+        let detail = action.2.unwrap();
+        assert_eq!(action.1, "app");
+        assert!(!detail.contains("t0ken"));
+        assert!(!detail.contains("pa55"));
+        assert!(detail.contains("8080"));
+    }
+}
@@ -0,0 +1,153 @@
+// Worker process capabilities, Linux only. Listed capabilities are kept
+// over uid drop and exec in ambient set, all other capabilities are dropped
+use std::io;
+
+use libc;
+
+const PR_SET_KEEPCAPS: libc::c_int = 8;
+const PR_CAP_AMBIENT: libc::c_int = 47;
+const PR_CAP_AMBIENT_RAISE: libc::c_ulong = 2;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+const NAMES: [&str; 41] = [
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+/// `struct __user_cap_header_struct`
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+/// `struct __user_cap_data_struct`
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Capability number by name, i.e. `CAP_NET_BIND_SERVICE` or `net_bind_service`
+pub fn parse(name: &str) -> Option<u32> {
+    let name = name.to_uppercase();
+    let name = name.trim_start_matches("CAP_");
+    NAMES.iter().position(|n| *n == name).map(|cap| cap as u32)
+}
+
+/// Keep permitted capabilities over uid change, used by forked worker
+/// before privileges drop
+#[cfg(target_os = "linux")]
+pub fn keep() -> io::Result<()> {
+    if unsafe { libc::prctl(PR_SET_KEEPCAPS, 1, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restrict capabilities of current process to `caps` and raise them
+/// in ambient set, used by forked worker after privileges drop
+#[cfg(target_os = "linux")]
+pub fn apply(caps: &[u32]) -> io::Result<()> {
+    let mut data = [CapData::default(); 2];
+    for cap in caps {
+        let mask = 1 << (cap % 32);
+        let data = &mut data[(cap / 32) as usize];
+        data.effective |= mask;
+        data.permitted |= mask;
+        data.inheritable |= mask;
+    }
+    let header = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    unsafe {
+        if libc::syscall(libc::SYS_capset, &header, data.as_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // ambient capabilities are preserved over exec of unprivileged program
+        for cap in caps {
+            let cap = libc::c_ulong::from(*cap);
+            if libc::prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, cap, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn keep() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "capabilities are not supported",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_: &[u32]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "capabilities are not supported",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("CAP_NET_BIND_SERVICE"), Some(10));
+        assert_eq!(parse("NET_BIND_SERVICE"), Some(10));
+        assert_eq!(parse("net_bind_service"), Some(10));
+        assert_eq!(parse("cap_net_bind_service"), Some(10));
+        assert_eq!(parse("CHOWN"), Some(0));
+        assert_eq!(parse("CAP_CHECKPOINT_RESTORE"), Some(40));
+
+        assert_eq!(parse("NET_BIND"), None);
+        assert_eq!(parse("CAP_"), None);
+        assert_eq!(parse(""), None);
+    }
+}
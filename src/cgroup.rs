@@ -0,0 +1,92 @@
+// Service cgroup v2 resource limits and accounting, Linux only
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use config::CgroupConfig;
+use event::CgroupUsage;
+
+/// cgroup directory of service
+pub fn path(cfg: &CgroupConfig, service: &str) -> PathBuf {
+    Path::new(&cfg.path).join(service)
+}
+
+/// Create service cgroup and apply limits, used by master process
+pub fn setup(cfg: &CgroupConfig, service: &str) -> Result<(), String> {
+    let parent = Path::new(&cfg.path);
+    fs::create_dir_all(parent).map_err(|err| error(parent, &err))?;
+
+    // controllers have to be enabled in parent cgroup
+    let mut controllers = Vec::new();
+    if cfg.memory_max.is_some() {
+        controllers.push("+memory");
+    }
+    if cfg.cpu_max.is_some() {
+        controllers.push("+cpu");
+    }
+    if cfg.pids_max.is_some() {
+        controllers.push("+pids");
+    }
+    if !controllers.is_empty() {
+        write(&parent.join("cgroup.subtree_control"), &controllers.join(" "))?;
+    }
+
+    let dir = path(cfg, service);
+    fs::create_dir_all(&dir).map_err(|err| error(&dir, &err))?;
+
+    let limits = [
+        ("memory.max", &cfg.memory_max),
+        ("cpu.max", &cfg.cpu_max),
+        ("pids.max", &cfg.pids_max),
+    ];
+    for &(name, value) in &limits {
+        if let Some(ref value) = *value {
+            write(&dir.join(name), value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move current process to service cgroup, used by forked worker before exec
+pub fn join(cfg: &CgroupConfig, service: &str) -> Result<(), String> {
+    write(&path(cfg, service).join("cgroup.procs"), "0")
+}
+
+/// Memory and cpu usage of service cgroup
+pub fn usage(cfg: &CgroupConfig, service: &str) -> CgroupUsage {
+    let dir = path(cfg, service);
+    let memory_current = fs::read_to_string(dir.join("memory.current"))
+        .ok()
+        .and_then(|s| s.trim().parse().ok());
+    let cpu_usage_usec = fs::read_to_string(dir.join("cpu.stat"))
+        .ok()
+        .and_then(|stat| {
+            stat.lines()
+                .filter_map(|line| {
+                    let mut parts = line.split_whitespace();
+                    match (parts.next(), parts.next()) {
+                        (Some("usage_usec"), Some(val)) => val.parse().ok(),
+                        _ => None,
+                    }
+                }).next()
+        });
+
+    CgroupUsage {
+        memory_current,
+        cpu_usage_usec,
+    }
+}
+
+fn write(path: &Path, value: &str) -> Result<(), String> {
+    fs::write(path, value).map_err(|err| error(path, &err))
+}
+
+fn error(path: &Path, err: &io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => format!(
+            "{:?}: {}, cgroup is not delegated to fectld user",
+            path, err
+        ),
+        _ => format!("{:?}: {}", path, err),
+    }
+}
@@ -47,7 +47,7 @@ pub fn read_response(
                         return Ok(resp);
                     } else {
                         if n == 0 {
-                            return Err(io::Error::new(io::ErrorKind::Other, "closed"));
+                            return Err(io::Error::other("closed"));
                         }
                     }
                 }
@@ -1,18 +1,66 @@
-use std::collections::HashMap;
+use std;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 
-use nix::sys::wait::{waitpid, WaitStatus, WNOHANG};
-use nix::unistd::getpid;
+use libc;
+use nix;
+use nix::errno::Errno;
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::{waitpid, WaitStatus, WCONTINUED, WNOHANG, WUNTRACED};
+use nix::unistd::{getpid, getppid, Pid};
 
 use actix::actors::signal;
+use actix::dev::ToEnvelope;
 use actix::prelude::*;
 use actix::Response;
-use futures::Future;
+use futures::sync::oneshot;
+use futures::{future, stream, Future, Stream};
 
-use config::Config;
-use event::{Reason, ServiceStatus};
-use process::ProcessError;
+use config::{self, Config, ServiceConfig, ServiceOrder};
+use disk;
+use event::{
+    self, Diagnosis, FailureCounters, Reason, ServiceStatus, Snapshot, WorkerAck,
+    WorkerCounters,
+};
+use probe;
+use process::{self, ProcessError};
+use serde_json as json;
 use service::{self, FeService, ReloadStatus, ServiceOperationError, StartStatus};
+use statsd;
+use utils;
+use version::PKG_INFO;
+
+/// Time for internal actors to respond to self check
+const SELF_CHECK_TIMEOUT: u64 = 5;
+
+/// Configuration file is reloaded after it does not change for this many seconds,
+/// same delay is used for service `watch_files`
+pub const WATCH_CONFIG_DELAY: u64 = 2;
+
+/// Time for orphaned processes to exit after `SIGTERM` in init mode
+const ORPHANS_TIMEOUT: u64 = 5;
+
+/// Maximum time in seconds reaping of exited workers can be paused
+pub const MAX_REAP_PAUSE: u64 = 300;
+
+thread_local! {
+    /// exit code of supervisor process, actix system can not be stopped
+    /// with custom code
+    static EXIT_CODE: Cell<i32> = const { Cell::new(0) };
+}
+
+/// Exit code of supervisor process after actix system stops
+pub fn exit_code() -> i32 {
+    EXIT_CODE.with(|code| code.get())
+}
+
+/// Supervisor process exits with code 1, some service failed worker
+/// handshake in validation mode or all services failed
+fn set_failed() {
+    EXIT_CODE.with(|code| code.set(1));
+}
 
 #[derive(Debug)]
 /// Command center errors
@@ -23,6 +71,10 @@ pub enum CommandError {
     UnknownService,
     /// service is stopped
     ServiceStopped,
+    /// service with same name is already running
+    ServiceExists,
+    /// can not re-read configuration
+    ConfigError(String),
     /// underlying service error
     Service(ServiceOperationError),
 }
@@ -38,22 +90,64 @@ pub struct CommandCenter {
     cfg: Rc<Config>,
     state: State,
     services: HashMap<String, Addr<FeService>>,
+    /// services that stop before removal, they still get worker exits
+    /// but do not accept commands
+    removing: HashSet<String>,
+    /// services added with `add-service`, they are not in configuration file,
+    /// so re-read of configuration does not remove them
+    runtime: HashSet<String>,
+    /// service names in configuration file order
+    order: Vec<String>,
+    /// shift of `rotating` service order
+    rotation: Cell<usize>,
     stop_waiter: Option<actix::Condition<bool>>,
     stopping: usize,
+    /// last seen config file modification time and time of change
+    config_mtime: Option<SystemTime>,
+    config_changed: Option<Instant>,
+    reap_scheduled: bool,
+    /// reaping is paused until this time, exited workers stay zombies
+    reap_paused: Option<Instant>,
+    /// highest observed number of open fds
+    max_fds: usize,
+    /// ready file is not written because disk is full
+    ready_file_pending: bool,
+    /// orphaned processes are killed after this time, init mode only
+    orphans_deadline: Option<Instant>,
+    exiting: bool,
 }
 
 impl CommandCenter {
     pub fn start(cfg: Rc<Config>) -> Addr<CommandCenter> {
+        process::set_epoch(cfg.master.epoch);
         CommandCenter {
             cfg,
             state: State::Starting,
             services: HashMap::new(),
+            removing: HashSet::new(),
+            runtime: HashSet::new(),
+            order: Vec::new(),
+            rotation: Cell::new(0),
             stop_waiter: None,
             stopping: 0,
+            config_mtime: None,
+            config_changed: None,
+            reap_scheduled: false,
+            reap_paused: None,
+            max_fds: 0,
+            ready_file_pending: false,
+            orphans_deadline: None,
+            exiting: false,
         }.start()
     }
 
-    fn exit(&mut self) {
+    fn exit(&mut self, ctx: &mut Context<Self>) {
+        // in init mode exit is delayed until orphaned processes are gone
+        let init = self.cfg.master.init && self.orphans_deadline.is_none();
+        if init && self.stop_orphans(ctx) {
+            return;
+        }
+        self.exiting = true;
         if let Some(waiter) = self.stop_waiter.take() {
             waiter.set(true);
         }
@@ -61,54 +155,914 @@ impl CommandCenter {
         System::current().stop();
     }
 
+    /// start all services, report result of worker handshake and stop
+    fn validate(&mut self, ctx: &mut Context<Self>) {
+        let starts: Vec<_> = self
+            .services
+            .iter()
+            .map(|(name, srv)| {
+                let name = name.clone();
+                srv.send(service::Start).then(move |res| {
+                    let passed = matches!(res, Ok(Ok(StartStatus::Success)));
+                    Ok::<_, ()>((name, passed))
+                })
+            }).collect();
+        future::join_all(starts)
+            .into_actor(self)
+            .then(|res: Result<Vec<(String, bool)>, ()>, act, ctx| {
+                if let Ok(mut results) = res {
+                    results.sort();
+                    for &(ref name, passed) in &results {
+                        if passed {
+                            println!("Service {:?}: handshake passed", name);
+                        } else {
+                            println!("Service {:?}: handshake failed", name);
+                            set_failed();
+                        }
+                    }
+                }
+                act.stop(ctx, true);
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    /// terminate processes that are left after all services stopped,
+    /// only PID 1 can signal all processes in container. returns `false`
+    /// if there is nothing to wait for
+    fn stop_orphans(&mut self, ctx: &mut Context<Self>) -> bool {
+        if getpid() != Pid::from_raw(1) {
+            return false;
+        }
+        info!("Stopping orphaned processes");
+        if kill(Pid::from_raw(-1), Signal::SIGTERM).is_err() {
+            return false;
+        }
+        self.orphans_deadline = Some(Instant::now() + Duration::new(ORPHANS_TIMEOUT, 0));
+        self.wait_orphans(ctx);
+        true
+    }
+
+    /// check orphaned processes periodically, exit when all are reaped
+    fn wait_orphans(&mut self, ctx: &mut Context<Self>) {
+        // exits of workers and commands are reported as usual
+        while self.reap(None) {}
+        if !utils::has_children() {
+            return self.exit(ctx);
+        }
+        if self.orphans_deadline.is_some_and(|t| t <= Instant::now()) {
+            warn!("Orphaned processes did not exit in time, killing");
+            let _ = kill(Pid::from_raw(-1), Signal::SIGKILL);
+            return self.exit(ctx);
+        }
+        ctx.run_later(Duration::from_millis(100), |act, ctx| act.wait_orphans(ctx));
+    }
+
+    /// increase supervisor epoch, new worker processes get new epoch,
+    /// i.e. after reload
+    pub fn bump_epoch(&self) -> u64 {
+        let epoch = process::epoch() + 1;
+        process::set_epoch(epoch);
+        info!("Supervisor epoch is {}", epoch);
+        epoch
+    }
+
+    /// supervisor and all services state, each service reports
+    /// its state in one pass
+    pub fn snapshot(&self) -> Box<dyn Future<Item = Snapshot, Error = CommandError>> {
+        let mut names: Vec<_> = self.services.keys().cloned().collect();
+        names.sort();
+        let dumps: Vec<_> = names
+            .iter()
+            .map(|name| {
+                self.services[name]
+                    .send(service::Dump)
+                    .then(|res| match res {
+                        Ok(Ok(snapshot)) => Ok(snapshot),
+                        _ => Err(CommandError::UnknownService),
+                    })
+            }).collect();
+
+        let version = format!("{} {}", PKG_INFO.name, PKG_INFO.version);
+        let config = self.cfg.path.to_string_lossy().into_owned();
+        let time = event::now_rfc3339();
+        let disk_full = disk::is_full();
+        let epoch = process::epoch();
+        let reaping_paused = self.reaping_paused();
+        Box::new(future::join_all(dumps).map(move |services| Snapshot {
+            version,
+            config,
+            time,
+            disk_full,
+            epoch,
+            reaping_paused,
+            services,
+            pid: format!("{}", getpid()),
+        }))
+    }
+
+    /// worker failure counters of service
+    pub fn failure_counters(
+        &self, name: &str,
+    ) -> Box<dyn Future<Item = FailureCounters, Error = CommandError>> {
+        match self.service(name) {
+            Some(service) => Box::new(
+                service
+                    .send(service::Failures)
+                    .map_err(|_| CommandError::UnknownService),
+            ),
+            None => Box::new(future::err(CommandError::UnknownService)),
+        }
+    }
+
+    /// likely causes why service does not run desired number of workers
+    pub fn diagnose(
+        &self, name: &str,
+    ) -> Box<dyn Future<Item = Diagnosis, Error = CommandError>> {
+        match self.service(name) {
+            Some(service) => Box::new(
+                service
+                    .send(service::Diagnose)
+                    .then(|res| match res {
+                        Ok(Ok(diagnosis)) => Ok(diagnosis),
+                        _ => Err(CommandError::UnknownService),
+                    }),
+            ),
+            None => Box::new(future::err(CommandError::UnknownService)),
+        }
+    }
+
+    /// clear worker failure counters of service
+    pub fn reset_failure_counters(
+        &self, name: &str,
+    ) -> Box<dyn Future<Item = (), Error = CommandError>> {
+        match self.service(name) {
+            Some(service) => Box::new(
+                service
+                    .send(service::ResetFailures)
+                    .map_err(|_| CommandError::UnknownService),
+            ),
+            None => Box::new(future::err(CommandError::UnknownService)),
+        }
+    }
+
+    /// override desired number of workers of service, configured `num`
+    /// is restored on next reload with re-read configuration if flag is set
+    pub fn override_num(
+        &self, name: &str, num: u16, revert_on_reload: bool,
+    ) -> Box<dyn Future<Item = (), Error = CommandError>> {
+        match self.service(name) {
+            Some(service) => Box::new(
+                service
+                    .send(service::OverrideNum(num, revert_on_reload))
+                    .then(|res| match res {
+                        Ok(Ok(_)) => Ok(()),
+                        Ok(Err(err)) => Err(CommandError::Service(err)),
+                        Err(_) => Err(CommandError::UnknownService),
+                    }),
+            ),
+            None => Box::new(future::err(CommandError::UnknownService)),
+        }
+    }
+
+    /// start new service at runtime, service is not added to configuration file
+    pub fn add_service(&mut self, mut cfg: ServiceConfig) -> Result<(), CommandError> {
+        if self.state != State::Running {
+            warn!("Can not add service in `{:?}` state", self.state);
+            return Err(CommandError::NotReady);
+        }
+        if self.services.contains_key(&cfg.name) {
+            // includes service that is being removed
+            return Err(CommandError::ServiceExists);
+        }
+        config::prepare_service(&mut cfg).map_err(|err| {
+            error!("{}", err);
+            CommandError::ConfigError(err)
+        })?;
+
+        info!("Adding service {:?}", cfg.name);
+        let service = FeService::start(cfg.num, cfg.clone());
+        self.services.insert(cfg.name.clone(), service);
+        self.order.push(cfg.name);
+        Ok(())
+    }
+
+    /// stop all workers of service and forget service, receiver resolves after
+    /// removal. Service gets started again on `fectld` restart if it is in
+    /// configuration file.
+    pub fn remove_service(
+        &mut self, ctx: &mut Context<Self>, name: &str, graceful: bool,
+    ) -> Result<oneshot::Receiver<()>, CommandError> {
+        if self.state != State::Running {
+            warn!("Can not remove service in `{:?}` state", self.state);
+            return Err(CommandError::NotReady);
+        }
+        let service = match self.service(name) {
+            Some(service) => service.clone(),
+            None => return Err(CommandError::UnknownService),
+        };
+
+        // service stays in services map until workers stop,
+        // so it gets exits of its worker processes
+        info!("Removing service {:?}", name);
+        self.removing.insert(name.to_owned());
+        self.order.retain(|n| n != name);
+
+        let (tx, rx) = oneshot::channel();
+        let name = name.to_owned();
+        service
+            .send(service::Stop(graceful, Reason::ConsoleRequest))
+            .into_actor(self)
+            .then(move |_, act, _| {
+                // stopped or failed service has no workers to stop
+                act.forget_service(&name);
+                let _ = tx.send(());
+                actix::fut::ok(())
+            }).spawn(ctx);
+        Ok(rx)
+    }
+
+    /// drop stopped service, its actor stops with pending timers,
+    /// so workers can not be restarted
+    fn forget_service(&mut self, name: &str) {
+        self.removing.remove(name);
+        self.runtime.remove(name);
+        if let Some(service) = self.services.remove(name) {
+            service.do_send(service::Shutdown);
+        }
+        process::forget_peers(name);
+        info!("Service {:?} is removed", name);
+    }
+
+    /// re-read configuration file, returns services configuration
+    fn reread_config(&self) -> Result<Vec<ServiceConfig>, CommandError> {
+        info!("Re-reading configuration file {:?}", self.cfg.path);
+        let host = utils::hostname().unwrap_or_default();
+        config::reload_services(&self.cfg.path, &host).map_err(|err| {
+            error!("{}", err);
+            CommandError::ConfigError(err)
+        })
+    }
+
+    /// latest modification time of configuration file and service directory,
+    /// added and removed files change modification time of directory
+    fn config_mtime(&self) -> Option<SystemTime> {
+        let mtime = utils::mtime(&self.cfg.path);
+        match self.cfg.master.service_dir {
+            Some(ref dir) => {
+                let files: Vec<_> = std::fs::read_dir(dir)
+                    .map(|entries| {
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .filter_map(|entry| utils::mtime(entry.path()))
+                            .collect()
+                    }).unwrap_or_default();
+                mtime
+                    .into_iter()
+                    .chain(utils::mtime(dir))
+                    .chain(files)
+                    .max()
+            }
+            None => mtime,
+        }
+    }
+
+    /// reload all services if configuration file got changed,
+    /// reload is delayed until file stops changing
+    fn watch_config(&mut self, ctx: &mut Context<Self>) {
+        let mtime = self.config_mtime();
+        if mtime.is_some() && mtime != self.config_mtime {
+            self.config_mtime = mtime;
+            self.config_changed = Some(Instant::now());
+            return;
+        }
+
+        let delay = Duration::new(WATCH_CONFIG_DELAY, 0);
+        if self.config_changed.is_some_and(|changed| changed.elapsed() >= delay) {
+            self.config_changed = None;
+            if self.state == State::Running {
+                info!("Configuration file changed: {:?}", self.cfg.path);
+                ctx.notify(ReloadAll(true));
+            }
+        }
+    }
+
+    /// send number of running workers of each service to StatsD agent
+    fn flush_gauges(&mut self, ctx: &mut Context<Self>) {
+        for (name, srv) in &self.services {
+            let name = name.clone();
+            srv.send(service::Pids)
+                .into_actor(self)
+                .then(move |res, _, _| {
+                    if let Ok(pids) = res {
+                        statsd::gauge(&name, "running", pids.len());
+                    }
+                    actix::fut::ok(())
+                }).spawn(ctx);
+        }
+    }
+
+    /// check that service and process actors are responsive
+    fn self_check(&mut self, ctx: &mut Context<Self>) {
+        // number of fds should not grow with worker restarts
+        if let Some(fds) = utils::open_fds() {
+            debug!("Open file descriptors: {}", fds);
+            if fds > self.max_fds {
+                if self.max_fds != 0 {
+                    info!("Open file descriptors: {} (max was {})", fds, self.max_fds);
+                }
+                self.max_fds = fds;
+            }
+        }
+
+        let timeout = Duration::new(SELF_CHECK_TIMEOUT, 0);
+        let checks: Vec<_> = self
+            .services
+            .iter()
+            .map(|(name, srv)| {
+                let name = name.clone();
+                srv.send(service::SelfCheck(timeout))
+                    .timeout(timeout * 2)
+                    .then(move |res| match res {
+                        Ok(Ok(ok)) => Ok(ok),
+                        _ => {
+                            error!("Service actor is stuck: {:?}", name);
+                            Ok::<_, ()>(false)
+                        }
+                    })
+            }).collect();
+
+        future::join_all(checks)
+            .into_actor(self)
+            .then(|res, act, _| {
+                let ok = match res {
+                    Ok(res) => res.iter().all(|ok| *ok),
+                    Err(_) => false,
+                };
+                if ok && act.cfg.master.watchdog && act.state == State::Running {
+                    if let Err(err) = utils::sd_notify("WATCHDOG=1") {
+                        warn!("Can not notify systemd watchdog: {}", err);
+                    }
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    /// services in order of operations on all services, see `service_order`
+    fn ordered(&self) -> Vec<(String, Addr<FeService>)> {
+        let mut names: Vec<_> = self
+            .order
+            .iter()
+            .filter(|name| self.services.contains_key(*name))
+            .collect();
+        match self.cfg.master.service_order {
+            ServiceOrder::insertion => (),
+            ServiceOrder::name => names.sort(),
+            ServiceOrder::rotating => if !names.is_empty() {
+                let shift = self.rotation.get() % names.len();
+                names.rotate_left(shift);
+                self.rotation.set(self.rotation.get().wrapping_add(1));
+            },
+        }
+        names
+            .into_iter()
+            .map(|name| (name.clone(), self.services[name].clone()))
+            .collect()
+    }
+
+    /// service by name, services that are being removed are not available
+    fn service(&self, name: &str) -> Option<&Addr<FeService>> {
+        if self.removing.contains(name) {
+            None
+        } else {
+            self.services.get(name)
+        }
+    }
+
+    /// send message to all services in service order
+    fn send_all<M>(&self, msg: M)
+    where
+        M: Message + Clone + Send,
+        M::Result: Send,
+        FeService: Handler<M>,
+        <FeService as Actor>::Context: ToEnvelope<FeService, M>,
+    {
+        for (_, srv) in self.ordered() {
+            srv.do_send(msg.clone());
+        }
+    }
+
+    /// seconds until reaping of worker processes is resumed
+    pub fn reaping_paused(&self) -> Option<u64> {
+        self.reap_paused.map(|until| {
+            let now = Instant::now();
+            if until > now {
+                (until - now).as_secs()
+            } else {
+                0
+            }
+        })
+    }
+
+    /// pause reaping of exited worker processes for debugging,
+    /// reaping resumes after `secs`, at most `MAX_REAP_PAUSE`
+    fn pause_reaping(&mut self, ctx: &mut Context<Self>, secs: u32) -> u64 {
+        let hold = std::cmp::min(u64::from(secs), MAX_REAP_PAUSE);
+        if hold == 0 {
+            self.resume_reaping(ctx);
+            return 0;
+        }
+        warn!("Reaping of worker processes is paused for {} seconds", hold);
+        self.reap_paused = Some(Instant::now() + Duration::new(hold, 0));
+        ctx.run_later(Duration::new(hold, 0), |act, ctx| {
+            // pause could be extended in the meantime
+            if act.reap_paused.is_some_and(|until| until <= Instant::now()) {
+                act.resume_reaping(ctx);
+            }
+        });
+        hold
+    }
+
+    fn resume_reaping(&mut self, ctx: &mut Context<Self>) {
+        if self.reap_paused.take().is_some() {
+            info!("Reaping of worker processes is resumed");
+            self.reap_workers(ctx);
+        }
+    }
+
+    /// collect status of exited, stopped and continued worker processes
+    fn reap_workers(&mut self, ctx: &mut Context<Self>) {
+        if self.reap_paused.is_some() {
+            debug!("Reaping of workers is paused");
+            return;
+        }
+        debug!("Reap workers");
+        if self.cfg.master.reap_all {
+            while self.reap(None) {}
+        } else {
+            // commands of probes and hooks are reaped too
+            for pid in process::forked_pids()
+                .into_iter()
+                .chain(probe::command_pids())
+            {
+                while self.reap(Some(pid)) {}
+            }
+        }
+
+        // last child is gone, no need to wait for services
+        if self.state == State::Stopping && !utils::has_children() {
+            info!("All child processes exited");
+            self.exit(ctx);
+        }
+    }
+
+    /// collect status of one child process, returns `false` if there is
+    /// no status available. status of unknown processes is not reported
+    fn reap(&mut self, pid: Option<Pid>) -> bool {
+        match waitpid(pid, Some(WNOHANG | WUNTRACED | WCONTINUED)) {
+            Ok(WaitStatus::Exited(pid, code)) => {
+                if process::forget_pid(pid) {
+                    info!("Worker {} exit code: {}", pid, code);
+                    self.send_all(service::ProcessExited(pid, ProcessError::from(code)));
+                } else if probe::exited(pid, Ok(i32::from(code))) {
+                    debug!("Command {} exit code: {}", pid, code);
+                } else {
+                    debug!("Reaped process {} exit code: {}", pid, code);
+                }
+                true
+            }
+            Ok(WaitStatus::Signaled(pid, sig, _)) => {
+                if process::forget_pid(pid) {
+                    info!("Worker {} exit by signal {:?}", pid, sig);
+                    let err = ProcessError::Signal(sig as usize);
+                    self.send_all(service::ProcessExited(pid, err));
+                } else if probe::exited(pid, Err(sig)) {
+                    debug!("Command {} exit by signal {:?}", pid, sig);
+                } else {
+                    debug!("Reaped process {} exit by signal {:?}", pid, sig);
+                }
+                true
+            }
+            Ok(WaitStatus::Stopped(pid, sig)) => {
+                info!("Worker {} stopped by signal {:?}", pid, sig);
+                self.send_all(service::ProcessSuspended(pid, true));
+                true
+            }
+            Ok(WaitStatus::Continued(pid)) => {
+                info!("Worker {} continued", pid);
+                self.send_all(service::ProcessSuspended(pid, false));
+                true
+            }
+            Err(nix::Error::Sys(Errno::ECHILD)) => {
+                debug!("No child processes to reap");
+                false
+            }
+            Ok(_) | Err(_) => false,
+        }
+    }
+
+    // stop with exit code 1 if every service is failed or quarantined
+    // and has no worker processes
+    fn check_all_failed(&mut self, ctx: &mut Context<Self>) {
+        if self.state != State::Running || self.services.is_empty() {
+            return;
+        }
+        let checks: Vec<_> = self
+            .services
+            .values()
+            .map(|srv| srv.send(service::IsFailed))
+            .collect();
+        future::join_all(checks)
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                let failed = match res {
+                    Ok(res) => res.iter().all(|st| *st == Ok(true)),
+                    Err(_) => false,
+                };
+                if failed && act.state == State::Running {
+                    error!("All services failed, exiting");
+                    set_failed();
+                    act.stop(ctx, false);
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
     fn stop(&mut self, ctx: &mut Context<Self>, graceful: bool) {
         if self.state != State::Stopping {
             info!("Stopping service");
 
             self.state = State::Stopping;
-            for service in self.services.values() {
-                self.stopping += 1;
-                service
-                    .send(service::Stop(graceful, Reason::Exit))
+            self.cfg.master.remove_ready_file();
+            self.ready_file_pending = false;
+            self.resume_reaping(ctx);
+
+            // pause all services first, so workers stop accepting new requests
+            // at the same time, then stop services
+            let drain_timeout = self.cfg.master.drain_timeout;
+            if graceful && drain_timeout > 0 {
+                info!("Draining services");
+                let timeout = Duration::new(u64::from(drain_timeout), 0);
+                let pauses: Vec<_> = self
+                    .ordered()
+                    .into_iter()
+                    .map(|(_, srv)| {
+                        srv.send(service::Pause)
+                            .timeout(timeout)
+                            .then(|_| Ok(()))
+                    }).collect();
+                future::join_all(pauses)
                     .into_actor(self)
-                    .then(|res, srv, _| {
-                        srv.stopping -= 1;
-                        let exit = srv.stopping == 0;
-                        if exit {
-                            srv.exit();
-                        }
-                        match res {
-                            Ok(_) => actix::fut::ok(()),
-                            Err(_) => actix::fut::err(()),
-                        }
+                    .then(|_: Result<Vec<()>, ()>, act, ctx| {
+                        act.stop_services(ctx, true);
+                        actix::fut::ok(())
                     }).spawn(ctx);
+            } else {
+                self.stop_services(ctx, graceful);
+            }
+        }
+    }
+
+    // `term_deadline` is reached, kill remaining worker processes before
+    // orchestrator kills supervisor and orphans them
+    fn kill_workers(&mut self, ctx: &mut Context<Self>) {
+        let pids = process::forked_pids();
+        if pids.is_empty() {
+            return;
+        }
+        warn!("Shutdown deadline reached, killing {} worker processes", pids.len());
+        for pid in pids {
+            if libc::pid_t::from(pid) <= 0 {
+                continue;
+            }
+            if process::is_group_leader(pid) {
+                let _ = kill(Pid::from_raw(-libc::pid_t::from(pid)), Signal::SIGKILL);
+            }
+            if let Err(err) = kill(pid, Signal::SIGKILL) {
+                warn!("Can not kill worker (pid:{}): {}", pid, err);
             }
         }
+        self.stop_services(ctx, false);
+    }
+
+    fn stop_services(&mut self, ctx: &mut Context<Self>, graceful: bool) {
+        // services that are being removed are not in service order
+        let mut services: Vec<_> =
+            self.ordered().into_iter().map(|(_, srv)| srv).collect();
+        services.extend(
+            self.removing
+                .iter()
+                .filter_map(|name| self.services.get(name).cloned()),
+        );
+        // no stopped service would trigger exit
+        if services.is_empty() && self.stopping == 0 {
+            return self.exit(ctx);
+        }
+        for service in services {
+            self.stopping += 1;
+            service
+                .send(service::Stop(graceful, Reason::Exit))
+                .into_actor(self)
+                .then(|res, srv, ctx| {
+                    srv.stopping -= 1;
+                    let exit = srv.stopping == 0;
+                    if exit {
+                        srv.exit(ctx);
+                    }
+                    match res {
+                        Ok(_) => actix::fut::ok(()),
+                        Err(_) => actix::fut::err(()),
+                    }
+                }).spawn(ctx);
+        }
+    }
+
+    // run `ready_gate_cmd` if configured, then report readiness
+    fn ready_gate(&mut self, ctx: &mut Context<Self>) {
+        let cmd = match self.cfg.master.ready_gate_cmd {
+            Some(ref cmd) => cmd.clone(),
+            None => return self.ready(),
+        };
+        info!("Running ready gate command: {}", cmd);
+
+        // same runner as exec probes, hung command is killed on timeout
+        let timeout = self.cfg.master.ready_gate_timeout;
+        probe::command(&cmd, timeout)
+            .into_actor(self)
+            .then(move |res, act, _| {
+                match res {
+                    Ok(_) => if act.state == State::Running {
+                        act.ready();
+                    },
+                    Err(ref err) if err == "timeout" => error!(
+                        "Ready gate command {:?} did not finish in {} secs, \
+                         readiness is not reported",
+                        cmd, timeout
+                    ),
+                    Err(err) => error!(
+                        "Ready gate command {:?} failed: {}, readiness is not reported",
+                        cmd, err
+                    ),
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    // write ready file, write is repeated later if disk is full
+    fn save_ready_file(&mut self) {
+        let res = disk::write("ready file", || self.cfg.master.save_ready_file());
+        match res {
+            Ok(written) => self.ready_file_pending = !written,
+            Err(err) => error!(
+                "Can not write ready file {:?} err: {}",
+                self.cfg.master.ready_file, err
+            ),
+        }
+    }
+
+    // remove ready file and notify systemd, supervisor is ready again
+    // after reload
+    fn unready(&mut self) {
+        self.cfg.master.remove_ready_file();
+        self.ready_file_pending = false;
+        if let Err(err) = utils::sd_notify("RELOADING=1") {
+            error!("Can not notify systemd: {}", err);
+        }
+    }
+
+    // write ready file and notify systemd
+    fn ready(&mut self) {
+        info!("All services are started");
+        self.save_ready_file();
+        if let Err(err) = utils::sd_notify("READY=1") {
+            error!("Can not notify systemd: {}", err);
+        }
+    }
+}
+
+pub struct ServicePids(pub String);
+
+impl Message for ServicePids {
+    type Result = Result<Vec<String>, CommandError>;
+}
+
+impl Handler<ServicePids> for CommandCenter {
+    type Result = Response<Vec<String>, CommandError>;
+
+    fn handle(
+        &mut self, msg: ServicePids, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => match self.service(&msg.0) {
+                Some(service) => Response::async(
+                    service
+                        .send(service::Pids)
+                        .map_err(|_| CommandError::UnknownService),
+                ),
+                None => Response::reply(Err(CommandError::UnknownService)),
+            },
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+pub struct SignalService(pub String, pub Signal);
+
+impl Message for SignalService {
+    type Result = Result<Vec<String>, CommandError>;
+}
+
+impl Handler<SignalService> for CommandCenter {
+    type Result = Response<Vec<String>, CommandError>;
+
+    fn handle(
+        &mut self, msg: SignalService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => match self.service(&msg.0) {
+                Some(service) => Response::async(
+                    service
+                        .send(service::SignalWorkers(msg.1))
+                        .map_err(|_| CommandError::UnknownService),
+                ),
+                None => Response::reply(Err(CommandError::UnknownService)),
+            },
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+pub struct ServiceFailures(pub String);
+
+impl Message for ServiceFailures {
+    type Result = Result<FailureCounters, CommandError>;
+}
+
+impl Handler<ServiceFailures> for CommandCenter {
+    type Result = Response<FailureCounters, CommandError>;
+
+    fn handle(
+        &mut self, msg: ServiceFailures, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => Response::async(self.failure_counters(&msg.0)),
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+pub struct DiagnoseService(pub String);
+
+impl Message for DiagnoseService {
+    type Result = Result<Diagnosis, CommandError>;
+}
+
+impl Handler<DiagnoseService> for CommandCenter {
+    type Result = Response<Diagnosis, CommandError>;
+
+    fn handle(
+        &mut self, msg: DiagnoseService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => Response::async(self.diagnose(&msg.0)),
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+pub struct ResetServiceFailures(pub String);
+
+impl Message for ResetServiceFailures {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<ResetServiceFailures> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: ResetServiceFailures, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => Response::async(self.reset_failure_counters(&msg.0)),
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+pub struct ServiceCounters(pub String);
+
+impl Message for ServiceCounters {
+    type Result = Result<Vec<WorkerCounters>, CommandError>;
+}
+
+impl Handler<ServiceCounters> for CommandCenter {
+    type Result = Response<Vec<WorkerCounters>, CommandError>;
+
+    fn handle(
+        &mut self, msg: ServiceCounters, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => match self.service(&msg.0) {
+                Some(service) => Response::async(service.send(service::Counters).then(
+                    |res| match res {
+                        Ok(Ok(counters)) => Ok(counters),
+                        _ => Err(CommandError::UnknownService),
+                    },
+                )),
+                None => Response::reply(Err(CommandError::UnknownService)),
+            },
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+/// Check that command center processes messages
+pub struct Ping;
+
+impl Message for Ping {
+    type Result = ();
+}
+
+impl Handler<Ping> for CommandCenter {
+    type Result = ();
+
+    fn handle(&mut self, _: Ping, _: &mut Context<CommandCenter>) {}
+}
+
+/// Check that supervisor is running and all services are ready
+pub struct Ready;
+
+impl Message for Ready {
+    type Result = Result<bool, ()>;
+}
+
+impl Handler<Ready> for CommandCenter {
+    type Result = Response<bool, ()>;
+
+    fn handle(&mut self, _: Ready, _: &mut Context<CommandCenter>) -> Self::Result {
+        if self.state != State::Running {
+            return Response::reply(Ok(false));
+        }
+        let checks: Vec<_> =
+            self.services.values().map(|srv| srv.send(service::Ready)).collect();
+        Response::async(future::join_all(checks).then(|res| match res {
+            Ok(res) => Ok(res.iter().all(|ready| *ready == Ok(true))),
+            Err(_) => Ok(false),
+        }))
+    }
+}
+
+/// Supervisor state snapshot
+pub struct Dump;
+
+impl Message for Dump {
+    type Result = Result<Snapshot, CommandError>;
+}
+
+impl Handler<Dump> for CommandCenter {
+    type Result = Response<Snapshot, CommandError>;
+
+    fn handle(&mut self, _: Dump, _: &mut Context<CommandCenter>) -> Self::Result {
+        match self.state {
+            State::Running => Response::async(self.snapshot()),
+            _ => Response::reply(Err(CommandError::NotReady)),
+        }
+    }
+}
+
+/// Increase supervisor epoch, resolves with new epoch
+pub struct BumpEpoch;
+
+impl Message for BumpEpoch {
+    type Result = Result<u64, CommandError>;
+}
+
+impl Handler<BumpEpoch> for CommandCenter {
+    type Result = Result<u64, CommandError>;
+
+    fn handle(&mut self, _: BumpEpoch, _: &mut Context<CommandCenter>) -> Self::Result {
+        match self.state {
+            State::Running => Ok(self.bump_epoch()),
+            _ => Err(CommandError::NotReady),
+        }
     }
 }
 
-pub struct ServicePids(pub String);
+/// Pause reaping of exited workers for number of seconds, `0` resumes reaping
+pub struct PauseReaping(pub u32);
 
-impl Message for ServicePids {
-    type Result = Result<Vec<String>, CommandError>;
+impl Message for PauseReaping {
+    type Result = Result<u64, CommandError>;
 }
 
-impl Handler<ServicePids> for CommandCenter {
-    type Result = Response<Vec<String>, CommandError>;
+impl Handler<PauseReaping> for CommandCenter {
+    type Result = Result<u64, CommandError>;
 
     fn handle(
-        &mut self, msg: ServicePids, _: &mut Context<CommandCenter>,
+        &mut self, msg: PauseReaping, ctx: &mut Context<CommandCenter>,
     ) -> Self::Result {
         match self.state {
-            State::Running => match self.services.get(&msg.0) {
-                Some(service) => Response::async(
-                    service
-                        .send(service::Pids)
-                        .map_err(|_| CommandError::UnknownService),
-                ),
-                None => Response::reply(Err(CommandError::UnknownService)),
-            },
-            _ => Response::reply(Err(CommandError::NotReady)),
+            State::Running => Ok(self.pause_reaping(ctx, msg.0)),
+            _ => Err(CommandError::NotReady),
         }
     }
 }
@@ -121,17 +1075,17 @@ impl Handler<Stop> for CommandCenter {
     type Result = Response<bool, ()>;
 
     fn handle(&mut self, _: Stop, ctx: &mut Context<Self>) -> Self::Result {
-        self.stop(ctx, true);
-
+        // waiter is set before stop, supervisor without services exits at once
         if self.stop_waiter.is_none() {
             self.stop_waiter = Some(actix::Condition::default());
         }
+        let wait = match self.stop_waiter {
+            Some(ref mut waiter) => waiter.wait(),
+            None => unreachable!(),
+        };
 
-        if let Some(ref mut waiter) = self.stop_waiter {
-            Response::async(waiter.wait().map_err(|_| ()))
-        } else {
-            unreachable!();
-        }
+        self.stop(ctx, true);
+        Response::async(wait.map_err(|_| ()))
     }
 }
 
@@ -151,7 +1105,7 @@ impl Handler<StartService> for CommandCenter {
         match self.state {
             State::Running => {
                 info!("Starting service {:?}", msg.0);
-                match self.services.get(&msg.0) {
+                match self.service(&msg.0) {
                     Some(service) => Response::async(service.send(service::Start).then(
                         |res| match res {
                             Ok(Ok(status)) => Ok(status),
@@ -170,6 +1124,60 @@ impl Handler<StartService> for CommandCenter {
     }
 }
 
+/// Reset Service by `name`, start service if flag is set
+pub struct ResetService(pub String, pub bool);
+
+impl Message for ResetService {
+    type Result = Result<Option<StartStatus>, CommandError>;
+}
+
+impl Handler<ResetService> for CommandCenter {
+    type Result = Response<Option<StartStatus>, CommandError>;
+
+    fn handle(
+        &mut self, msg: ResetService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => {
+                info!("Reset service {:?}", msg.0);
+                match self.service(&msg.0) {
+                    Some(service) => {
+                        let start = msg.1;
+                        let srv = service.clone();
+                        Response::async(
+                            service
+                                .send(service::Reset)
+                                .then(|res| match res {
+                                    Ok(Ok(_)) => Ok(()),
+                                    Ok(Err(err)) => Err(CommandError::Service(err)),
+                                    Err(_) => Err(CommandError::NotReady),
+                                }).and_then(move |_| {
+                                    if !start {
+                                        return future::Either::A(future::ok(None));
+                                    }
+                                    future::Either::B(srv.send(service::Start).then(
+                                        |res| match res {
+                                            Ok(Ok(status)) => Ok(Some(status)),
+                                            Ok(Err(err)) => {
+                                                Err(CommandError::Service(err))
+                                            }
+                                            Err(_) => Err(CommandError::NotReady),
+                                        },
+                                    ))
+                                }),
+                        )
+                    }
+                    None => Response::reply(Err(CommandError::UnknownService)),
+                }
+            }
+            _ => {
+                warn!("Can not reset service in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
 /// Stop Service by `name`
 pub struct StopService(pub String, pub bool);
 
@@ -186,7 +1194,7 @@ impl Handler<StopService> for CommandCenter {
         match self.state {
             State::Running => {
                 info!("Stopping service {:?}", msg.0);
-                match self.services.get(&msg.0) {
+                match self.service(&msg.0) {
                     Some(service) => Response::async(
                         service
                             .send(service::Stop(msg.1, Reason::ConsoleRequest))
@@ -206,6 +1214,207 @@ impl Handler<StopService> for CommandCenter {
     }
 }
 
+/// Start new service with configuration
+pub struct AddService(pub ServiceConfig);
+
+impl Message for AddService {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<AddService> for CommandCenter {
+    type Result = Result<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: AddService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        let name = msg.0.name.clone();
+        self.add_service(msg.0)?;
+        self.runtime.insert(name);
+        Ok(())
+    }
+}
+
+/// Stop and remove Service by `name`, gracefully if flag is set
+pub struct RemoveService(pub String, pub bool);
+
+impl Message for RemoveService {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<RemoveService> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: RemoveService, ctx: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.remove_service(ctx, &msg.0, msg.1) {
+            Ok(rx) => Response::async(rx.map_err(|_| CommandError::ServiceStopped)),
+            Err(err) => Response::reply(Err(err)),
+        }
+    }
+}
+
+/// Gracefully stop and disable Service by `name`
+pub struct DisableService(pub String);
+
+impl Message for DisableService {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<DisableService> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: DisableService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => {
+                info!("Disabling service {:?}", msg.0);
+                match self.service(&msg.0) {
+                    Some(service) => Response::async(
+                        service.send(service::Disable).then(|res| match res {
+                            Ok(Ok(_)) => Ok(()),
+                            _ => Err(CommandError::ServiceStopped),
+                        }),
+                    ),
+                    None => Response::reply(Err(CommandError::UnknownService)),
+                }
+            }
+            _ => {
+                warn!("Can not disable service in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
+/// Enable disabled Service by `name`
+pub struct EnableService(pub String);
+
+impl Message for EnableService {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<EnableService> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: EnableService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => {
+                info!("Enabling service {:?}", msg.0);
+                match self.service(&msg.0) {
+                    Some(service) => Response::async(
+                        service.send(service::Enable).then(|res| match res {
+                            Ok(Ok(_)) => Ok(()),
+                            Ok(Err(err)) => Err(CommandError::Service(err)),
+                            Err(_) => Err(CommandError::NotReady),
+                        }),
+                    ),
+                    None => Response::reply(Err(CommandError::UnknownService)),
+                }
+            }
+            _ => {
+                warn!("Can not enable service in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
+/// Set desired number of workers of Service `name`
+pub struct ScaleService(pub String, pub u16);
+
+impl Message for ScaleService {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<ScaleService> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: ScaleService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => match self.service(&msg.0) {
+                Some(service) => Response::async(
+                    service.send(service::Scale(msg.1)).then(|res| match res {
+                        Ok(Ok(_)) => Ok(()),
+                        Ok(Err(err)) => Err(CommandError::Service(err)),
+                        Err(_) => Err(CommandError::UnknownService),
+                    }),
+                ),
+                None => Response::reply(Err(CommandError::UnknownService)),
+            },
+            _ => {
+                warn!("Can not scale service in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
+/// Temporarily override number of workers of Service `name`
+pub struct OverrideServiceNum(pub String, pub u16, pub bool);
+
+impl Message for OverrideServiceNum {
+    type Result = Result<(), CommandError>;
+}
+
+impl Handler<OverrideServiceNum> for CommandCenter {
+    type Result = Response<(), CommandError>;
+
+    fn handle(
+        &mut self, msg: OverrideServiceNum, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => Response::async(self.override_num(&msg.0, msg.1, msg.2)),
+            _ => {
+                warn!("Can not override service workers in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
+/// Send custom command with name and data to workers of Service `name`
+pub struct NotifyService(pub String, pub String, pub json::Value);
+
+impl Message for NotifyService {
+    type Result = Result<Vec<WorkerAck>, CommandError>;
+}
+
+impl Handler<NotifyService> for CommandCenter {
+    type Result = Response<Vec<WorkerAck>, CommandError>;
+
+    fn handle(
+        &mut self, msg: NotifyService, _: &mut Context<CommandCenter>,
+    ) -> Self::Result {
+        match self.state {
+            State::Running => {
+                info!("Notify service {:?}", msg.0);
+                match self.service(&msg.0) {
+                    Some(service) => Response::async(
+                        service.send(service::Notify(msg.1, msg.2)).then(|res| {
+                            match res {
+                                Ok(Ok(acks)) => Ok(acks),
+                                Ok(Err(err)) => Err(CommandError::Service(err)),
+                                Err(_) => Err(CommandError::UnknownService),
+                            }
+                        }),
+                    ),
+                    None => Response::reply(Err(CommandError::UnknownService)),
+                }
+            }
+            _ => {
+                warn!("Can not notify service in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
+            }
+        }
+    }
+}
+
 /// Service status message
 pub struct StatusService(pub String);
 
@@ -220,13 +1429,24 @@ impl Handler<StatusService> for CommandCenter {
         &mut self, msg: StatusService, _: &mut Context<CommandCenter>,
     ) -> Self::Result {
         match self.state {
-            State::Running => match self.services.get(&msg.0) {
-                Some(service) => Response::async(service.send(service::Status).then(
-                    |res| match res {
-                        Ok(Ok(status)) => Ok(status),
-                        _ => Err(CommandError::UnknownService),
-                    },
-                )),
+            State::Running => match self.service(&msg.0) {
+                Some(service) => {
+                    let paused = self.reaping_paused();
+                    Response::async(service.send(service::Status).then(move |res| {
+                        match res {
+                            Ok(Ok(mut status)) => {
+                                if let Some(secs) = paused {
+                                    status.0 = format!(
+                                        "{} (reaping paused for {}s)",
+                                        status.0, secs
+                                    );
+                                }
+                                Ok(status)
+                            }
+                            _ => Err(CommandError::UnknownService),
+                        }
+                    }))
+                }
                 None => Response::reply(Err(CommandError::UnknownService)),
             },
             _ => Response::reply(Err(CommandError::NotReady)),
@@ -250,7 +1470,7 @@ impl Handler<PauseService> for CommandCenter {
         match self.state {
             State::Running => {
                 info!("Pause service {:?}", msg.0);
-                match self.services.get(&msg.0) {
+                match self.service(&msg.0) {
                     Some(service) => Response::async(service.send(service::Pause).then(
                         |res| match res {
                             Ok(Ok(_)) => Ok(()),
@@ -285,7 +1505,7 @@ impl Handler<ResumeService> for CommandCenter {
         match self.state {
             State::Running => {
                 info!("Resume service {:?}", msg.0);
-                match self.services.get(&msg.0) {
+                match self.service(&msg.0) {
                     Some(service) => {
                         Response::async(service.send(service::Resume).then(|res| {
                             match res {
@@ -307,7 +1527,11 @@ impl Handler<ResumeService> for CommandCenter {
 }
 
 /// Reload service
-pub struct ReloadService(pub String, pub bool);
+///
+/// `ReloadService(name, graceful, reread_config)`. If `reread_config` is true,
+/// service configuration is re-read from configuration file before
+/// workers get restarted, otherwise in-memory configuration is used.
+pub struct ReloadService(pub String, pub bool, pub bool);
 
 impl Message for ReloadService {
     type Result = Result<ReloadStatus, CommandError>;
@@ -321,9 +1545,25 @@ impl Handler<ReloadService> for CommandCenter {
             State::Running => {
                 info!("Reloading service {:?}", msg.0);
                 let graceful = msg.1;
-                match self.services.get(&msg.0) {
+                match self.service(&msg.0) {
                     Some(service) => {
-                        Response::async(service.send(service::Reload(graceful)).then(
+                        let cfg = if msg.2 {
+                            match self.reread_config() {
+                                Ok(services) => {
+                                    let cfg =
+                                        services.into_iter().find(|cfg| cfg.name == msg.0);
+                                    if cfg.is_none() {
+                                        warn!("Service {:?} is not in config file", msg.0);
+                                    }
+                                    cfg
+                                }
+                                Err(err) => return Response::reply(Err(err)),
+                            }
+                        } else {
+                            None
+                        };
+                        let reload = service::Reload(graceful, cfg);
+                        Response::async(service.send(reload).then(
                             |res| match res {
                                 Ok(Ok(status)) => Ok(status),
                                 Ok(Err(err)) => Err(CommandError::Service(err)),
@@ -342,25 +1582,130 @@ impl Handler<ReloadService> for CommandCenter {
     }
 }
 
-/// reload all services
-pub struct ReloadAll;
+/// reload all services, re-read configuration file if flag is set.
+/// resolves after all services finish reload
+pub struct ReloadAll(pub bool);
 
 impl Message for ReloadAll {
-    type Result = ();
+    type Result = Result<(), CommandError>;
 }
 
 impl Handler<ReloadAll> for CommandCenter {
-    type Result = ();
+    type Result = Response<(), CommandError>;
 
-    fn handle(&mut self, _: ReloadAll, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: ReloadAll, ctx: &mut Context<Self>) -> Self::Result {
         match self.state {
             State::Running => {
                 info!("reloading all services");
-                for srv in self.services.values() {
-                    srv.do_send(service::Reload(true));
+                let mut services = if msg.0 {
+                    match self.reread_config() {
+                        Ok(services) => services,
+                        Err(err) => {
+                            // configuration file is missing or broken,
+                            // running services are not touched
+                            error!(
+                                "Reload of all services is rejected, services keep \
+                                 running with current configuration"
+                            );
+                            return Response::reply(Err(err));
+                        }
+                    }
+                } else {
+                    Vec::new()
+                };
+                self.unready();
+
+                let mut reloads = Vec::new();
+                let mut removed = Vec::new();
+                for (name, srv) in self.ordered() {
+                    let cfg = services
+                        .iter()
+                        .position(|cfg| cfg.name == name)
+                        .map(|idx| services.swap_remove(idx));
+
+                    if msg.0 {
+                        if cfg.is_some() {
+                            // service is part of configuration now
+                            self.runtime.remove(&name);
+                        } else if !self.runtime.contains(&name) {
+                            removed.push(name);
+                            continue;
+                        }
+                    }
+
+                    // re-read configuration decides, if available
+                    let current = self.cfg.services.iter().find(|cfg| cfg.name == name);
+                    let reload = cfg
+                        .as_ref()
+                        .or(current)
+                        .is_none_or(|cfg| cfg.reload_on_sighup);
+                    if !reload {
+                        info!(
+                            "Skipping reload of service {:?}, reload_on_sighup is off",
+                            name
+                        );
+                        continue;
+                    }
+                    reloads.push((srv, cfg));
+                }
+
+                // removed services stop gracefully, new services start
+                let mut removals = Vec::new();
+                for name in removed {
+                    info!("Service {:?} is removed from configuration", name);
+                    match self.remove_service(ctx, &name, true) {
+                        Ok(rx) => removals.push(rx),
+                        Err(err) => {
+                            error!("Can not remove service {:?}: {:?}", name, err)
+                        }
+                    }
+                }
+                for cfg in services {
+                    info!("Service {:?} is added to configuration", cfg.name);
+                    let name = cfg.name.clone();
+                    if let Err(err) = self.add_service(cfg) {
+                        error!("Can not add service {:?}: {:?}", name, err);
+                    }
                 }
+
+                // reload is sent when stream polls it, so at most
+                // `max_concurrent_reloads` services reload at the same time
+                let max = match self.cfg.master.max_concurrent_reloads {
+                    0 => std::cmp::max(reloads.len(), 1),
+                    max => max as usize,
+                };
+                let reloads = stream::iter_ok(reloads)
+                    .map(|(srv, cfg)| {
+                        srv.send(service::Reload(true, cfg))
+                            .then(|_| Ok::<_, CommandError>(()))
+                    }).buffer_unordered(max);
+                let removals = future::join_all(removals).then(|_| Ok(()));
+
+                // supervisor is ready again after all services reload
+                let (tx, rx) = oneshot::channel();
+                reloads
+                    .collect()
+                    .join(removals)
+                    .into_actor(self)
+                    .then(move |res, act, _| {
+                        if act.state == State::Running {
+                            act.save_ready_file();
+                            if let Err(err) = utils::sd_notify("READY=1") {
+                                error!("Can not notify systemd: {}", err);
+                            }
+                        }
+                        let _ = tx.send(res.map(|_| ()));
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+                Response::async(
+                    rx.map_err(|_| CommandError::NotReady)
+                        .and_then(|res| res),
+                )
+            }
+            _ => {
+                warn!("Can not reload in system in `{:?}` state", self.state);
+                Response::reply(Err(CommandError::NotReady))
             }
-            _ => warn!("Can not reload in system in `{:?}` state", self.state),
         }
     }
 }
@@ -372,15 +1717,34 @@ impl Handler<signal::Signal> for CommandCenter {
     fn handle(&mut self, msg: signal::Signal, ctx: &mut Context<Self>) {
         match msg.0 {
             signal::SignalType::Int => {
-                info!("SIGINT received, exiting");
-                self.stop(ctx, false);
+                if self.state == State::Stopping && self.cfg.master.graceful_sigint {
+                    // second Ctrl-C, do not wait for workers
+                    info!("SIGINT received, quitting");
+                    self.stop_services(ctx, false);
+                } else {
+                    info!("SIGINT received, exiting");
+                    let graceful = self.cfg.master.graceful_sigint;
+                    self.stop(ctx, graceful);
+                }
             }
             signal::SignalType::Hup => {
-                info!("SIGHUP received, reloading");
-                // self.handle(ReloadAll, ctx);
+                info!("SIGHUP received, reloading all services");
+                if self.state == State::Running {
+                    // new worker processes of reload get new epoch
+                    self.bump_epoch();
+                    ctx.notify(ReloadAll(true));
+                } else {
+                    warn!("Can not reload in `{:?}` state", self.state);
+                }
             }
             signal::SignalType::Term => {
                 info!("SIGTERM received, stopping");
+                let deadline = self.cfg.master.term_deadline;
+                if deadline > 0 && self.state != State::Stopping {
+                    ctx.run_later(Duration::new(u64::from(deadline), 0), |act, ctx| {
+                        act.kill_workers(ctx)
+                    });
+                }
                 self.stop(ctx, true);
             }
             signal::SignalType::Quit => {
@@ -389,29 +1753,16 @@ impl Handler<signal::Signal> for CommandCenter {
             }
             signal::SignalType::Child => {
                 info!("SIGCHLD received");
-                debug!("Reap workers");
-                loop {
-                    match waitpid(None, Some(WNOHANG)) {
-                        Ok(WaitStatus::Exited(pid, code)) => {
-                            info!("Worker {} exit code: {}", pid, code);
-                            let err = ProcessError::from(code);
-                            for srv in self.services.values_mut() {
-                                srv.do_send(service::ProcessExited(pid, err.clone()));
-                            }
-                            continue;
-                        }
-                        Ok(WaitStatus::Signaled(pid, sig, _)) => {
-                            info!("Worker {} exit by signal {:?}", pid, sig);
-                            let err = ProcessError::Signal(sig as usize);
-                            for srv in self.services.values_mut() {
-                                srv.do_send(service::ProcessExited(pid, err.clone()));
-                            }
-                            continue;
-                        }
-                        Ok(_) => (),
-                        Err(_) => (),
-                    }
-                    break;
+                let delay = self.cfg.master.reap_delay;
+                if delay == 0 {
+                    self.reap_workers(ctx);
+                } else if !self.reap_scheduled {
+                    // batch exits that arrive during delay
+                    self.reap_scheduled = true;
+                    ctx.run_later(Duration::from_millis(u64::from(delay)), |act, ctx| {
+                        act.reap_scheduled = false;
+                        act.reap_workers(ctx);
+                    });
                 }
             }
         }
@@ -431,16 +1782,173 @@ impl Actor for CommandCenter {
             .get::<signal::ProcessSignals>()
             .do_send(signal::Subscribe(addr.recipient()));
 
+        // metrics of worker starts and failures are sent from the start
+        statsd::init(&self.cfg.master);
+
         // start services
+        let host = utils::hostname().unwrap_or_default();
         for cfg in &self.cfg.services {
-            let service = FeService::start(cfg.num, cfg.clone());
+            if !cfg.runs_on(&host) {
+                info!("Skipping service {:?}, host {:?} does not match", cfg.name, host);
+                continue;
+            }
+            // one worker is enough to check handshake
+            let num = if self.cfg.master.validate { 1 } else { cfg.num };
+            let service = FeService::start(num, cfg.clone());
             self.services.insert(cfg.name.clone(), service);
+            self.order.push(cfg.name.clone());
+        }
+        if self.cfg.services.is_empty() {
+            match self.cfg.master.service_dir {
+                Some(ref dir) => warn!("No services in service directory {:?}", dir),
+                None => warn!("No services are configured"),
+            }
+        } else if self.services.is_empty() {
+            warn!("No services to run, host {:?} does not match any service", host);
         }
         self.state = State::Running;
+
+        if self.cfg.master.validate {
+            self.validate(ctx);
+            return;
+        }
+
+        // watch configuration file changes
+        if self.cfg.master.watch_config {
+            self.config_mtime = self.config_mtime();
+            ctx.run_interval(Duration::new(1, 0), |act, ctx| act.watch_config(ctx));
+        }
+
+        // stop if parent process exits, orphaned process gets re-parented
+        if self.cfg.master.exit_on_parent_death {
+            let parent = getppid();
+            ctx.run_interval(Duration::new(1, 0), move |act, ctx| {
+                if act.state == State::Running && getppid() != parent {
+                    info!("Parent process {} exited, stopping", parent);
+                    act.stop(ctx, true);
+                }
+            });
+        }
+
+        // exit when all services failed, orchestrator restarts supervisor
+        if self.cfg.master.exit_when_all_failed {
+            ctx.run_interval(Duration::new(1, 0), |act, ctx| act.check_all_failed(ctx));
+        }
+
+        // write ready file that was skipped because disk was full
+        if self.cfg.master.ready_file.is_some() {
+            let interval = Duration::new(disk::RETRY_INTERVAL, 0);
+            ctx.run_interval(interval, |act, _| {
+                if act.ready_file_pending && act.state == State::Running {
+                    act.save_ready_file();
+                }
+            });
+        }
+
+        // periodic flush of StatsD gauges
+        if statsd::enabled() {
+            let interval = Duration::new(u64::from(self.cfg.master.statsd_interval), 0);
+            ctx.run_interval(interval, |act, ctx| act.flush_gauges(ctx));
+        }
+
+        // periodic self check of internal actors
+        if self.cfg.master.self_check > 0 {
+            let interval = Duration::new(u64::from(self.cfg.master.self_check), 0);
+            ctx.run_interval(interval, |act, ctx| act.self_check(ctx));
+        }
+
+        // report readiness after all services run `min_healthy` workers
+        let starts: Vec<_> = self
+            .ordered()
+            .into_iter()
+            .map(|(_, srv)| srv.send(service::WaitQuorum))
+            .collect();
+        future::join_all(starts)
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                let ready = match res {
+                    Ok(statuses) => statuses.iter().all(|st| *st == Ok(true)),
+                    Err(_) => false,
+                };
+                if !ready {
+                    warn!("Not all services are started, readiness is not reported");
+                } else if act.state == State::Running {
+                    act.ready_gate(ctx);
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    fn stopping(&mut self, ctx: &mut Context<Self>) -> Running {
+        self.exit(ctx);
+        if self.exiting {
+            Running::Stop
+        } else {
+            // wait for orphaned processes
+            Running::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::timer::Delay;
+
+    // run supervisor until it exits, `false` if it does not exit in time
+    fn run_until_exit<F>(cfg: Config, f: F) -> bool
+    where
+        F: FnOnce(Addr<CommandCenter>) + 'static,
+    {
+        let sys = System::new("test");
+        let addr = CommandCenter::start(Rc::new(cfg));
+        f(addr);
+
+        let hung = Rc::new(Cell::new(false));
+        let timeout = hung.clone();
+        Arbiter::spawn(Delay::new(Instant::now() + Duration::new(5, 0)).then(
+            move |_| {
+                timeout.set(true);
+                System::current().stop();
+                Ok(())
+            },
+        ));
+        sys.run();
+        !hung.get()
     }
 
-    fn stopping(&mut self, _: &mut Context<Self>) -> Running {
-        self.exit();
-        Running::Stop
+    #[test]
+    fn test_stop_without_services() {
+        let mut cfg = config::run_config("test".to_owned(), "true".to_owned(), 1, 10)
+            .unwrap();
+        cfg.services[0].hosts = vec!["no-such-host".to_owned()];
+
+        assert!(run_until_exit(cfg, |addr| {
+            Arbiter::spawn(addr.send(Stop).then(|res| {
+                assert_eq!(res.unwrap(), Ok(true));
+                Ok(())
+            }))
+        }));
+    }
+
+    #[test]
+    fn test_stop_after_services_removed() {
+        // service without workers stops right away
+        let cfg = config::run_config("test".to_owned(), "true".to_owned(), 0, 10)
+            .unwrap();
+
+        assert!(run_until_exit(cfg, |addr| {
+            let stop = addr.clone();
+            Arbiter::spawn(
+                addr.send(RemoveService("test".to_owned(), true))
+                    .and_then(move |res| {
+                        assert!(res.is_ok());
+                        stop.send(Stop)
+                    }).then(|res| {
+                        assert_eq!(res.unwrap(), Ok(true));
+                        Ok(())
+                    }),
+            )
+        }));
     }
 }
@@ -1,19 +1,31 @@
 use std;
-use std::error::Error;
+use std::collections::BTreeMap;
 use std::ffi::OsString;
 use std::io::prelude::*;
-use std::path::Path;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
 
+use byteorder::{ByteOrder, NativeEndian};
 use nix;
-use nix::unistd::{Gid, Uid};
+use nix::sys::signal::Signal;
+use nix::unistd::{Gid, Pid, Uid};
+use serde_json as json;
 use structopt::StructOpt;
 use toml;
 
+use caps;
 use config_helpers;
+use process::{WORKER_BOOT_FAILED, WORKER_INIT_FAILED, WORKER_TIMEOUT};
 use socket;
+use utils;
+use webhook;
 
 pub struct Config {
+    /// Path to configuration file
+    pub path: PathBuf,
     pub master: MasterConfig,
+    /// listening sockets stay open for worker processes
+    #[allow(dead_code)]
     pub sockets: Vec<socket::Socket>,
     pub logging: LoggingConfig,
     pub services: Vec<ServiceConfig>,
@@ -26,7 +38,11 @@ pub struct Config {
 /// daemon = true
 /// pid = "fectl.pid"
 /// sock = "fectl.sock"
+/// sock_mode = "0660"
+/// sock_gid = "fectl"
 /// directory = "/path/to/dir"
+/// ready_file = "fectl.ready"
+/// ready_gate_cmd = "./warmup.sh"
 /// ```
 #[derive(Debug)]
 pub struct MasterConfig {
@@ -36,8 +52,16 @@ pub struct MasterConfig {
     pub pid: Option<OsString>,
     /// Path to controller unix domain socket
     pub sock: OsString,
+    /// Controller socket file mode
+    pub sock_mode: u32,
+    /// Controller socket owner
+    pub sock_uid: Option<Uid>,
+    /// Controller socket group
+    pub sock_gid: Option<Gid>,
     /// Change to specified directory before apps loading.
     pub directory: OsString,
+    /// Directory with one configuration file per service
+    pub service_dir: Option<OsString>,
 
     /// Set group id
     pub gid: Option<Gid>,
@@ -48,15 +72,99 @@ pub struct MasterConfig {
     pub stdout: Option<String>,
     /// Redirect stderr
     pub stderr: Option<String>,
+
+    /// Path to file that is written when all services are started
+    pub ready_file: Option<OsString>,
+    /// Shell command that has to exit with `0` after all services are started,
+    /// before master reports readiness
+    pub ready_gate_cmd: Option<String>,
+    /// Timeout in seconds for `ready_gate_cmd`
+    pub ready_gate_timeout: u32,
+    /// Path to audit log of operator commands
+    pub audit_log: Option<OsString>,
+    /// Address of `/healthz` and `/readyz` http endpoints
+    pub health_address: Option<std::net::SocketAddr>,
+    /// UDP address of StatsD agent for lifecycle metrics
+    pub statsd_address: Option<std::net::SocketAddr>,
+    /// Prefix of metric names
+    pub statsd_prefix: String,
+    /// Tag metrics with service name in DogStatsD syntax,
+    /// otherwise service name is part of metric name
+    pub statsd_tags: bool,
+    /// Interval in seconds between gauge flushes
+    pub statsd_interval: u32,
+
+    /// Interval in seconds for checking that internal actors are responsive,
+    /// `0` disables check
+    pub self_check: u32,
+    /// Notify systemd watchdog after each successful self check
+    pub watchdog: bool,
+    /// Reload all services when configuration file changes
+    pub watch_config: bool,
+    /// Delay in milliseconds before reaping exited workers after `SIGCHLD`
+    pub reap_delay: u32,
+    /// Pause all services before graceful stop and wait up to this many
+    /// seconds for workers to confirm, `0` disables drain phase
+    pub drain_timeout: u32,
+    /// Seconds after `SIGTERM` when remaining worker processes get killed,
+    /// `0` disables deadline
+    pub term_deadline: u32,
+    /// Order of services in start, reload, pause and stop of all services
+    pub service_order: ServiceOrder,
+    /// Maximum number of services that reload at the same time during reload
+    /// of all services, `0` means no limit
+    pub max_concurrent_reloads: u16,
+    /// Initial supervisor epoch, passed to worker processes
+    pub epoch: u64,
+    /// Reap all child processes, not only forked workers.
+    /// Enabled automatically in init mode.
+    pub reap_all: bool,
+    /// `SIGINT` stops services gracefully, second `SIGINT` forces quit.
+    /// Otherwise `SIGINT` quits immediately, like `SIGQUIT`.
+    pub graceful_sigint: bool,
+    /// Gracefully stop when parent process of `fectld` exits
+    pub exit_on_parent_death: bool,
+    /// Exit with code 1 when all services are failed or quarantined
+    pub exit_when_all_failed: bool,
+    /// Run as container init process, enabled automatically
+    /// if `fectld` runs as PID 1
+    pub init: bool,
+    /// Run single service from command line, without controller socket
+    pub run: bool,
+    /// Start one worker of each service, check worker handshake and exit
+    pub validate: bool,
 }
 
 impl MasterConfig {
-    /// remove pid and sock files
+    /// remove pid, sock and ready files
     pub fn remove_files(&self) {
         if let Some(ref pid) = self.pid {
             let _ = std::fs::remove_file(pid);
         }
         let _ = std::fs::remove_file(&self.sock);
+        self.remove_ready_file();
+    }
+
+    /// write master pid to ready file, file is replaced atomically
+    pub fn save_ready_file(&self) -> Result<(), std::io::Error> {
+        if let Some(ref path) = self.ready_file {
+            let mut tmp = path.clone();
+            tmp.push(".tmp");
+            {
+                let mut file = std::fs::File::create(&tmp)?;
+                file.write_all(nix::unistd::getpid().to_string().as_ref())?;
+                file.sync_all()?;
+            }
+            std::fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+
+    /// remove ready file
+    pub fn remove_ready_file(&self) {
+        if let Some(ref path) = self.ready_file {
+            let _ = std::fs::remove_file(path);
+        }
     }
 
     /// load pid of the master process
@@ -99,8 +207,18 @@ struct TomlConfig {
 struct TomlMasterConfig {
     #[serde(default = "config_helpers::default_sock")]
     pub sock: String,
+    #[serde(default = "config_helpers::default_sock_mode")]
+    #[serde(deserialize_with = "config_helpers::deserialize_mode_field")]
+    pub sock_mode: u32,
+    #[serde(default)]
+    #[serde(deserialize_with = "config_helpers::deserialize_uid_field")]
+    pub sock_uid: Option<Uid>,
+    #[serde(default)]
+    #[serde(deserialize_with = "config_helpers::deserialize_gid_field")]
+    pub sock_gid: Option<Gid>,
     pub pid: Option<String>,
     pub directory: Option<String>,
+    pub service_dir: Option<String>,
 
     #[serde(default)]
     #[serde(deserialize_with = "config_helpers::deserialize_gid_field")]
@@ -112,6 +230,46 @@ struct TomlMasterConfig {
 
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    pub ready_file: Option<String>,
+    pub ready_gate_cmd: Option<String>,
+    #[serde(default = "config_helpers::default_ready_gate_timeout")]
+    pub ready_gate_timeout: u32,
+    pub audit_log: Option<String>,
+    pub health_address: Option<String>,
+    pub statsd_address: Option<String>,
+    #[serde(default = "config_helpers::default_statsd_prefix")]
+    pub statsd_prefix: String,
+    #[serde(default)]
+    pub statsd_tags: bool,
+    #[serde(default = "config_helpers::default_statsd_interval")]
+    pub statsd_interval: u32,
+
+    #[serde(default = "config_helpers::default_self_check")]
+    pub self_check: u32,
+    #[serde(default)]
+    pub watchdog: bool,
+    #[serde(default)]
+    pub watch_config: bool,
+    #[serde(default)]
+    pub reap_delay: u32,
+    #[serde(default)]
+    pub drain_timeout: u32,
+    #[serde(default)]
+    pub term_deadline: u32,
+    #[serde(default = "config_helpers::default_service_order")]
+    pub service_order: ServiceOrder,
+    #[serde(default)]
+    pub max_concurrent_reloads: u16,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub reap_all: bool,
+    #[serde(default = "config_helpers::default_graceful_sigint")]
+    pub graceful_sigint: bool,
+    #[serde(default)]
+    pub exit_on_parent_death: bool,
+    #[serde(default)]
+    pub exit_when_all_failed: bool,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -122,6 +280,154 @@ pub enum Proto {
     unix,
 }
 
+/// Order of services in operations on all services
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ServiceOrder {
+    /// order of configuration file
+    insertion,
+    /// sorted by service name
+    name,
+    /// configuration file order, shifted by one on each operation
+    rotating,
+}
+
+/// Delivery of signals sent by `fectl signal`
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum SignalDelivery {
+    /// master sends signal to worker process
+    kill,
+    /// master sends `signal` command, worker signals itself
+    protocol,
+}
+
+/// Handling of invalid data on worker pipe
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ProtocolDesync {
+    /// worker process fails with `ProtocolDesync` reason
+    kill,
+    /// invalid bytes are skipped up to next message
+    resync,
+}
+
+/// Handling of `wait_for` dependencies that are not reachable in time
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum DependencyTimeout {
+    /// service fails with `DependencyTimeout` reason
+    fail,
+    /// workers get started anyway
+    proceed,
+}
+
+/// Handling of worker reload and restart requests while service is paused
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum WorkerReload {
+    /// reload worker
+    allow,
+    /// reload worker after service gets resumed
+    defer,
+    /// ignore request
+    deny,
+}
+
+/// Worker probe type
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ProbeType {
+    /// tcp connect to `address`
+    tcp,
+    /// http `GET` request, expects `2xx` status
+    http,
+    /// shell command, expects exit code `0`
+    exec,
+}
+
+/// Service cgroup v2 configuration, Linux only
+///
+/// ```toml
+/// [service.cgroup]
+/// memory_max = "512M"
+/// cpu_max = "50000 100000"
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+pub struct CgroupConfig {
+    /// Parent cgroup directory, default `/sys/fs/cgroup/fectl`
+    #[serde(default = "config_helpers::default_cgroup_path")]
+    pub path: String,
+    /// Value of `memory.max`
+    pub memory_max: Option<String>,
+    /// Value of `cpu.max`, quota and period in microseconds
+    pub cpu_max: Option<String>,
+    /// Value of `pids.max`
+    pub pids_max: Option<String>,
+}
+
+/// Worker probe configuration
+///
+/// ```toml
+/// [service.readiness_probe]
+/// type = "http"
+/// address = "127.0.0.1:8080"
+/// path = "/health"
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProbeConfig {
+    #[serde(rename = "type")]
+    pub kind: ProbeType,
+    /// `host:port` for `tcp` and `http` probes
+    pub address: Option<String>,
+    /// Request path for `http` probe, default `/`
+    #[serde(default = "config_helpers::default_probe_path")]
+    pub path: String,
+    /// Shell command for `exec` probe
+    pub command: Option<String>,
+    /// Delay in seconds before first check, default `0`
+    #[serde(default)]
+    pub initial_delay: u32,
+    /// Interval in seconds between checks, default 1 second
+    #[serde(default = "config_helpers::default_probe_interval")]
+    pub interval: u32,
+    /// Check timeout in seconds, default 1 second
+    #[serde(default = "config_helpers::default_probe_timeout")]
+    pub timeout: u32,
+    /// Number of consecutive failed checks, default `3`
+    #[serde(default = "config_helpers::default_probe_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl ProbeConfig {
+    /// Checked address or command, used in status
+    pub fn target(&self) -> String {
+        match self.kind {
+            ProbeType::exec => self.command.clone().unwrap_or_default(),
+            _ => self.address.clone().unwrap_or_default(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self.kind {
+            // host name is resolved on each check
+            ProbeType::tcp | ProbeType::http => match self.address {
+                Some(ref addr) => match addr.rsplitn(2, ':').collect::<Vec<_>>()[..] {
+                    [port, host] if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                        Ok(())
+                    }
+                    _ => Err(format!("invalid probe address: {}", addr)),
+                },
+                None => Err("probe address is required".to_owned()),
+            },
+            ProbeType::exec => match self.command {
+                Some(_) => Ok(()),
+                None => Err("probe command is required".to_owned()),
+            },
+        }
+    }
+}
+
 /// Socket configuration
 ///
 /// ```toml
@@ -143,6 +449,7 @@ pub struct SocketConfig {
     #[serde(default = "config_helpers::default_proto")]
     pub proto: Proto,
     #[serde(default = "config_helpers::default_vec")]
+    #[allow(dead_code)]
     pub service: Vec<String>,
     pub app: Option<String>,
     #[serde(default = "config_helpers::default_vec")]
@@ -157,9 +464,26 @@ pub struct ServiceConfig {
     /// Number of workers to start
     pub num: u16,
 
+    /// Number of running workers that is enough for supervisor readiness,
+    /// other workers start in background. Default `0`, all workers.
+    #[serde(default)]
+    pub min_healthy: u16,
+
     /// Worker start command
     pub command: String,
 
+    /// Start service only on hosts with matching host name.
+    ///
+    /// Patterns may contain `*` wildcard. Empty list means all hosts.
+    #[serde(default = "config_helpers::default_vec")]
+    pub hosts: Vec<String>,
+
+    /// Arbitrary metadata for tooling, i.e. `owner` or `runbook_url`.
+    ///
+    /// Labels are reported by status and dump, supervisor does not use them.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
     /// Number of restarts before marking worker as failed, default 3
     #[serde(default = "config_helpers::default_restarts")]
     pub restarts: u16,
@@ -167,6 +491,10 @@ pub struct ServiceConfig {
     /// Change to specified directory before service worker loading.
     pub directory: Option<String>,
 
+    /// Parent directory of per-worker scratch directories. Supervisor creates
+    /// directory for each worker process and removes it after process exits.
+    pub scratch_dir: Option<String>,
+
     /// Switch worker process to run as this group.
     ///
     /// A valid group id (as an integer) or the name of a user that can be
@@ -202,6 +530,18 @@ pub struct ServiceConfig {
     #[serde(default = "config_helpers::default_startup_timeout")]
     pub startup_timeout: u32,
 
+    /// Timeout for `forked` message from new worker process, default `0`,
+    /// same as `startup_timeout`.
+    ///
+    /// Worker that does not report `forked` likely does not support fectl
+    /// protocol, so it is marked as failed without restart.
+    #[serde(default)]
+    pub handshake_timeout: u32,
+
+    /// Restart workers that did not report `forked`, default `false`.
+    #[serde(default)]
+    pub restart_no_handshake: bool,
+
     /// Timeout for graceful workers shutdown.
     ///
     /// After receiving a restart or stop signal, workers have this much time to finish
@@ -210,6 +550,185 @@ pub struct ServiceConfig {
     #[serde(default = "config_helpers::default_shutdown_timeout")]
     pub shutdown_timeout: u32,
 
+    /// Upper bound for worker process in stopping state, default `0`,
+    /// twice `shutdown_timeout`.
+    ///
+    /// Worker that is still not reaped after this time is killed with `SIGKILL`
+    /// and considered exited, so reloads and shutdown can proceed.
+    #[serde(default)]
+    pub max_stopping_time: u32,
+
+    /// Signal used for forceful worker termination, default `SIGKILL`.
+    ///
+    /// `SIGABRT` could be used to get core dump of the stuck worker,
+    /// `SIGQUIT` and `SIGSEGV` are allowed as well.
+    #[serde(default = "config_helpers::default_force_kill_signal")]
+    #[serde(deserialize_with = "config_helpers::deserialize_kill_signal")]
+    pub force_kill_signal: Signal,
+
+    /// Delivery of signals sent by `fectl signal`, default `kill`.
+    ///
+    /// `protocol` is useful if worker is in other pid namespace.
+    #[serde(default = "config_helpers::default_signal_delivery")]
+    pub signal_delivery: SignalDelivery,
+
+    /// Handling of invalid data from worker, default `kill`.
+    ///
+    /// `resync` skips invalid bytes and continues with next message.
+    #[serde(default = "config_helpers::default_protocol_desync")]
+    pub protocol_desync: ProtocolDesync,
+
+    /// Do not check worker heartbeat while worker process is stopped
+    /// (i.e. by `SIGSTOP` from debugger), default `false`.
+    #[serde(default)]
+    pub suspend_heartbeat_on_stop: bool,
+
+    /// Probe that has to pass before worker is considered loaded
+    pub readiness_probe: Option<ProbeConfig>,
+
+    /// Probe that runs periodically while worker is running,
+    /// worker gets restarted if probe fails
+    pub liveness_probe: Option<ProbeConfig>,
+
+    /// External dependencies that have to be reachable before workers
+    /// get started, checked with probes
+    #[serde(default = "config_helpers::default_vec")]
+    pub wait_for: Vec<ProbeConfig>,
+
+    /// Time in seconds to wait for `wait_for` dependencies,
+    /// service fails if dependencies are not reachable. Default 60 seconds,
+    /// `0` waits without limit.
+    #[serde(default = "config_helpers::default_wait_for_timeout")]
+    pub wait_for_timeout: u32,
+
+    /// Action after `wait_for_timeout`, default `fail`.
+    ///
+    /// `proceed` starts workers although dependencies are not reachable.
+    #[serde(default = "config_helpers::default_on_dep_timeout")]
+    pub on_dep_timeout: DependencyTimeout,
+
+    /// Delay in seconds between stopping old and starting new workers on reload.
+    ///
+    /// By default new workers start before old workers get stopped. If delay
+    /// is set, all old workers get stopped first, after delay new workers
+    /// get started. Useful for workers that use exclusive resources.
+    #[serde(default)]
+    pub reload_swap_delay: u32,
+
+    /// Delay in milliseconds between starting workers of the service,
+    /// on service start and on reload. Default `0`, all workers start at once.
+    #[serde(default)]
+    pub start_stagger: u32,
+
+    /// Random extra delay in milliseconds, up to this value, added to
+    /// `start_stagger` of each worker. Default `0`.
+    #[serde(default)]
+    pub start_stagger_jitter: u32,
+
+    /// Timeout in seconds for reload, default `0`, no timeout.
+    ///
+    /// If new workers do not start in time, reload is aborted, new workers
+    /// get killed and old workers keep running.
+    #[serde(default)]
+    pub reload_timeout: u32,
+
+    /// Exit codes that are not counted as worker start failure, like `0`.
+    #[serde(default = "config_helpers::default_vec")]
+    pub success_exit_codes: Vec<u8>,
+
+    /// Exit codes that mark worker as failed without restart,
+    /// i.e. code for invalid configuration.
+    #[serde(default = "config_helpers::default_vec")]
+    pub no_restart_exit_codes: Vec<u8>,
+
+    /// Maximum number of failed workers that restart at the same time.
+    ///
+    /// Restarts of other failed workers are queued. Default `0`, unlimited.
+    #[serde(default)]
+    pub restart_concurrency: u16,
+
+    /// Time in seconds to delay restart of crashed worker.
+    ///
+    /// Details of crashed process are kept for inspection with `fectl dump`.
+    /// Default `0`, crashed worker restarts immediately.
+    #[serde(default)]
+    pub hold_failed: u32,
+
+    /// Interval in seconds for starting failed service again.
+    ///
+    /// Service is started with reset restart counters. Default `0`,
+    /// failed service stays failed until `reset` or `start` command.
+    #[serde(default)]
+    pub retry_failed_interval: u32,
+
+    /// Number of failed running worker restarts within `flap_window`
+    /// that quarantines service. Default `0`, disabled.
+    #[serde(default)]
+    pub flap_restarts: u16,
+
+    /// Flap detection window in seconds, default 300 seconds
+    #[serde(default = "config_helpers::default_flap_window")]
+    pub flap_window: u32,
+
+    /// Shell command to run when service gets quarantined
+    pub on_flap_cmd: Option<String>,
+
+    /// Url for json notifications about failed workers, failed
+    /// and quarantined service. Only `http://` urls are supported.
+    pub webhook_url: Option<String>,
+
+    /// Extra http headers of webhook requests, i.e. `Authorization`
+    #[serde(default)]
+    pub webhook_headers: BTreeMap<String, String>,
+
+    /// Handling of worker reload requests while service is paused by operator,
+    /// default `allow`.
+    #[serde(default = "config_helpers::default_worker_reload")]
+    pub worker_reload: WorkerReload,
+
+    /// Reload service together with all services, i.e. on `SIGHUP` or
+    /// configuration file change. Reload by service name is not affected,
+    /// default `true`.
+    #[serde(
+        default = "config_helpers::default_reload_on_sighup",
+        alias = "bulk_reload"
+    )]
+    pub reload_on_sighup: bool,
+
+    /// Run worker in its own process group and send signals to whole group.
+    ///
+    /// Makes sure that children of the worker process get stopped as well.
+    #[serde(default)]
+    pub process_group: bool,
+
+    /// Run worker in its own session, detached from controlling terminal
+    /// of `fectld`, default `false`.
+    ///
+    /// Worker stdin is redirected to `/dev/null`, signals are sent to
+    /// worker process group.
+    #[serde(default)]
+    pub detach_session: bool,
+
+    /// Extra environment variables for worker processes
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Path to a file with environment variables, `KEY=VALUE` per line.
+    ///
+    /// File is read on config load, variables from `env` take precedence.
+    pub env_file: Option<String>,
+
+    /// Do not fail if `env_file` does not exist, default `false`.
+    #[serde(default)]
+    pub env_file_optional: bool,
+
+    /// Keep worker attached to controlling terminal of `fectld`, default `false`.
+    ///
+    /// For interactive services during development. Can not be used with
+    /// `process_group`, `stdout`, `stderr` or daemon mode.
+    #[serde(default)]
+    pub tty: bool,
+
     /// A path to a file where `fectld` should redirect `stdout` for this service.
     ///
     /// By default redirect for stdout is not enabled
@@ -219,6 +738,344 @@ pub struct ServiceConfig {
     ///
     /// By default redirect for stderr is not enabled
     pub stderr: Option<String>,
+
+    /// Shell command that prints version of service binary, i.e. `app --version`.
+    ///
+    /// Command runs on service start and reload.
+    pub version_cmd: Option<String>,
+
+    /// Files that trigger graceful service reload on change,
+    /// i.e. TLS certificates or application config.
+    #[serde(default = "config_helpers::default_vec")]
+    pub watch_files: Vec<String>,
+
+    /// Path to compiled seccomp BPF program, applied to worker process before exec.
+    ///
+    /// Linux only.
+    pub seccomp_profile: Option<String>,
+
+    /// cgroup v2 limits, workers of service share one cgroup.
+    ///
+    /// Linux only.
+    pub cgroup: Option<CgroupConfig>,
+
+    /// OOM killer score adjustment of worker processes, `-1000..1000`.
+    ///
+    /// Linux only.
+    pub oom_score_adj: Option<i16>,
+
+    /// Capabilities kept by worker process, i.e. `CAP_NET_BIND_SERVICE`,
+    /// all other capabilities are dropped after uid and gid change.
+    ///
+    /// Linux only.
+    #[serde(default = "config_helpers::default_vec")]
+    pub capabilities: Vec<String>,
+
+    /// Log command, arguments, directory, uid, gid and environment of each
+    /// worker process before exec, secret-looking values are redacted
+    #[serde(default)]
+    pub log_exec: bool,
+
+    /// Instructions of `seccomp_profile` program, loaded on config load
+    #[serde(skip)]
+    pub seccomp_filter: Vec<u64>,
+
+    /// Numbers of `capabilities`, resolved on config load
+    #[serde(skip)]
+    pub capability_set: Vec<u32>,
+
+    /// Names of fields that are set in configuration file,
+    /// other fields have default values
+    #[serde(skip)]
+    pub explicit: Vec<String>,
+}
+
+impl ServiceConfig {
+    /// Signals are sent to worker process group instead of worker process
+    pub fn signal_group(&self) -> bool {
+        self.process_group || self.detach_session
+    }
+
+    /// Check if service should run on host
+    pub fn runs_on(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|p| utils::host_matches(p, host))
+    }
+
+    /// Effective upper bound for worker stopping state in seconds
+    pub fn max_stopping_time(&self) -> u32 {
+        if self.max_stopping_time == 0 {
+            self.shutdown_timeout * 2
+        } else {
+            self.max_stopping_time
+        }
+    }
+
+    /// Service environment with secret-looking values redacted
+    fn redacted_env(&self) -> BTreeMap<&str, &str> {
+        self.env
+            .iter()
+            .map(|(name, value)| (name.as_str(), utils::redact_env(name, value)))
+            .collect()
+    }
+
+    /// Effective configuration values
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        fn opt<T: std::fmt::Debug>(val: &Option<T>) -> String {
+            match *val {
+                Some(ref val) => format!("{:?}", val),
+                None => "none".to_owned(),
+            }
+        }
+
+        vec![
+            ("name", format!("{:?}", self.name)),
+            ("num", format!("{}", self.num)),
+            ("min_healthy", format!("{}", self.min_healthy)),
+            ("command", format!("{:?}", self.command)),
+            ("hosts", format!("{:?}", self.hosts)),
+            ("labels", format!("{:?}", self.labels)),
+            ("restarts", format!("{}", self.restarts)),
+            ("directory", opt(&self.directory)),
+            ("scratch_dir", opt(&self.scratch_dir)),
+            ("gid", opt(&self.gid)),
+            ("uid", opt(&self.uid)),
+            ("timeout", format!("{}", self.timeout)),
+            ("startup_timeout", format!("{}", self.startup_timeout)),
+            ("handshake_timeout", format!("{}", self.handshake_timeout)),
+            ("restart_no_handshake", format!("{}", self.restart_no_handshake)),
+            ("shutdown_timeout", format!("{}", self.shutdown_timeout)),
+            ("max_stopping_time", format!("{}", self.max_stopping_time())),
+            ("force_kill_signal", format!("{:?}", self.force_kill_signal)),
+            ("signal_delivery", format!("{:?}", self.signal_delivery)),
+            ("protocol_desync", format!("{:?}", self.protocol_desync)),
+            (
+                "suspend_heartbeat_on_stop",
+                format!("{}", self.suspend_heartbeat_on_stop),
+            ),
+            ("readiness_probe", opt(&self.readiness_probe)),
+            ("liveness_probe", opt(&self.liveness_probe)),
+            ("wait_for", format!("{:?}", self.wait_for)),
+            ("wait_for_timeout", format!("{}", self.wait_for_timeout)),
+            ("on_dep_timeout", format!("{:?}", self.on_dep_timeout)),
+            ("reload_swap_delay", format!("{}", self.reload_swap_delay)),
+            ("start_stagger", format!("{}", self.start_stagger)),
+            ("start_stagger_jitter", format!("{}", self.start_stagger_jitter)),
+            ("reload_timeout", format!("{}", self.reload_timeout)),
+            ("success_exit_codes", format!("{:?}", self.success_exit_codes)),
+            ("no_restart_exit_codes", format!("{:?}", self.no_restart_exit_codes)),
+            ("restart_concurrency", format!("{}", self.restart_concurrency)),
+            ("hold_failed", format!("{}", self.hold_failed)),
+            ("retry_failed_interval", format!("{}", self.retry_failed_interval)),
+            ("flap_restarts", format!("{}", self.flap_restarts)),
+            ("flap_window", format!("{}", self.flap_window)),
+            ("on_flap_cmd", opt(&self.on_flap_cmd)),
+            ("webhook_url", opt(&self.webhook_url)),
+            (
+                "webhook_headers",
+                format!("{:?}", self.webhook_headers.keys().collect::<Vec<_>>()),
+            ),
+            ("worker_reload", format!("{:?}", self.worker_reload)),
+            ("reload_on_sighup", format!("{}", self.reload_on_sighup)),
+            ("process_group", format!("{}", self.process_group)),
+            ("detach_session", format!("{}", self.detach_session)),
+            ("env", format!("{:?}", self.redacted_env())),
+            ("env_file", opt(&self.env_file)),
+            ("env_file_optional", format!("{}", self.env_file_optional)),
+            ("tty", format!("{}", self.tty)),
+            ("stdout", opt(&self.stdout)),
+            ("stderr", opt(&self.stderr)),
+            ("version_cmd", opt(&self.version_cmd)),
+            ("watch_files", format!("{:?}", self.watch_files)),
+            ("seccomp_profile", opt(&self.seccomp_profile)),
+            ("cgroup", opt(&self.cgroup)),
+            ("oom_score_adj", opt(&self.oom_score_adj)),
+            ("capabilities", format!("{:?}", self.capabilities)),
+            ("log_exec", format!("{}", self.log_exec)),
+        ]
+    }
+
+    /// Check configuration consistency
+    pub fn validate(&self) -> Result<(), String> {
+        let redirect = self.stdout.is_some() || self.stderr.is_some();
+        if self.tty && (self.process_group || redirect) {
+            return Err(format!(
+                "Service {:?}: tty can not be used with process_group, stdout or stderr",
+                self.name
+            ));
+        }
+        if self.tty && self.detach_session {
+            return Err(format!(
+                "Service {:?}: tty can not be used with detach_session",
+                self.name
+            ));
+        }
+        for probe in self
+            .readiness_probe
+            .iter()
+            .chain(self.liveness_probe.iter())
+            .chain(self.wait_for.iter())
+        {
+            probe
+                .validate()
+                .map_err(|err| format!("Service {:?}: {}", self.name, err))?;
+        }
+        if self.cgroup.is_some() && !cfg!(target_os = "linux") {
+            return Err(format!(
+                "Service {:?}: cgroup is supported only on Linux",
+                self.name
+            ));
+        }
+        if let Some(adj) = self.oom_score_adj {
+            if !(-1000..=1000).contains(&adj) {
+                return Err(format!(
+                    "Service {:?}: oom_score_adj has to be in -1000..1000 range",
+                    self.name
+                ));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(format!(
+                    "Service {:?}: oom_score_adj is supported only on Linux",
+                    self.name
+                ));
+            }
+        }
+        for code in self
+            .success_exit_codes
+            .iter()
+            .chain(self.no_restart_exit_codes.iter())
+        {
+            match i32::from(*code) {
+                WORKER_TIMEOUT | WORKER_INIT_FAILED | WORKER_BOOT_FAILED => {
+                    return Err(format!(
+                        "Service {:?}: exit code {} is reserved by fectl protocol",
+                        self.name, code
+                    ))
+                }
+                _ => (),
+            }
+        }
+        for key in self.labels.keys() {
+            if !valid_label(key) {
+                return Err(format!(
+                    "Service {:?}: invalid label {:?}, label has to start with letter \
+                     and contain only letters, digits, '_', '-' and '.'",
+                    self.name, key
+                ));
+            }
+        }
+        if let Some(ref url) = self.webhook_url {
+            webhook::validate(url).map_err(|err| {
+                format!("Service {:?}: invalid webhook_url: {}", self.name, err)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Merge variables from `env_file` into `env`
+    fn load_env_file(&mut self) -> Result<(), String> {
+        let path = match self.env_file {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        let mut content = String::new();
+        if let Err(err) =
+            std::fs::File::open(&path).and_then(|mut f| f.read_to_string(&mut content))
+        {
+            if self.env_file_optional && err.kind() == std::io::ErrorKind::NotFound {
+                return Ok(());
+            }
+            return Err(format!(
+                "Service {:?}: can not read env file {}: {}",
+                self.name, path, err
+            ));
+        }
+        let vars = config_helpers::parse_env_file(&content).map_err(|err| {
+            format!("Service {:?}: can not parse env file {}: {}", self.name, path, err)
+        })?;
+        for (key, value) in vars {
+            self.env.entry(key).or_insert(value);
+        }
+        Ok(())
+    }
+
+    /// Load `seccomp_profile`, file contains array of `struct sock_filter`
+    fn load_seccomp_profile(&mut self) -> Result<(), String> {
+        let path = match self.seccomp_profile {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        if !cfg!(target_os = "linux") {
+            return Err(format!(
+                "Service {:?}: seccomp is not supported on this platform",
+                self.name
+            ));
+        }
+        let mut content = Vec::new();
+        if let Err(err) =
+            std::fs::File::open(&path).and_then(|mut f| f.read_to_end(&mut content))
+        {
+            return Err(format!(
+                "Service {:?}: can not read seccomp profile {}: {}",
+                self.name, path, err
+            ));
+        }
+        // BPF_MAXINSNS
+        if content.is_empty() || content.len() % 8 != 0 || content.len() / 8 > 4096 {
+            return Err(format!(
+                "Service {:?}: seccomp profile {} is not a valid BPF program",
+                self.name, path
+            ));
+        }
+        self.seccomp_filter = content.chunks(8).map(NativeEndian::read_u64).collect();
+        Ok(())
+    }
+
+    /// Resolve `capabilities` names
+    fn load_capabilities(&mut self) -> Result<(), String> {
+        if self.capabilities.is_empty() {
+            return Ok(());
+        }
+        if !cfg!(target_os = "linux") {
+            return Err(format!(
+                "Service {:?}: capabilities are supported only on Linux",
+                self.name
+            ));
+        }
+        let mut caps = Vec::new();
+        for name in &self.capabilities {
+            match caps::parse(name) {
+                Some(cap) => caps.push(cap),
+                None => {
+                    return Err(format!(
+                        "Service {:?}: unknown capability {}",
+                        self.name, name
+                    ))
+                }
+            }
+        }
+        self.capability_set = caps;
+        Ok(())
+    }
+
+    /// Check if field is set in configuration file
+    pub fn is_explicit(&self, name: &str) -> bool {
+        self.explicit.iter().any(|field| field == name)
+    }
+}
+
+/// Print effective services configuration, marks default values
+fn dump_config(services: &[ServiceConfig]) {
+    for srv in services {
+        println!("[[service]]");
+        for (name, value) in srv.fields() {
+            if srv.is_explicit(name) {
+                println!("{} = {}", name, value);
+            } else {
+                println!("{} = {}  # default", name, value);
+            }
+        }
+        println!();
+    }
 }
 
 /// Loging configuration
@@ -229,6 +1086,7 @@ pub struct ServiceConfig {
 /// facility = "user"
 /// ```
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct LoggingConfig {
     pub name: String,
     pub service: String,
@@ -247,6 +1105,15 @@ impl Default for LoggingConfig {
     }
 }
 
+/// Label key starts with letter and contains letters, digits, `_`, `-` and `.`
+fn valid_label(key: &str) -> bool {
+    key.len() <= 63
+        && key.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
 /// Command line arguments
 #[derive(StructOpt, Debug)]
 struct Cli {
@@ -257,39 +1124,353 @@ struct Cli {
     /// Run in background
     #[structopt(long = "daemon", short = "d")]
     daemon: bool,
+
+    /// Print effective services configuration with default values and exit
+    #[structopt(long = "dump-config")]
+    dump_config: bool,
+
+    /// Run as init process (reap all children, terminate orphans on exit)
+    #[structopt(long = "init")]
+    init: bool,
+
+    /// Start one worker of each service, check that worker completes handshake
+    /// and exit, exit code is non-zero if any service fails
+    #[structopt(long = "validate-and-exit")]
+    validate_and_exit: bool,
+
+    #[structopt(subcommand)]
+    cmd: Option<CliCommand>,
 }
 
-pub fn load_config() -> Option<Config> {
-    let args = Cli::from_args();
+#[derive(StructOpt, Debug)]
+enum CliCommand {
+    /// Run single service without configuration file, stop with Ctrl-C
+    #[structopt(name = "run")]
+    Run {
+        /// Worker start command
+        #[structopt(long = "command")]
+        command: String,
 
+        /// Number of workers to start
+        #[structopt(long = "num", default_value = "1")]
+        num: u16,
+
+        /// Worker heartbeat timeout in seconds
+        #[structopt(long = "timeout", default_value = "10")]
+        timeout: u32,
+
+        /// Service name
+        #[structopt(long = "name", default_value = "run")]
+        name: String,
+    },
+}
+
+fn read_config(path: &Path) -> Result<TomlConfig, String> {
     let mut cfg_str = String::new();
     if let Err(err) =
-        std::fs::File::open(args.config).and_then(|mut f| f.read_to_string(&mut cfg_str))
+        std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut cfg_str))
     {
-        println!(
-            "Can not read configuration file due to: {}",
-            err.description()
-        );
-        return None;
+        return Err(format!("Can not read configuration file due to: {}", err));
+    }
+
+    // format is detected by extension, otherwise try toml and then json
+    let mut cfg = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_toml(&cfg_str),
+        Some("json") => parse_json(&cfg_str),
+        _ => parse_toml(&cfg_str).or_else(|toml_err| {
+            parse_json(&cfg_str).map_err(|json_err| {
+                format!("Can not detect config format: {}, {}", toml_err, json_err)
+            })
+        }),
+    }?;
+
+    // one service per file in `service_dir`
+    let dir = cfg.master.as_ref().and_then(|m| m.service_dir.clone());
+    if let Some(dir) = dir {
+        for (file, srv) in read_service_dir(&service_dir_path(path, &dir))? {
+            if cfg.service.iter().any(|s| s.name == srv.name) {
+                return Err(format!(
+                    "Service {:?} in {:?} is already defined",
+                    srv.name, file
+                ));
+            }
+            cfg.service.push(srv);
+        }
+    }
+
+    for srv in &mut cfg.service {
+        prepare_service(srv)?;
+    }
+    Ok(cfg)
+}
+
+fn parse_toml(cfg_str: &str) -> Result<TomlConfig, String> {
+    let mut cfg: TomlConfig = toml::from_str(cfg_str)
+        .map_err(|err| format!("Can not parse config file as toml: {}", err))?;
+
+    // remember fields that are set explicitly
+    if let Ok(value) = cfg_str.parse::<toml::Value>() {
+        if let Some(services) = value.get("service").and_then(|s| s.as_array()) {
+            for (srv, table) in cfg.service.iter_mut().zip(services) {
+                if let Some(table) = table.as_table() {
+                    srv.explicit = table.keys().cloned().collect();
+                }
+            }
+        }
+    }
+    Ok(cfg)
+}
+
+fn parse_json(cfg_str: &str) -> Result<TomlConfig, String> {
+    let mut cfg: TomlConfig = json::from_str(cfg_str)
+        .map_err(|err| format!("Can not parse config file as json: {}", err))?;
+
+    // remember fields that are set explicitly
+    if let Ok(value) = json::from_str::<json::Value>(cfg_str) {
+        if let Some(services) = value.get("service").and_then(|s| s.as_array()) {
+            for (srv, obj) in cfg.service.iter_mut().zip(services) {
+                if let Some(obj) = obj.as_object() {
+                    srv.explicit = obj.keys().cloned().collect();
+                }
+            }
+        }
+    }
+    Ok(cfg)
+}
+
+/// Service directory path, relative path is relative to configuration file
+fn service_dir_path(path: &Path, dir: &str) -> PathBuf {
+    path.parent().unwrap_or_else(|| Path::new(".")).join(dir)
+}
+
+/// Read `*.toml` and `*.json` files of service directory in name order,
+/// each file is one service
+fn read_service_dir(dir: &Path) -> Result<Vec<(PathBuf, ServiceConfig)>, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("Can not read service directory {:?}: {}", dir, err))?;
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| match file.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") | Some("json") => file.is_file(),
+            _ => false,
+        }).collect();
+    files.sort();
+
+    let mut services = Vec::new();
+    for file in files {
+        let srv = read_service_file(&file)?;
+        services.push((file, srv));
     }
+    Ok(services)
+}
+
+/// Read service file, file name without extension is default service name
+fn read_service_file(file: &Path) -> Result<ServiceConfig, String> {
+    let cfg_str = std::fs::read_to_string(file)
+        .map_err(|err| format!("Can not read service file {:?}: {}", file, err))?;
+
+    let value = match file.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => cfg_str
+            .parse::<toml::Value>()
+            .map_err(|err| format!("{}", err))
+            .and_then(|value| json::to_value(value).map_err(|err| format!("{}", err))),
+        _ => json::from_str::<json::Value>(&cfg_str).map_err(|err| format!("{}", err)),
+    }.map_err(|err| format!("Can not parse service file {:?}: {}", file, err))?;
+
+    let mut srv = match value {
+        json::Value::Object(srv) => srv,
+        _ => return Err(format!("Service file {:?} is not a table", file)),
+    };
+    if !srv.contains_key("name") {
+        if let Some(name) = file.file_stem().and_then(|name| name.to_str()) {
+            srv.insert("name".to_owned(), json::Value::from(name));
+        }
+    }
+
+    let explicit = srv.keys().cloned().collect();
+    let mut srv: ServiceConfig = json::from_value(json::Value::Object(srv))
+        .map_err(|err| format!("Invalid service file {:?}: {}", file, err))?;
+    srv.explicit = explicit;
+    Ok(srv)
+}
+
+/// Service configuration from json object, i.e. service added at runtime
+pub fn parse_service(value: json::Value) -> Result<ServiceConfig, String> {
+    let srv = match value {
+        json::Value::Object(srv) => srv,
+        _ => return Err("Service options are not an object".to_owned()),
+    };
+    let explicit = srv.keys().cloned().collect();
+    let mut srv: ServiceConfig = json::from_value(json::Value::Object(srv))
+        .map_err(|err| format!("Invalid service options: {}", err))?;
+    srv.explicit = explicit;
+    Ok(srv)
+}
+
+/// Check service configuration and load referenced files
+pub fn prepare_service(srv: &mut ServiceConfig) -> Result<(), String> {
+    srv.validate()?;
+    srv.load_env_file()?;
+    srv.load_seccomp_profile()?;
+    srv.load_capabilities()
+}
+
+/// Re-read services configuration from configuration file, services
+/// that do not run on `host` are skipped
+pub fn reload_services(path: &Path, host: &str) -> Result<Vec<ServiceConfig>, String> {
+    let services = read_config(path)?.service;
+    Ok(services
+        .into_iter()
+        .filter(|cfg| {
+            if !cfg.runs_on(host) {
+                info!("Skipping service {:?}, host {:?} does not match", cfg.name, host);
+                return false;
+            }
+            true
+        }).collect())
+}
+
+/// Configuration for single service from command line, other service
+/// options have default values
+pub(crate) fn run_config(
+    name: String, command: String, num: u16, timeout: u32,
+) -> Result<Config, String> {
+    let mut srv = json::Map::new();
+    srv.insert("name".to_owned(), json::Value::from(name));
+    srv.insert("command".to_owned(), json::Value::from(command));
+    srv.insert("num".to_owned(), json::Value::from(num));
+    srv.insert("timeout".to_owned(), json::Value::from(timeout));
+
+    let explicit = srv.keys().cloned().collect();
+    let mut srv: ServiceConfig = json::from_value(json::Value::Object(srv))
+        .map_err(|err| format!("Invalid service options: {}", err))?;
+    srv.explicit = explicit;
+    srv.validate()?;
+
+    let directory = std::env::current_dir()
+        .map_err(|err| format!("Error accessing working directory: {}", err))?
+        .into_os_string();
+
+    let master = MasterConfig {
+        daemon: false,
+        pid: None,
+        sock: OsString::new(),
+        sock_mode: config_helpers::default_sock_mode(),
+        sock_uid: None,
+        sock_gid: None,
+        directory,
+        service_dir: None,
+        gid: None,
+        uid: None,
+        stdout: None,
+        stderr: None,
+        ready_file: None,
+        ready_gate_cmd: None,
+        ready_gate_timeout: config_helpers::default_ready_gate_timeout(),
+        audit_log: None,
+        health_address: None,
+        statsd_address: None,
+        statsd_prefix: config_helpers::default_statsd_prefix(),
+        statsd_tags: false,
+        statsd_interval: config_helpers::default_statsd_interval(),
+        self_check: config_helpers::default_self_check(),
+        watchdog: false,
+        watch_config: false,
+        reap_delay: 0,
+        drain_timeout: 0,
+        term_deadline: 0,
+        service_order: config_helpers::default_service_order(),
+        max_concurrent_reloads: 0,
+        epoch: 0,
+        reap_all: false,
+        graceful_sigint: true,
+        exit_on_parent_death: false,
+        exit_when_all_failed: false,
+        init: false,
+        run: true,
+        validate: false,
+    };
+
+    Ok(Config {
+        path: PathBuf::new(),
+        master,
+        sockets: Vec::new(),
+        services: vec![srv],
+        logging: LoggingConfig::default(),
+    })
+}
 
-    let cfg: TomlConfig = match toml::from_str(&cfg_str) {
+pub fn load_config() -> Option<Config> {
+    let args = Cli::from_args();
+
+    if let Some(CliCommand::Run {
+        name,
+        command,
+        num,
+        timeout,
+    }) = args.cmd
+    {
+        return match run_config(name, command, num, timeout) {
+            Ok(cfg) => Some(cfg),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        };
+    }
+
+    // master changes working directory, so keep absolute path for re-reading
+    let path = std::fs::canonicalize(&args.config)
+        .unwrap_or_else(|_| PathBuf::from(&args.config));
+
+    let cfg = match read_config(&path) {
         Ok(cfg) => cfg,
         Err(err) => {
-            println!("Can not parse config file: {}", err);
+            println!("{}", err);
             return None;
         }
     };
 
+    if args.dump_config {
+        dump_config(&cfg.service);
+        std::process::exit(0);
+    }
+
     // master config
     let toml_master = cfg.master.unwrap_or(TomlMasterConfig {
         sock: config_helpers::default_sock(),
+        sock_mode: config_helpers::default_sock_mode(),
+        sock_uid: None,
+        sock_gid: None,
         directory: None,
+        service_dir: None,
         pid: None,
         gid: None,
         uid: None,
         stdout: None,
         stderr: None,
+        ready_file: None,
+        ready_gate_cmd: None,
+        ready_gate_timeout: config_helpers::default_ready_gate_timeout(),
+        audit_log: None,
+        health_address: None,
+        statsd_address: None,
+        statsd_prefix: config_helpers::default_statsd_prefix(),
+        statsd_tags: false,
+        statsd_interval: config_helpers::default_statsd_interval(),
+        self_check: config_helpers::default_self_check(),
+        watchdog: false,
+        watch_config: false,
+        reap_delay: 0,
+        drain_timeout: 0,
+        term_deadline: 0,
+        service_order: config_helpers::default_service_order(),
+        max_concurrent_reloads: 0,
+        epoch: 0,
+        reap_all: false,
+        graceful_sigint: config_helpers::default_graceful_sigint(),
+        exit_on_parent_death: false,
+        exit_when_all_failed: false,
     });
 
     // check if working directory exists
@@ -309,11 +1490,67 @@ pub fn load_config() -> Option<Config> {
     };
 
     // canonizalize pid file path
-    let pid = if let Some(pid) = toml_master.pid {
-        Some(Path::new(&directory).join(&pid).into_os_string())
-    } else {
-        None
+    let pid = toml_master
+        .pid
+        .map(|pid| Path::new(&directory).join(&pid).into_os_string());
+
+    // canonizalize ready file path
+    let ready_file = toml_master
+        .ready_file
+        .map(|ready_file| Path::new(&directory).join(&ready_file).into_os_string());
+
+    // service directory is relative to configuration file
+    let service_dir = toml_master
+        .service_dir
+        .as_ref()
+        .map(|dir| service_dir_path(&path, dir).into_os_string());
+
+    // canonizalize audit log path
+    let audit_log = toml_master
+        .audit_log
+        .map(|audit_log| Path::new(&directory).join(&audit_log).into_os_string());
+
+    let health_address = match toml_master.health_address {
+        Some(ref addr) => match addr.parse() {
+            Ok(addr) => Some(addr),
+            Err(_) => {
+                println!("Invalid health_address: {}", addr);
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    // agent address is resolved once
+    let statsd_address = match toml_master.statsd_address {
+        Some(ref addr) => {
+            let resolved = addr.to_socket_addrs().ok().and_then(|mut a| a.next());
+            if resolved.is_none() {
+                println!("Invalid statsd_address: {}", addr);
+                return None;
+            }
+            resolved
+        }
+        None => None,
     };
+    if toml_master.statsd_interval == 0 {
+        println!("statsd_interval has to be greater than 0");
+        return None;
+    }
+
+    let init = args.init || nix::unistd::getpid() == Pid::from_raw(1);
+    if init && args.daemon {
+        println!("Daemon mode can not be used in init mode");
+        return None;
+    }
+    if args.validate_and_exit && (init || args.daemon) {
+        println!("--validate-and-exit can not be used in daemon or init mode");
+        return None;
+    }
+    if toml_master.exit_on_parent_death && (init || args.daemon) {
+        println!("exit_on_parent_death can not be used in daemon or init mode");
+        return None;
+    }
 
     let master = MasterConfig {
         // set default value from command line
@@ -323,19 +1560,55 @@ pub fn load_config() -> Option<Config> {
         sock: Path::new(&directory)
             .join(&toml_master.sock)
             .into_os_string(),
+        sock_mode: toml_master.sock_mode,
+        sock_uid: toml_master.sock_uid,
+        sock_gid: toml_master.sock_gid,
 
         pid,
+        ready_file,
+        ready_gate_cmd: toml_master.ready_gate_cmd,
+        ready_gate_timeout: toml_master.ready_gate_timeout,
+        audit_log,
+        health_address,
+        statsd_address,
+        statsd_prefix: toml_master.statsd_prefix,
+        statsd_tags: toml_master.statsd_tags,
+        statsd_interval: toml_master.statsd_interval,
+        self_check: toml_master.self_check,
+        watchdog: toml_master.watchdog,
+        watch_config: toml_master.watch_config,
+        reap_delay: toml_master.reap_delay,
+        drain_timeout: toml_master.drain_timeout,
+        term_deadline: toml_master.term_deadline,
+        service_order: toml_master.service_order,
+        max_concurrent_reloads: toml_master.max_concurrent_reloads,
+        epoch: toml_master.epoch,
+        reap_all: toml_master.reap_all || init,
+        graceful_sigint: toml_master.graceful_sigint,
+        exit_on_parent_death: toml_master.exit_on_parent_death,
+        exit_when_all_failed: toml_master.exit_when_all_failed,
+        init,
+        run: false,
+        validate: args.validate_and_exit,
         gid: toml_master.gid,
         uid: toml_master.uid,
 
         // check if working directory exists
         directory,
+        service_dir,
 
         // redirect stdout/stdout to specifi files
         stdout: toml_master.stdout,
         stderr: toml_master.stderr,
     };
 
+    if master.daemon {
+        if let Some(srv) = cfg.service.iter().find(|srv| srv.tty) {
+            println!("Service {:?}: tty can not be used in daemon mode", srv.name);
+            return None;
+        }
+    }
+
     // sockets config
     let sockets = match socket::Socket::load_config(&cfg.socket) {
         Ok(sockets) => sockets,
@@ -346,9 +1619,101 @@ pub fn load_config() -> Option<Config> {
     };
 
     Some(Config {
+        path,
         master,
         sockets,
         services: cfg.service,
         logging: cfg.logging.unwrap_or(LoggingConfig::default()),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_services_skips_other_hosts() {
+        let path = std::env::temp_dir()
+            .join(format!("fectl-test-hosts-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            [[service]]
+            name = "web"
+            num = 1
+            command = "true"
+            hosts = ["web*"]
+
+            [[service]]
+            name = "db"
+            num = 1
+            command = "true"
+            hosts = ["db*"]
+            "#,
+        ).unwrap();
+
+        // re-read on web host does not bring back service of db hosts
+        let services = reload_services(&path, "web1").unwrap();
+        let _ = std::fs::remove_file(&path);
+        let names: Vec<_> = services.iter().map(|cfg| cfg.name.as_str()).collect();
+        assert_eq!(names, vec!["web"]);
+    }
+
+    #[test]
+    fn test_probe_address_host_name() {
+        let probe = |addr: &str| -> ProbeConfig {
+            json::from_str(&format!(r#"{{"type": "tcp", "address": "{}"}}"#, addr))
+                .unwrap()
+        };
+        assert!(probe("db.internal:5432").validate().is_ok());
+        assert!(probe("10.0.0.5:5432").validate().is_ok());
+        assert!(probe("db.internal").validate().is_err());
+        assert!(probe(":5432").validate().is_err());
+        assert!(probe("db.internal:port").validate().is_err());
+    }
+
+    #[test]
+    fn test_reload_on_sighup_alias() {
+        let srv = |opt: &str| {
+            let cfg = format!(r#"{{"name": "a", "num": 1, "command": "true"{}}}"#, opt);
+            parse_service(json::from_str(&cfg).unwrap()).unwrap()
+        };
+        assert!(srv("").reload_on_sighup);
+        assert!(!srv(r#", "reload_on_sighup": false"#).reload_on_sighup);
+        assert!(!srv(r#", "bulk_reload": false"#).reload_on_sighup);
+    }
+
+    #[test]
+    fn test_empty_service_dir() {
+        let dir = std::env::temp_dir()
+            .join(format!("fectl-test-service-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("services")).unwrap();
+        let path = dir.join("fectl.toml");
+        std::fs::write(&path, "[master]\nservice_dir = \"services\"\n").unwrap();
+
+        // supervisor without services starts and stops
+        let res = read_config(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(res.unwrap().service.is_empty());
+    }
+
+    #[test]
+    fn test_force_kill_signal() {
+        let parse = |sig: &str| {
+            let cfg = format!(
+                r#"{{"name": "test", "num": 1, "command": "true",
+                    "force_kill_signal": "{}"}}"#,
+                sig
+            );
+            parse_service(json::from_str(&cfg).unwrap())
+                .map(|cfg| cfg.force_kill_signal)
+        };
+        assert_eq!(parse("SIGKILL").unwrap(), Signal::SIGKILL);
+        assert_eq!(parse("abrt").unwrap(), Signal::SIGABRT);
+        assert_eq!(parse("SIGQUIT").unwrap(), Signal::SIGQUIT);
+
+        // worker could handle signal and keep running
+        assert!(parse("SIGTERM").is_err());
+        assert!(parse("SIGUSR1").is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use std::ffi::CString;
 
 use libc;
+use nix::sys::signal::Signal;
 use nix::unistd::{Gid, Uid};
 use serde;
 use serde_json as json;
 
-use config::Proto;
+use config::{
+    DependencyTimeout, Proto, ProtocolDesync, ServiceOrder, SignalDelivery, WorkerReload,
+};
 
 pub fn default_vec<T>() -> Vec<T> {
     Vec::new()
@@ -15,6 +18,62 @@ pub fn default_sock() -> String {
     "fectld.sock".to_owned()
 }
 
+pub fn default_sock_mode() -> u32 {
+    0o600
+}
+
+pub fn default_self_check() -> u32 {
+    30
+}
+
+pub fn default_statsd_prefix() -> String {
+    "fectl".to_owned()
+}
+
+pub fn default_statsd_interval() -> u32 {
+    10
+}
+
+pub fn default_ready_gate_timeout() -> u32 {
+    60
+}
+
+pub fn default_graceful_sigint() -> bool {
+    true
+}
+
+pub fn default_reload_on_sighup() -> bool {
+    true
+}
+
+pub fn default_cgroup_path() -> String {
+    "/sys/fs/cgroup/fectl".to_owned()
+}
+
+pub fn default_flap_window() -> u32 {
+    300
+}
+
+pub fn default_probe_path() -> String {
+    "/".to_owned()
+}
+
+pub fn default_probe_interval() -> u32 {
+    1
+}
+
+pub fn default_wait_for_timeout() -> u32 {
+    60
+}
+
+pub fn default_probe_timeout() -> u32 {
+    1
+}
+
+pub fn default_probe_failure_threshold() -> u32 {
+    3
+}
+
 pub fn default_backlog() -> u16 {
     256
 }
@@ -23,6 +82,14 @@ pub fn default_proto() -> Proto {
     Proto::tcp4
 }
 
+pub fn default_worker_reload() -> WorkerReload {
+    WorkerReload::allow
+}
+
+pub fn default_service_order() -> ServiceOrder {
+    ServiceOrder::insertion
+}
+
 pub fn default_restarts() -> u16 {
     3
 }
@@ -39,6 +106,146 @@ pub fn default_shutdown_timeout() -> u32 {
     30
 }
 
+pub fn default_force_kill_signal() -> Signal {
+    Signal::SIGKILL
+}
+
+pub fn default_signal_delivery() -> SignalDelivery {
+    SignalDelivery::kill
+}
+
+pub fn default_protocol_desync() -> ProtocolDesync {
+    ProtocolDesync::kill
+}
+
+pub fn default_on_dep_timeout() -> DependencyTimeout {
+    DependencyTimeout::fail
+}
+
+/// Convert signal name (`SIGKILL` or `KILL`) into `Signal`
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    let name = name.trim().to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+
+    let sig = match name.as_str() {
+        "SIGHUP" => Signal::SIGHUP,
+        "SIGINT" => Signal::SIGINT,
+        "SIGQUIT" => Signal::SIGQUIT,
+        "SIGABRT" => Signal::SIGABRT,
+        "SIGKILL" => Signal::SIGKILL,
+        "SIGUSR1" => Signal::SIGUSR1,
+        "SIGUSR2" => Signal::SIGUSR2,
+        "SIGSEGV" => Signal::SIGSEGV,
+        "SIGTERM" => Signal::SIGTERM,
+        "SIGBUS" => Signal::SIGBUS,
+        "SIGTRAP" => Signal::SIGTRAP,
+        _ => return None,
+    };
+    Some(sig)
+}
+
+/// Deserialize `force_kill_signal` field into `Signal`, worker could ignore
+/// other signals and stay around after forced termination
+pub(crate) fn deserialize_kill_signal<'de, D>(de: D) -> Result<Signal, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let name: String = serde::Deserialize::deserialize(de)?;
+    match parse_signal(&name) {
+        Some(sig @ Signal::SIGKILL)
+        | Some(sig @ Signal::SIGABRT)
+        | Some(sig @ Signal::SIGQUIT)
+        | Some(sig @ Signal::SIGSEGV) => Ok(sig),
+        Some(sig) => Err(serde::de::Error::custom(format!(
+            "Signal {:?} can not be used for force kill, \
+             expected SIGKILL, SIGABRT, SIGQUIT or SIGSEGV",
+            sig
+        ))),
+        None => Err(serde::de::Error::custom(format!(
+            "Unknown signal name: {}",
+            name
+        ))),
+    }
+}
+
+/// Variable name starts with letter or `_` and contains letters, digits and `_`
+fn valid_env_key(key: &str) -> bool {
+    key.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse environment file, `KEY=VALUE` per line
+///
+/// Empty lines and lines starting with `#` are ignored, optional `export`
+/// prefix is allowed, values could be single or double quoted.
+pub fn parse_env_file(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.strip_prefix("export ") {
+            Some(line) => line.trim_start(),
+            None => line,
+        };
+        let pos = match line.find('=') {
+            Some(pos) if pos > 0 => pos,
+            _ => return Err(format!("line {}: expected KEY=VALUE", num + 1)),
+        };
+        let key = line[..pos].trim();
+        if !valid_env_key(key) {
+            return Err(format!("line {}: invalid variable name {:?}", num + 1, key));
+        }
+        let value = line[pos + 1..].trim();
+
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            let mut unescaped = String::new();
+            let mut chars = value[1..value.len() - 1].chars();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => match chars.next() {
+                        Some('n') => unescaped.push('\n'),
+                        Some(ch) => unescaped.push(ch),
+                        None => unescaped.push('\\'),
+                    },
+                    ch => unescaped.push(ch),
+                }
+            }
+            unescaped
+        } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            value[1..value.len() - 1].to_owned()
+        } else {
+            value.to_owned()
+        };
+        vars.push((key.to_owned(), value));
+    }
+    Ok(vars)
+}
+
+/// Deserialize file mode, string value is parsed as octal number
+pub(crate) fn deserialize_mode_field<'de, D>(de: D) -> Result<u32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let deser_result: json::Value = serde::Deserialize::deserialize(de)?;
+    let mode = match deser_result {
+        json::Value::String(ref s) => u32::from_str_radix(s, 8).ok(),
+        json::Value::Number(num) => num.as_u64().map(|num| num as u32),
+        _ => None,
+    };
+    match mode {
+        Some(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(serde::de::Error::custom("Can not parse file mode")),
+    }
+}
+
 /// Deserialize `gid` field into `Gid`
 pub(crate) fn deserialize_gid_field<'de, D>(de: D) -> Result<Option<Gid>, D::Error>
 where
@@ -62,7 +269,7 @@ where
         },
         json::Value::Number(num) => {
             if let Some(num) = num.as_u64() {
-                if num <= u64::from(u32::max_value()) {
+                if num <= u64::from(u32::MAX) {
                     return Ok(Some(Gid::from_raw(num as libc::gid_t)));
                 }
             }
@@ -95,7 +302,7 @@ where
         },
         json::Value::Number(num) => {
             if let Some(num) = num.as_u64() {
-                if num <= u64::from(u32::max_value()) {
+                if num <= u64::from(u32::MAX) {
                     return Ok(Some(Uid::from_raw(num as u32)));
                 }
             }
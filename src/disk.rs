@@ -0,0 +1,57 @@
+// Full disk handling of supervisor file writes, writes that fail because
+// disk is full get skipped and supervision of workers continues
+use std::cell::Cell;
+use std::io;
+use std::time::{Duration, Instant};
+
+use libc;
+
+/// Interval in seconds between write attempts while disk is full
+pub const RETRY_INTERVAL: u64 = 10;
+
+thread_local! {
+    /// time of last write that failed because disk is full
+    static FULL: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Supervisor file writes are skipped because disk is full
+pub fn is_full() -> bool {
+    FULL.with(|full| full.get().is_some())
+}
+
+/// Run file write. While disk is full, writes are skipped, except one attempt
+/// every `RETRY_INTERVAL` seconds. Returns `Ok(false)` if write is skipped
+/// or fails because disk is full, full disk is reported once.
+pub fn write<F>(name: &str, f: F) -> io::Result<bool>
+where
+    F: FnOnce() -> io::Result<()>,
+{
+    if let Some(time) = FULL.with(|full| full.get()) {
+        if time.elapsed() < Duration::new(RETRY_INTERVAL, 0) {
+            return Ok(false);
+        }
+    }
+
+    match f() {
+        Ok(_) => {
+            if FULL.with(|full| full.replace(None)).is_some() {
+                info!("Disk space is available, file writes are resumed");
+            }
+            Ok(true)
+        }
+        Err(ref err) if no_space(err) => {
+            if FULL.with(|full| full.replace(Some(Instant::now()))).is_none() {
+                error!(
+                    "Can not write {}, disk is full, file writes are skipped: {}",
+                    name, err
+                );
+            }
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn no_space(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::ENOSPC) | Some(libc::EDQUOT))
+}
@@ -1,12 +1,195 @@
 use std;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub type ServiceStatus = (String, Vec<(String, Vec<Event>)>);
+use chrono::Local;
+
+/// Service status, binary version, desired and actual number of workers,
+/// workers events, service labels and worker count override
+pub type ServiceStatus = (
+    String,
+    Option<String>,
+    (usize, usize),
+    Vec<(String, Vec<Event>)>,
+    BTreeMap<String, String>,
+    Option<NumOverride>,
+);
+
+/// Runtime override of service worker count
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct NumOverride {
+    pub num: u16,
+    /// `num` of service configuration
+    pub configured: u16,
+    /// configured `num` is restored on next reload with re-read configuration
+    pub revert_on_reload: bool,
+}
+
+/// Worker name, pid, command result (`None` if worker did not respond) and details
+pub type WorkerAck = (String, String, Option<bool>, Option<String>);
+
+/// Worker name, pid, received messages and sent commands counters
+pub type WorkerCounters = (String, String, Vec<(String, u64)>, Vec<(String, u64)>);
+
+/// Worker process starts and failures of service since start or last reset
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct FailureCounters {
+    pub starts: u64,
+    /// worker failed to start, init or boot
+    pub failed_starts: u64,
+    pub heartbeat_failures: u64,
+    pub probe_failures: u64,
+    /// startup and stop timeouts
+    pub timeouts: u64,
+    pub pipe_closed: u64,
+    /// invalid data on worker pipe
+    pub protocol_desyncs: u64,
+    /// exits by signal number
+    pub signals: BTreeMap<usize, u64>,
+    /// exits by exit code
+    pub exit_codes: BTreeMap<i8, u64>,
+}
+
+impl FailureCounters {
+    /// Add counters of other worker
+    pub fn add(&mut self, other: &FailureCounters) {
+        self.starts += other.starts;
+        self.failed_starts += other.failed_starts;
+        self.heartbeat_failures += other.heartbeat_failures;
+        self.probe_failures += other.probe_failures;
+        self.timeouts += other.timeouts;
+        self.pipe_closed += other.pipe_closed;
+        self.protocol_desyncs += other.protocol_desyncs;
+        for (sig, count) in &other.signals {
+            *self.signals.entry(*sig).or_insert(0) += count;
+        }
+        for (code, count) in &other.exit_codes {
+            *self.exit_codes.entry(*code).or_insert(0) += count;
+        }
+    }
+}
+
+/// Supervisor state, used for diagnostics
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub version: String,
+    pub pid: String,
+    /// wall-clock time of snapshot in RFC 3339 format
+    pub time: String,
+    /// path to configuration file
+    pub config: String,
+    /// supervisor file writes are skipped because disk is full
+    pub disk_full: bool,
+    /// supervisor epoch of new worker processes
+    pub epoch: u64,
+    /// seconds until paused reaping of exited workers is resumed
+    pub reaping_paused: Option<u64>,
+    pub services: Vec<ServiceSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServiceSnapshot {
+    pub name: String,
+    pub status: String,
+    /// output of `version_cmd`
+    pub version: Option<String>,
+    /// desired number of workers and number of workers with running process
+    pub desired: usize,
+    pub actual: usize,
+    /// `None` if desired number is not overridden
+    pub num_override: Option<NumOverride>,
+    pub labels: BTreeMap<String, String>,
+    /// effective configuration values
+    pub config: Vec<(String, String)>,
+    pub workers: Vec<WorkerSnapshot>,
+    pub counters: Vec<WorkerCounters>,
+    /// `None` if cgroup is not configured
+    pub cgroup: Option<CgroupUsage>,
+}
+
+/// Likely causes why service does not run desired number of workers
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Diagnosis {
+    pub service: String,
+    pub status: String,
+    /// most relevant cause first, empty if service is healthy
+    pub causes: Vec<String>,
+    pub desired: usize,
+    pub actual: usize,
+    pub disabled: bool,
+    pub quarantined: bool,
+    pub paused: bool,
+    /// workers start is delayed until `wait_for` dependencies are reachable
+    pub waiting_for_dependency: bool,
+    /// `wait_for` dependency that is not reachable
+    pub blocked_by: Option<String>,
+    /// service runs `min_healthy` workers, supervisor readiness is not blocked
+    pub quorum_met: bool,
+    pub workers: Vec<WorkerDiagnosis>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkerDiagnosis {
+    pub name: String,
+    pub state: String,
+    pub pid: Option<String>,
+    /// fast restarts of starting process and `restarts` limit,
+    /// worker is failed when limit is reached
+    pub restarts: u16,
+    pub max_restarts: u16,
+    /// reason of last process failure
+    pub last_failure: Option<Reason>,
+    /// recent failure events, oldest first
+    pub failures: Vec<Event>,
+    /// seconds until restart of crashed process
+    pub held: Option<u64>,
+    /// last lines of `stderr` of crashed process, kept if `hold_failed` is set
+    pub stderr: Vec<String>,
+}
+
+/// Resource usage of service cgroup
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CgroupUsage {
+    /// bytes
+    pub memory_current: Option<u64>,
+    pub cpu_usage_usec: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkerSnapshot {
+    pub name: String,
+    pub state: String,
+    pub pid: Option<String>,
+    /// seconds since worker got running
+    pub uptime: Option<u64>,
+    pub restarts: u16,
+    pub reason: Option<Reason>,
+    pub events: Vec<Event>,
+    /// last crashed process, kept for `hold_failed` seconds
+    pub failed: Option<FailedWorker>,
+    /// seconds until restart of crashed process
+    pub held: Option<u64>,
+}
+
+/// Details of crashed worker process, recorded if `hold_failed` is set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FailedWorker {
+    pub pid: String,
+    pub reason: Reason,
+    /// wall-clock time of exit in RFC 3339 format
+    pub time: String,
+    /// last lines of service `stdout` and `stderr` files
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+    /// resource usage of service cgroup at exit time
+    pub cgroup: Option<CgroupUsage>,
+}
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug)]
 pub enum State {
     Starting,
+    /// worker process is forked, application is loading
+    Preparing,
     Reloading,
     Restarting,
     Running,
@@ -27,27 +210,46 @@ pub enum Reason {
     ConsoleRequest,
     WorkerRequest,
     SomeWorkersFailed,
-    WorkerError(String),
+    WorkerError(Option<u32>, String),
     FailedToStart(Option<String>),
     HeartbeatFailed,
-    StartupTimeout,
+    /// startup timeout with last observed handshake phase
+    StartupTimeout(Option<String>),
+    /// worker did not report `forked`
+    NoHandshake,
     StopTimeout,
     InitFailed,
     BootFailed,
     Signal(usize),
     ExitCode(i8),
+    PipeClosed,
+    /// invalid data on worker pipe
+    ProtocolDesync(String),
+    ProbeFailed(String),
+    /// desired number of workers changed
+    Scaled,
+    /// `wait_for` dependencies are not reachable
+    DependencyTimeout,
     NewProcessDied,
     RestartFailedStartingWorker,
     RestartFailedRunningWorker,
+    /// running worker exited with success exit code
+    RestartExitedRunningWorker,
     RestoreAftreFailed,
+    RetryFailed,
     ReloadAftreTimeout,
+    ReloadTimeout,
+    Flapping,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Event {
     pub state: State,
     pub reason: Reason,
+    /// unix timestamp in seconds
     pub timestamp: u64,
+    /// wall-clock time in RFC 3339 format
+    pub time: String,
     pub pid: Option<String>,
 }
 
@@ -61,10 +263,19 @@ impl Event {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            time: now_rfc3339(),
         }
     }
 }
 
+/// Current local time in RFC 3339 format with milliseconds,
+/// used for events and log records
+pub fn now_rfc3339() -> String {
+    Local::now()
+        .format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+        .to_string()
+}
+
 pub struct Events {
     max: usize,
     events: VecDeque<Event>,
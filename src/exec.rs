@@ -3,54 +3,215 @@ use std;
 use std::ffi::CString;
 use std::io::{Read, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::PathBuf;
 
 use bytes::{Buf, BufMut, BytesMut, IntoBuf};
 use libc;
-use nix::unistd::{chdir, dup2, execve, setgid, setuid};
+use nix::unistd::{chdir, dup2, execve, setgid, setsid, setuid};
 use serde_json as json;
 
+use caps;
+use cgroup;
 use config::ServiceConfig;
-use process::{WORKER_BOOT_FAILED, WORKER_INIT_FAILED};
+use process::{MAX_FRAME, WORKER_BOOT_FAILED, WORKER_INIT_FAILED};
 use utils;
 use worker::{WorkerCommand, WorkerMessage};
 
+/// `struct sock_fprog`
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct SockFprog {
+    len: libc::c_ushort,
+    filter: *const u64,
+}
+
+/// Install seccomp filter, `filter` contains `struct sock_filter` instructions
+#[cfg(target_os = "linux")]
+fn apply_seccomp(filter: &[u64]) -> std::io::Result<()> {
+    let prog = SockFprog {
+        len: filter.len() as libc::c_ushort,
+        filter: filter.as_ptr(),
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &prog as *const SockFprog,
+        ) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_seccomp(_: &[u64]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "seccomp is not supported",
+    ))
+}
+
 fn send_msg(file: &mut std::fs::File, msg: WorkerMessage) {
     let msg = json::to_string(&msg).unwrap();
     let msg_ref: &[u8] = msg.as_ref();
 
-    let mut buf = BytesMut::with_capacity(msg_ref.len() + 2);
-    buf.put_u16_be(msg_ref.len() as u16);
+    let mut buf = BytesMut::with_capacity(msg_ref.len() + 4);
+    buf.put_u32_be(msg_ref.len() as u32);
     buf.put(msg_ref);
     if let Err(err) = file.write_all(buf.as_ref()) {
         error!("Failed to notify master: {}", err);
-        std::process::exit(WORKER_INIT_FAILED as i32);
+        std::process::exit(WORKER_INIT_FAILED);
     }
 }
 
-pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
+/// Switch to configured `uid`, failure is reported to master
+fn set_uid(file: &mut std::fs::File, cfg: &ServiceConfig) {
+    if let Some(uid) = cfg.uid {
+        if let Err(err) = setuid(uid) {
+            send_msg(
+                file,
+                WorkerMessage::cfg_error(format!(
+                    "Can not set worker uid, err: {}",
+                    err
+                )),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    }
+}
+
+/// Switch to configured `gid`, failure is reported to master
+fn set_gid(file: &mut std::fs::File, cfg: &ServiceConfig) {
+    if let Some(gid) = cfg.gid {
+        if let Err(err) = setgid(gid) {
+            send_msg(
+                file,
+                WorkerMessage::cfg_error(format!(
+                    "Can not set worker gid, err: {}",
+                    err
+                )),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    }
+}
+
+/// Log resolved command of worker process, secret-looking values are redacted
+fn log_exec(cfg: &ServiceConfig, path: &str, args: &[CString], env: &[CString]) {
+    let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    let env: Vec<_> = env
+        .iter()
+        .map(|var| {
+            let var = var.to_string_lossy();
+            match var.find('=') {
+                Some(pos) => format!(
+                    "{}={}",
+                    &var[..pos],
+                    utils::redact_env(&var[..pos], &var[pos + 1..])
+                ),
+                None => var.into_owned(),
+            }
+        }).collect();
+    let cwd = std::env::current_dir()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    info!(
+        "Executing worker of service {:?}: path: {:?} args: {:?} cwd: {:?} \
+         uid: {} gid: {} env: {:?}",
+        cfg.name,
+        path,
+        args,
+        cwd,
+        unsafe { libc::getuid() },
+        unsafe { libc::getgid() },
+        env
+    );
+}
+
+pub fn exec_worker(
+    idx: usize, cfg: &ServiceConfig, scratch: Option<&PathBuf>, read: RawFd,
+    write: RawFd,
+) {
     // notify master
     let mut file = unsafe { std::fs::File::from_raw_fd(write) };
     send_msg(&mut file, WorkerMessage::forked);
 
     // read master response
-    let mut buffer = [0; 2];
+    let mut buffer = [0; 4];
     let mut file = unsafe { std::fs::File::from_raw_fd(read) };
     if let Err(err) = file.read_exact(&mut buffer) {
         error!("Failed to read master response: {}", err);
-        std::process::exit(WORKER_INIT_FAILED as i32);
+        std::process::exit(WORKER_INIT_FAILED);
+    }
+    let size = buffer.into_buf().get_u32_be();
+    if size as usize > MAX_FRAME {
+        error!("Master response length {} exceeds {} bytes", size, MAX_FRAME);
+        std::process::exit(WORKER_INIT_FAILED);
     }
-    let size = buffer.into_buf().get_u16_be();
-    let mut buffer = Vec::with_capacity(size as usize);
-    unsafe { buffer.set_len(size as usize) };
+    let mut buffer = vec![0; size as usize];
     if let Err(err) = file.read_exact(&mut buffer) {
         error!("Failed to read master response: {}", err);
-        std::process::exit(WORKER_INIT_FAILED as i32);
+        std::process::exit(WORKER_INIT_FAILED);
     }
-    match json::from_slice::<WorkerCommand>(&buffer) {
-        Ok(WorkerCommand::prepare) => (),
+    let (epoch, peers) = match json::from_slice::<WorkerCommand>(&buffer) {
+        Ok(WorkerCommand::prepare { epoch, peers, .. }) => (epoch, peers),
         Ok(_) | Err(_) => {
             error!("Can not decode master's message: {:?}", &buffer);
-            std::process::exit(WORKER_INIT_FAILED as i32);
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    };
+
+    // interactive worker shares terminal with master process
+    if cfg.tty && unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        send_msg(
+            &mut file,
+            WorkerMessage::cfg_error("tty is requested but stdin is not a terminal"),
+        );
+        std::process::exit(WORKER_INIT_FAILED);
+    }
+
+    // start new session, worker becomes process group leader
+    if cfg.process_group || cfg.detach_session {
+        if let Err(err) = setsid() {
+            send_msg(
+                &mut file,
+                WorkerMessage::cfg_error(format!(
+                    "Can not create session, err: {}",
+                    err
+                )),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    }
+
+    // join service cgroup, before privileges drop
+    if let Some(ref cgroup) = cfg.cgroup {
+        if let Err(err) = cgroup::join(cgroup, &cfg.name) {
+            send_msg(
+                &mut file,
+                WorkerMessage::cfg_error(format!("Can not join cgroup, err: {}", err)),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    }
+
+    // OOM killer preference, lowering score requires privileges
+    if let Some(adj) = cfg.oom_score_adj {
+        let res = std::fs::write("/proc/self/oom_score_adj", format!("{}", adj));
+        if let Err(err) = res {
+            send_msg(
+                &mut file,
+                WorkerMessage::cfg_error(format!(
+                    "Can not set oom_score_adj, err: {}",
+                    err
+                )),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
         }
     }
 
@@ -60,31 +221,35 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
             error!("Can not change directory {:?} err: {:?}", dir, err);
             send_msg(
                 &mut file,
-                WorkerMessage::cfgerror(format!("Can not change directory to {}", dir)),
+                WorkerMessage::cfg_error(format!("Can not change directory to {}", dir)),
             );
-            std::process::exit(WORKER_INIT_FAILED as i32);
+            std::process::exit(WORKER_INIT_FAILED);
         }
     }
 
-    // set uid
-    if let Some(uid) = cfg.uid {
-        if let Err(err) = setuid(uid) {
+    // listed capabilities have to survive uid change
+    if !cfg.capability_set.is_empty() {
+        if let Err(err) = caps::keep() {
             send_msg(
                 &mut file,
-                WorkerMessage::cfgerror(format!("Can not set worker uid, err: {}", err)),
+                WorkerMessage::cfg_error(format!("Can not keep capabilities: {}", err)),
             );
-            std::process::exit(WORKER_INIT_FAILED as i32);
+            std::process::exit(WORKER_INIT_FAILED);
         }
     }
 
-    // set gid
-    if let Some(gid) = cfg.gid {
-        if let Err(err) = setgid(gid) {
+    // set gid before uid, process without root uid can not change gid
+    set_gid(&mut file, cfg);
+    set_uid(&mut file, cfg);
+
+    // drop capabilities that are not listed
+    if !cfg.capability_set.is_empty() {
+        if let Err(err) = caps::apply(&cfg.capability_set) {
             send_msg(
                 &mut file,
-                WorkerMessage::cfgerror(format!("Can not set worker gid, err: {}", err)),
+                WorkerMessage::cfg_error(format!("Can not set capabilities: {}", err)),
             );
-            std::process::exit(WORKER_INIT_FAILED as i32);
+            std::process::exit(WORKER_INIT_FAILED);
         }
     }
 
@@ -97,21 +262,37 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
             error!("Can not find executable");
             send_msg(
                 &mut file,
-                WorkerMessage::cfgerror(format!("Can not find executable: {}", path)),
+                WorkerMessage::cfg_error(format!("Can not find executable: {}", path)),
             );
-            std::process::exit(WORKER_INIT_FAILED as i32);
+            std::process::exit(WORKER_INIT_FAILED);
         }
     } else {
         error!("Can not find executable");
         send_msg(
             &mut file,
-            WorkerMessage::cfgerror("Can not find executable".to_owned()),
+            WorkerMessage::cfg_error("Can not find executable"),
         );
-        std::process::exit(WORKER_INIT_FAILED as i32);
+        std::process::exit(WORKER_INIT_FAILED);
     };
     let mut args: Vec<_> = vec![CString::new(path.as_str()).unwrap()];
     args.extend(iter.map(|s| CString::new(s).unwrap()).collect::<Vec<_>>());
 
+    // detached worker does not read terminal of master process
+    if cfg.detach_session {
+        match std::fs::File::open("/dev/null") {
+            Ok(f) => {
+                let _ = dup2(f.as_raw_fd(), libc::STDIN_FILENO);
+            }
+            Err(err) => {
+                send_msg(
+                    &mut file,
+                    WorkerMessage::cfg_error(format!("Can not open /dev/null: {}", err)),
+                );
+                std::process::exit(WORKER_INIT_FAILED);
+            }
+        }
+    }
+
     // redirect stdout and stderr
     if let Some(ref stdout) = cfg.stdout {
         match std::fs::OpenOptions::new()
@@ -125,12 +306,12 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
             Err(err) => {
                 send_msg(
                     &mut file,
-                    WorkerMessage::cfgerror(format!(
+                    WorkerMessage::cfg_error(format!(
                         "Can open stdout file {}: {}",
                         stdout, err
                     )),
                 );
-                std::process::exit(WORKER_INIT_FAILED as i32);
+                std::process::exit(WORKER_INIT_FAILED);
             }
         }
     }
@@ -147,12 +328,12 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
             Err(err) => {
                 send_msg(
                     &mut file,
-                    WorkerMessage::cfgerror(format!(
+                    WorkerMessage::cfg_error(format!(
                         "Can open stderr file {}: {}",
                         stderr, err
                     )),
                 );
-                std::process::exit(WORKER_INIT_FAILED as i32);
+                std::process::exit(WORKER_INIT_FAILED);
             }
         }
     }
@@ -160,9 +341,51 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
     debug!("Starting worker: {:?}", cfg.command);
 
     let mut env = utils::get_env_vars(true);
+    if !cfg.env.is_empty() {
+        // service variables override inherited ones
+        env.retain(|var| {
+            let var = var.to_string_lossy();
+            var.find('=')
+                .is_none_or(|pos| !cfg.env.contains_key(&var[..pos]))
+        });
+        for (key, value) in &cfg.env {
+            if let Ok(var) = CString::new(format!("{}={}", key, value)) {
+                env.push(var);
+            }
+        }
+    }
     env.push(CString::new(format!("FECTL_FD={}:{}", read, write)).unwrap());
     env.push(CString::new(format!("FECTL_SRV_NAME={}", cfg.name)).unwrap());
     env.push(CString::new(format!("FECTL_PROC_IDX={}", idx)).unwrap());
+    env.push(CString::new(format!("FECTL_EPOCH={}", epoch)).unwrap());
+    let peers: Vec<_> = peers
+        .iter()
+        .map(|peer| format!("{}:{}", peer.idx, peer.pid))
+        .collect();
+    env.push(CString::new(format!("FECTL_PEERS={}", peers.join(","))).unwrap());
+    if let Some(dir) = scratch {
+        let var = format!("FECTL_SCRATCH_DIR={}", dir.to_string_lossy());
+        env.push(CString::new(var).unwrap());
+    }
+
+    if cfg.log_exec {
+        log_exec(cfg, &path, &args, &env);
+    }
+
+    // seccomp filter, applied after privileges drop
+    if !cfg.seccomp_filter.is_empty() {
+        if let Err(err) = apply_seccomp(&cfg.seccomp_filter) {
+            send_msg(
+                &mut file,
+                WorkerMessage::cfg_error(format!(
+                    "Can not apply seccomp profile: {}",
+                    err
+                )),
+            );
+            std::process::exit(WORKER_INIT_FAILED);
+        }
+    }
+
     match execve(&CString::new(path).unwrap(), &args, &env) {
         Ok(_) => unreachable!(),
         Err(err) => {
@@ -170,7 +393,7 @@ pub fn exec_worker(idx: usize, cfg: &ServiceConfig, read: RawFd, write: RawFd) {
                 "Can not execute command: \"{}\" with error: {:?}",
                 cfg.command, err
             );
-            std::process::exit(WORKER_BOOT_FAILED as i32);
+            std::process::exit(WORKER_BOOT_FAILED);
         }
     }
 }
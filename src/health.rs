@@ -0,0 +1,97 @@
+// Liveness and readiness http endpoints for load balancers and orchestrators
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use actix::prelude::*;
+use futures::{future, Future, Stream};
+use tokio;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::timer::Timeout;
+
+use cmd::{self, CommandCenter};
+use disk;
+
+/// Time for command center to respond to health check
+const HEALTH_TIMEOUT: u64 = 2;
+/// Time for client to send request and read response, connection
+/// of slow or idle client is closed
+const CONN_TIMEOUT: u64 = 5;
+
+const OK: &str = "200 OK";
+const DEGRADED: &str = "200 Degraded, disk is full";
+const UNAVAILABLE: &str = "503 Service Unavailable";
+const NOT_FOUND: &str = "404 Not Found";
+
+/// Start health endpoints listener
+pub fn start(addr: &SocketAddr, cmd: Addr<CommandCenter>) -> io::Result<()> {
+    let lst = TcpListener::bind(addr)?;
+    info!("Health endpoints are listening on {}", addr);
+
+    Arbiter::spawn(
+        lst.incoming()
+            .map_err(|err| error!("Health endpoints listener error: {}", err))
+            .for_each(move |stream| {
+                let timeout = Duration::new(CONN_TIMEOUT, 0);
+                Arbiter::spawn(
+                    Timeout::new(handle(stream, cmd.clone()), timeout).map_err(|_| ()),
+                );
+                Ok(())
+            }),
+    );
+    Ok(())
+}
+
+/// Read request line and write response, connection is closed after response
+fn handle(
+    stream: TcpStream, cmd: Addr<CommandCenter>,
+) -> impl Future<Item = (), Error = ()> {
+    tokio::io::read(stream, vec![0; 1024])
+        .map_err(|_| ())
+        .and_then(move |(stream, buf, size)| {
+            let path = String::from_utf8_lossy(&buf[..size])
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or("")
+                .to_owned();
+
+            check(&path, cmd).and_then(move |status| {
+                let body = &status[4..];
+                let resp = format!(
+                    "HTTP/1.0 {}\r\nContent-Type: text/plain\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+                    status,
+                    body.len() + 1,
+                    body
+                );
+                tokio::io::write_all(stream, resp)
+                    .map(|_| ())
+                    .map_err(|_| ())
+            })
+        })
+}
+
+/// `/healthz` checks that command center processes messages, supervisor
+/// with full disk is reported as degraded, `/readyz` checks that all services
+/// are running
+fn check(
+    path: &str, cmd: Addr<CommandCenter>,
+) -> Box<dyn Future<Item = &'static str, Error = ()>> {
+    let timeout = Duration::new(HEALTH_TIMEOUT, 0);
+    match path {
+        "/healthz" => Box::new(cmd.send(cmd::Ping).timeout(timeout).then(|res| {
+            match res {
+                Ok(_) if disk::is_full() => Ok(DEGRADED),
+                Ok(_) => Ok(OK),
+                Err(_) => Ok(UNAVAILABLE),
+            }
+        })),
+        "/readyz" => Box::new(cmd.send(cmd::Ready).timeout(timeout).then(|res| {
+            match res {
+                Ok(Ok(true)) => Ok(OK),
+                _ => Ok(UNAVAILABLE),
+            }
+        })),
+        _ => Box::new(future::ok(NOT_FOUND)),
+    }
+}
@@ -11,9 +11,9 @@ use tokio::io::AsyncRead;
 use tokio::prelude::*;
 use tokio::reactor::PollEvented2;
 
+/// Owns both pipe fds, fds get closed on drop
 pub struct PipeFile {
     read_poll: PollEvented2<Io>,
-    write: Io,
     write_poll: PollEvented2<Io>,
 }
 
@@ -21,7 +21,6 @@ impl PipeFile {
     pub fn new(read: RawFd, write: RawFd) -> PipeFile {
         PipeFile {
             read_poll: PollEvented2::new(unsafe { Io::from_raw_fd(read) }),
-            write: unsafe { Io::from_raw_fd(write) },
             write_poll: PollEvented2::new(unsafe { Io::from_raw_fd(write) }),
         }
     }
@@ -51,7 +50,7 @@ impl Write for PipeFile {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        (&self.write).flush()
+        self.write_poll.get_ref().flush()
     }
 }
 
@@ -130,7 +129,7 @@ impl Read for Io {
     }
 }
 
-impl<'a> Read for &'a Io {
+impl Read for &Io {
     fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
         (&self.fd).read(dst)
     }
@@ -146,7 +145,7 @@ impl Write for Io {
     }
 }
 
-impl<'a> Write for &'a Io {
+impl Write for &Io {
     fn write(&mut self, src: &[u8]) -> io::Result<usize> {
         (&self.fd).write(src)
     }
@@ -158,7 +157,7 @@ impl<'a> Write for &'a Io {
 
 impl AsyncRead for Io {}
 
-impl<'a> AsyncRead for &'a Io {}
+impl AsyncRead for &Io {}
 
 impl AsyncWrite for Io {
     fn shutdown(&mut self) -> Poll<(), io::Error> {
@@ -166,7 +165,7 @@ impl AsyncWrite for Io {
     }
 }
 
-impl<'a> AsyncWrite for &'a Io {
+impl AsyncWrite for &Io {
     fn shutdown(&mut self) -> Poll<(), io::Error> {
         Ok(().into())
     }
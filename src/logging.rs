@@ -3,31 +3,29 @@ use std::str::FromStr;
 
 use env_logger::Builder;
 use log::LevelFilter;
-use time;
 
 use config::LoggingConfig;
+use event;
 use version::PKG_INFO;
 
 pub fn init_logging(cfg: &LoggingConfig) {
     let level = cfg
         .level
         .as_ref()
-        .and_then(|s| match LevelFilter::from_str(&s) {
-            Ok(lvl) => Some(lvl),
+        .map(|s| match LevelFilter::from_str(s) {
+            Ok(lvl) => lvl,
             Err(_) => {
                 println!("Can not parse log level value, using `info` level");
-                Some(LevelFilter::Info)
+                LevelFilter::Info
             }
         }).unwrap_or(LevelFilter::Info);
 
     Builder::new()
         .format(|buf, record| {
-            let t = time::now();
-            write!(
+            writeln!(
                 buf,
-                "{},{:03} - {} - {}\n",
-                time::strftime("%Y-%m-%d %H:%M:%S", &t).unwrap(),
-                t.tm_nsec / 1000_000,
+                "{} - {} - {}",
+                event::now_rfc3339(),
                 record.level(),
                 record.args()
             )
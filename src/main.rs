@@ -1,10 +1,8 @@
 extern crate env_logger;
-extern crate time;
 #[macro_use]
 extern crate log;
 
 extern crate structopt;
-#[macro_use]
 extern crate structopt_derive;
 
 extern crate serde;
@@ -14,6 +12,7 @@ extern crate serde_derive;
 
 extern crate byteorder;
 extern crate bytes;
+extern crate chrono;
 extern crate futures;
 extern crate libc;
 extern crate mio;
@@ -22,26 +21,34 @@ extern crate nix;
 extern crate tokio;
 extern crate toml;
 
-#[macro_use]
 extern crate actix;
 
 mod addrinfo;
+mod audit;
+mod caps;
+mod cgroup;
 mod client;
 mod cmd;
 mod config;
 mod config_helpers;
+mod disk;
 mod event;
 mod exec;
+mod health;
 mod io;
 mod logging;
 mod master;
 mod master_types;
+mod probe;
 mod process;
 mod service;
 mod socket;
+mod statsd;
 mod utils;
+mod webhook;
 mod worker;
 
+#[allow(dead_code)]
 mod version {
     include!(concat!(env!("OUT_DIR"), "/version.rs"));
 }
@@ -49,9 +56,22 @@ mod version {
 fn main() {
     let sys = actix::System::new("fectl");
     let loaded = match config::load_config() {
-        Some(cfg) => master::start(cfg),
+        Some(cfg) => if cfg.master.run {
+            master::run(cfg)
+        } else if cfg.master.validate {
+            master::validate(cfg)
+        } else {
+            master::start(cfg)
+        },
         None => false,
     };
-    let code = if loaded { sys.run() } else { 1 };
+    let code = if loaded {
+        match sys.run() {
+            0 => cmd::exit_code(),
+            code => code,
+        }
+    } else {
+        1
+    };
     std::process::exit(code);
 }
@@ -1,6 +1,7 @@
 use std;
 use std::ffi::OsStr;
 use std::io;
+use std::os::unix::fs::PermissionsExt;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixListener as StdUnixListener;
 use std::rc::Rc;
@@ -19,17 +20,22 @@ use tokio::io::{WriteHalf, AsyncRead};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::reactor::Handle;
 
+use audit::{self, AuditLog, Principal};
 use client;
 use cmd::{self, CommandCenter, CommandError};
-use config::Config;
+use config::{self, Config};
+use config_helpers;
+use health;
 use logging;
 use master_types::{MasterRequest, MasterResponse};
 use service::{ReloadStatus, ServiceOperationError, StartStatus};
+use utils;
 use version::PKG_INFO;
 
 pub struct Master {
     cfg: Rc<Config>,
     cmd: Addr<CommandCenter>,
+    audit: Option<Rc<AuditLog>>,
 }
 
 impl Actor for Master {
@@ -42,13 +48,19 @@ struct NetStream(UnixStream);
 impl StreamHandler<NetStream, io::Error> for Master {
     fn handle(&mut self, msg: NetStream, _: &mut Context<Self>) {
         let cmd = self.cmd.clone();
+        let audit = self.audit.clone();
+        let principal =
+            utils::peer_cred(msg.0.as_raw_fd()).map(|(pid, uid)| Principal { pid, uid });
 
-        MasterClient::create(|ctx| {
+        MasterClient::create(move |ctx| {
             let (r, w) = msg.0.split();
             ctx.add_stream(FramedRead::new(r, MasterTransportCodec));
 
             MasterClient {
                 cmd,
+                audit,
+                principal,
+                pending: None,
                 framed: actix::io::FramedWrite::new(w, MasterTransportCodec, ctx),
             }
         });
@@ -63,6 +75,10 @@ impl Drop for Master {
 
 struct MasterClient {
     cmd: Addr<CommandCenter>,
+    audit: Option<Rc<AuditLog>>,
+    principal: Option<Principal>,
+    /// audited request that waits for response
+    pending: Option<audit::Action>,
     framed: actix::io::FramedWrite<WriteHalf<UnixStream>, MasterTransportCodec>,
 }
 
@@ -72,6 +88,15 @@ impl Actor for MasterClient {
     fn started(&mut self, ctx: &mut Self::Context) {
         self.hb(ctx);
     }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(action) = self.pending.take() {
+            if let Some(ref log) = self.audit {
+                log.record(self.principal, &action, "NoResponse");
+            }
+        }
+        Running::Stop
+    }
 }
 
 impl actix::io::WriteHandler<io::Error> for MasterClient {}
@@ -89,33 +114,61 @@ impl MasterClient {
         });
     }
 
+    /// write response to client, record result of audited request
+    fn write(&mut self, resp: MasterResponse) {
+        if let Some(action) = self.pending.take() {
+            if let Some(ref log) = self.audit {
+                log.record(self.principal, &action, &audit::result(&resp));
+            }
+        }
+        self.framed.write(resp);
+    }
+
     fn handle_error(&mut self, err: CommandError, _: &mut Context<Self>) {
         match err {
-            CommandError::NotReady => self.framed.write(MasterResponse::ErrorNotReady),
+            CommandError::NotReady => self.write(MasterResponse::ErrorNotReady),
             CommandError::UnknownService => {
-                self.framed.write(MasterResponse::ErrorUnknownService)
+                self.write(MasterResponse::ErrorUnknownService)
             }
             CommandError::ServiceStopped => {
-                self.framed.write(MasterResponse::ErrorServiceStopped)
+                self.write(MasterResponse::ErrorServiceStopped)
+            }
+            CommandError::ServiceExists => {
+                self.write(MasterResponse::ErrorServiceExists)
+            }
+            CommandError::ConfigError(err) => {
+                self.write(MasterResponse::ErrorConfig(err))
             }
             CommandError::Service(err) => match err {
                 ServiceOperationError::Starting => {
-                    self.framed.write(MasterResponse::ErrorServiceStarting)
+                    self.write(MasterResponse::ErrorServiceStarting)
                 }
                 ServiceOperationError::Reloading => {
-                    self.framed.write(MasterResponse::ErrorServiceReloading)
+                    self.write(MasterResponse::ErrorServiceReloading)
                 }
                 ServiceOperationError::Stopping => {
-                    self.framed.write(MasterResponse::ErrorServiceStopping)
+                    self.write(MasterResponse::ErrorServiceStopping)
                 }
                 ServiceOperationError::Running => {
-                    self.framed.write(MasterResponse::ErrorServiceRunning)
+                    self.write(MasterResponse::ErrorServiceRunning)
                 }
                 ServiceOperationError::Stopped => {
-                    self.framed.write(MasterResponse::ErrorServiceStopped)
+                    self.write(MasterResponse::ErrorServiceStopped)
                 }
                 ServiceOperationError::Failed => {
-                    self.framed.write(MasterResponse::ErrorServiceFailed)
+                    self.write(MasterResponse::ErrorServiceFailed)
+                }
+                ServiceOperationError::NotConfirmed => {
+                    self.write(MasterResponse::ErrorNotConfirmed)
+                }
+                ServiceOperationError::Quarantined => {
+                    self.write(MasterResponse::ErrorServiceQuarantined)
+                }
+                ServiceOperationError::Disabled => {
+                    self.write(MasterResponse::ErrorServiceDisabled)
+                }
+                ServiceOperationError::NoWorkers => {
+                    self.write(MasterResponse::ErrorServiceNoWorkers)
                 }
             },
         };
@@ -132,21 +185,23 @@ impl MasterClient {
                     Err(_) => (),
                     Ok(Err(err)) => match err {
                         CommandError::ServiceStopped => {
-                            srv.framed.write(MasterResponse::ServiceStarted)
+                            srv.write(MasterResponse::ServiceStarted)
                         }
                         _ => srv.handle_error(err, ctx),
                     },
-                    Ok(Ok(_)) => srv.framed.write(MasterResponse::ServiceStopped),
+                    Ok(Ok(_)) => srv.write(MasterResponse::ServiceStopped),
                 };
                 actix::fut::ok(())
             }).spawn(ctx);
     }
 
-    fn reload(&mut self, name: String, ctx: &mut Context<Self>, graceful: bool) {
+    fn reload(
+        &mut self, name: String, ctx: &mut Context<Self>, graceful: bool, reread: bool,
+    ) {
         info!("Client command: Reload service '{}'", name);
 
         self.cmd
-            .send(cmd::ReloadService(name, graceful))
+            .send(cmd::ReloadService(name, graceful, reread))
             .into_actor(self)
             .then(|res, srv, ctx| {
                 match res {
@@ -155,13 +210,45 @@ impl MasterClient {
                     Ok(Ok(res)) => {
                         match res {
                             ReloadStatus::Success => {
-                                srv.framed.write(MasterResponse::ServiceStarted)
+                                srv.write(MasterResponse::ServiceStarted)
                             }
                             ReloadStatus::Failed => {
-                                srv.framed.write(MasterResponse::ServiceFailed)
+                                srv.write(MasterResponse::ServiceFailed)
                             }
                             ReloadStatus::Stopping => {
-                                srv.framed.write(MasterResponse::ErrorServiceStopping)
+                                srv.write(MasterResponse::ErrorServiceStopping)
+                            }
+                            ReloadStatus::Timeout => {
+                                srv.write(MasterResponse::ErrorReloadTimeout)
+                            }
+                        };
+                    }
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    fn reset_service(&mut self, name: String, start: bool, ctx: &mut Context<Self>) {
+        info!("Client command: Reset service '{}'", name);
+
+        self.cmd
+            .send(cmd::ResetService(name, start))
+            .into_actor(self)
+            .then(|res, srv, ctx| {
+                match res {
+                    Err(_) => (),
+                    Ok(Err(err)) => srv.handle_error(err, ctx),
+                    Ok(Ok(res)) => {
+                        match res {
+                            None => srv.write(MasterResponse::Done),
+                            Some(StartStatus::Success) => {
+                                srv.write(MasterResponse::ServiceStarted)
+                            }
+                            Some(StartStatus::Failed) => {
+                                srv.write(MasterResponse::ServiceFailed)
+                            }
+                            Some(StartStatus::Stopping) => {
+                                srv.write(MasterResponse::ErrorServiceStopping)
                             }
                         };
                     }
@@ -183,13 +270,13 @@ impl MasterClient {
                     Ok(Ok(res)) => {
                         match res {
                             StartStatus::Success => {
-                                srv.framed.write(MasterResponse::ServiceStarted)
+                                srv.write(MasterResponse::ServiceStarted)
                             }
                             StartStatus::Failed => {
-                                srv.framed.write(MasterResponse::ServiceFailed)
+                                srv.write(MasterResponse::ServiceFailed)
                             }
                             StartStatus::Stopping => {
-                                srv.framed.write(MasterResponse::ErrorServiceStopping)
+                                srv.write(MasterResponse::ErrorServiceStopping)
                             }
                         };
                     }
@@ -207,14 +294,165 @@ impl Handler<MasterRequest> for MasterClient {
     type Result = ();
 
     fn handle(&mut self, msg: MasterRequest, ctx: &mut Context<Self>) {
+        if self.audit.is_some() {
+            self.pending = audit::action(&msg);
+        }
+
         match msg {
             MasterRequest::Ping => {
                 self.framed.write(MasterResponse::Pong);
             }
             MasterRequest::Start(name) => self.start_service(name, ctx),
-            MasterRequest::Reload(name) => self.reload(name, ctx, true),
-            MasterRequest::Restart(name) => self.reload(name, ctx, false),
+            MasterRequest::Reload(name, reread) => self.reload(name, ctx, true, reread),
+            MasterRequest::Restart(name, reread) => {
+                self.reload(name, ctx, false, reread)
+            }
+            MasterRequest::ReloadAll(reread) => {
+                info!("Client command: Reload all services");
+                self.cmd
+                    .send(cmd::ReloadAll(reread))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
             MasterRequest::Stop(name) => self.stop(name, ctx),
+            MasterRequest::Reset(name, start) => self.reset_service(name, start, ctx),
+            MasterRequest::Disable(name) => {
+                info!("Client command: Disable service '{}'", name);
+                self.cmd
+                    .send(cmd::DisableService(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::ServiceStopped),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::AddService(options) => {
+                let cfg = match config::parse_service(options) {
+                    Ok(cfg) => cfg,
+                    Err(err) => {
+                        self.write(MasterResponse::ErrorConfig(err));
+                        return;
+                    }
+                };
+                info!("Client command: Add service '{}'", cfg.name);
+                self.cmd
+                    .send(cmd::AddService(cfg))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::RemoveService(name) => {
+                info!("Client command: Remove service '{}'", name);
+                self.cmd
+                    .send(cmd::RemoveService(name, true))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::ServiceStopped),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Enable(name) => {
+                info!("Client command: Enable service '{}'", name);
+                self.cmd
+                    .send(cmd::EnableService(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Scale(name, num) => {
+                info!("Client command: Scale service '{}' to {}", name, num);
+                self.cmd
+                    .send(cmd::ScaleService(name, num))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::OverrideNum(name, num, revert) => {
+                info!(
+                    "Client command: Override workers of service '{}' to {}",
+                    name, num
+                );
+                self.cmd
+                    .send(cmd::OverrideServiceNum(name, num, revert))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Signal(name, sig) => {
+                info!("Client command: Send {} to service '{}'", sig, name);
+                let sig = match config_helpers::parse_signal(&sig) {
+                    Some(sig) => sig,
+                    None => return self.write(MasterResponse::ErrorUnknownSignal),
+                };
+                self.cmd
+                    .send(cmd::SignalService(name, sig))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(pids)) => {
+                                srv.write(MasterResponse::ServiceWorkerPids(pids))
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Notify(name, cmd, data) => {
+                info!("Client command: Notify service '{}' with '{}'", name, cmd);
+                self.cmd
+                    .send(cmd::NotifyService(name, cmd, data))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(acks)) => {
+                                srv.write(MasterResponse::ServiceWorkerAcks(acks))
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
             MasterRequest::Pause(name) => {
                 info!("Client command: Pause service '{}'", name);
                 self.cmd
@@ -225,7 +463,7 @@ impl Handler<MasterRequest> for MasterClient {
                             Err(_) => (),
                             Ok(Err(err)) => srv.handle_error(err, ctx),
                             Ok(Ok(_)) => {
-                                srv.framed.write(MasterResponse::Done);
+                                srv.write(MasterResponse::Done);
                             }
                         };
                         actix::fut::ok(())
@@ -241,7 +479,7 @@ impl Handler<MasterRequest> for MasterClient {
                             Err(_) => (),
                             Ok(Err(err)) => srv.handle_error(err, ctx),
                             Ok(Ok(_)) => {
-                                srv.framed.write(MasterResponse::Done);
+                                srv.write(MasterResponse::Done);
                             }
                         };
                         actix::fut::ok(())
@@ -257,7 +495,7 @@ impl Handler<MasterRequest> for MasterClient {
                             Err(_) => (),
                             Ok(Err(err)) => srv.handle_error(err, ctx),
                             Ok(Ok(status)) => {
-                                srv.framed.write(MasterResponse::ServiceStatus(status));
+                                srv.write(MasterResponse::ServiceStatus(status));
                             }
                         };
                         actix::fut::ok(())
@@ -273,19 +511,124 @@ impl Handler<MasterRequest> for MasterClient {
                             Err(_) => (),
                             Ok(Err(err)) => srv.handle_error(err, ctx),
                             Ok(Ok(pids)) => {
-                                srv.framed
-                                    .write(MasterResponse::ServiceWorkerPids(pids));
+                                srv.write(MasterResponse::ServiceWorkerPids(pids));
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Counters(name) => {
+                debug!("Client command: Service counters '{}'", name);
+                self.cmd
+                    .send(cmd::ServiceCounters(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(counters)) => srv
+                                .write(MasterResponse::ServiceWorkerCounters(counters)),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Failures(name) => {
+                debug!("Client command: Service failures '{}'", name);
+                self.cmd
+                    .send(cmd::ServiceFailures(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(failures)) => {
+                                srv.write(MasterResponse::ServiceFailures(failures))
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Diagnose(name) => {
+                debug!("Client command: Diagnose service '{}'", name);
+                self.cmd
+                    .send(cmd::DiagnoseService(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(diagnosis)) => {
+                                srv.write(MasterResponse::ServiceDiagnosis(diagnosis))
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::ResetFailures(name) => {
+                info!("Client command: Reset service failures '{}'", name);
+                self.cmd
+                    .send(cmd::ResetServiceFailures(name))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(_)) => srv.write(MasterResponse::Done),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::Dump => {
+                debug!("Client command: Dump supervisor state");
+                self.cmd
+                    .send(cmd::Dump)
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(snapshot)) => {
+                                srv.write(MasterResponse::Snapshot(snapshot))
+                            }
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::BumpEpoch => {
+                info!("Client command: Bump supervisor epoch");
+                self.cmd
+                    .send(cmd::BumpEpoch)
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(epoch)) => srv.write(MasterResponse::Epoch(epoch)),
+                        };
+                        actix::fut::ok(())
+                    }).spawn(ctx);
+            }
+            MasterRequest::PauseReaping(secs) => {
+                info!("Client command: Pause reaping for {} seconds", secs);
+                self.cmd
+                    .send(cmd::PauseReaping(secs))
+                    .into_actor(self)
+                    .then(|res, srv, ctx| {
+                        match res {
+                            Err(_) => (),
+                            Ok(Err(err)) => srv.handle_error(err, ctx),
+                            Ok(Ok(secs)) => {
+                                srv.write(MasterResponse::ReapingPaused(secs))
                             }
                         };
                         actix::fut::ok(())
                     }).spawn(ctx);
             }
             MasterRequest::Pid => {
-                self.framed
-                    .write(MasterResponse::Pid(format!("{}", nix::unistd::getpid())));
+                self.write(MasterResponse::Pid(format!("{}", nix::unistd::getpid())));
             }
             MasterRequest::Version => {
-                self.framed.write(MasterResponse::Version(format!(
+                self.write(MasterResponse::Version(format!(
                     "{} {}",
                     PKG_INFO.name, PKG_INFO.version
                 )));
@@ -295,7 +638,7 @@ impl Handler<MasterRequest> for MasterClient {
                     .send(cmd::Stop)
                     .into_actor(self)
                     .then(|_, act, _| {
-                        act.framed.write(MasterResponse::Done);
+                        act.write(MasterResponse::Done);
                         actix::fut::ok(())
                     }).spawn(ctx);
             }
@@ -348,6 +691,107 @@ impl Encoder for MasterTransportCodec {
 
 const HOST: &str = "127.0.0.1:57897";
 
+/// Attempts to bind commands socket and delay in milliseconds before first retry
+const BIND_ATTEMPTS: u32 = 5;
+const BIND_DELAY: u64 = 100;
+
+/// Run single service in foreground, without controller socket and pid file
+pub fn run(cfg: Config) -> bool {
+    // init logging
+    logging::init_logging(&cfg.logging);
+
+    info!("Running service {:?}, press Ctrl-C to stop", cfg.services[0].name);
+
+    let _ = CommandCenter::start(Rc::new(cfg));
+    true
+}
+
+/// Check worker handshake of all services, without controller socket and pid file
+pub fn validate(cfg: Config) -> bool {
+    // init logging
+    logging::init_logging(&cfg.logging);
+
+    info!("Validating services");
+
+    // service paths are relative to working dir
+    if let Err(err) = nix::unistd::chdir::<OsStr>(cfg.master.directory.as_ref()) {
+        error!(
+            "Can not change directory {:?} err: {}",
+            cfg.master.directory, err
+        );
+        return false;
+    }
+
+    let _ = CommandCenter::start(Rc::new(cfg));
+    true
+}
+
+/// Create commands listener. Stale socket file of exited master process
+/// gets removed, transient errors are retried with backoff
+fn bind(cfg: &Config) -> Option<StdUnixListener> {
+    let mut delay = Duration::from_millis(BIND_DELAY);
+    for attempt in 1..=BIND_ATTEMPTS {
+        let err = match StdUnixListener::bind(&cfg.master.sock) {
+            Ok(lst) => return Some(lst),
+            Err(err) => err,
+        };
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => {
+                error!(
+                    "Can not create socket file {:?} err: Permission denied.",
+                    cfg.master.sock
+                );
+                return None;
+            }
+            io::ErrorKind::AddrInUse => match client::is_alive(&cfg.master) {
+                client::AliveStatus::Alive => {
+                    error!("Can not start: Another process is running.");
+                    return None;
+                }
+                client::AliveStatus::NotResponding => {
+                    error!("Master process is not responding.");
+                    if let Some(pid) = cfg.master.load_pid() {
+                        error!("Master process: (pid:{})", pid);
+                    } else {
+                        error!("Can not load pid of the master process.");
+                    }
+                    return None;
+                }
+                client::AliveStatus::NotAlive => {
+                    // nothing listens on socket, left by unclean shutdown
+                    match std::fs::remove_file(&cfg.master.sock) {
+                        Ok(_) => info!(
+                            "Removed stale socket file {:?}",
+                            cfg.master.sock
+                        ),
+                        Err(err) => warn!(
+                            "Can not remove stale socket file {:?}: {}",
+                            cfg.master.sock, err
+                        ),
+                    }
+                    continue;
+                }
+            },
+            _ => {
+                // i.e. socket directory is not created yet
+                if attempt < BIND_ATTEMPTS {
+                    warn!(
+                        "Can not create listener socket: {}, retrying in {:?}",
+                        err, delay
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    continue;
+                }
+                error!("Can not create listener socket: {}", err);
+                return None;
+            }
+        }
+    }
+    error!("Can not create listener socket {:?}", cfg.master.sock);
+    None
+}
+
 /// Start master process
 pub fn start(cfg: Config) -> bool {
     // init logging
@@ -383,57 +827,48 @@ pub fn start(cfg: Config) -> bool {
     }
 
     // create commands listener and also check if service process is running
-    let lst: StdUnixListener = match StdUnixListener::bind(&cfg.master.sock) {
-        Ok(lst) => lst,
-        Err(err) => match err.kind() {
-            io::ErrorKind::PermissionDenied => {
-                error!(
-                    "Can not create socket file {:?} err: Permission denied.",
-                    cfg.master.sock
-                );
-                return false;
-            }
-            io::ErrorKind::AddrInUse => {
-                match client::is_alive(&cfg.master) {
-                    client::AliveStatus::Alive => {
-                        error!("Can not start: Another process is running.");
-                        return false;
-                    }
-                    client::AliveStatus::NotResponding => {
-                        error!("Master process is not responding.");
-                        if let Some(pid) = cfg.master.load_pid() {
-                            error!("Master process: (pid:{})", pid);
-                        } else {
-                            error!("Can not load pid of the master process.");
-                        }
-                        return false;
-                    }
-                    client::AliveStatus::NotAlive => {
-                        // remove socket and try again
-                        let _ = std::fs::remove_file(&cfg.master.sock);
-                        match StdUnixListener::bind(&cfg.master.sock) {
-                            Ok(lst) => lst,
-                            Err(err) => {
-                                error!("Can not create listener socket: {}", err);
-                                return false;
-                            }
-                        }
-                    }
-                }
-            }
-            _ => {
-                error!("Can not create listener socket: {}", err);
-                return false;
-            }
-        },
+    let lst = match bind(&cfg) {
+        Some(lst) => lst,
+        None => return false,
     };
 
+    // restrict access to commands socket
+    if let Err(err) = std::fs::set_permissions(
+        &cfg.master.sock,
+        std::fs::Permissions::from_mode(cfg.master.sock_mode),
+    ) {
+        error!("Can not set socket file {:?} mode: {}", cfg.master.sock, err);
+        return false;
+    }
+    if cfg.master.sock_uid.is_some() || cfg.master.sock_gid.is_some() {
+        if let Err(err) = nix::unistd::chown(
+            std::path::Path::new(&cfg.master.sock),
+            cfg.master.sock_uid,
+            cfg.master.sock_gid,
+        ) {
+            error!("Can not set socket file {:?} owner: {}", cfg.master.sock, err);
+            return false;
+        }
+    }
+
     // try to save pid
     if let Err(err) = cfg.master.save_pid() {
         error!("Can not write pid file {:?} err: {}", cfg.master.pid, err);
         return false;
     }
 
+    // audit log is opened before privileges drop
+    let audit = match cfg.master.audit_log {
+        Some(ref path) => match AuditLog::open(path) {
+            Ok(log) => Some(Rc::new(log)),
+            Err(err) => {
+                error!("Can not open audit log {:?} err: {}", path, err);
+                return false;
+            }
+        },
+        None => None,
+    };
+
     // set uid
     if let Some(uid) = cfg.master.uid {
         if let Err(err) = nix::unistd::setuid(uid) {
@@ -450,6 +885,11 @@ pub fn start(cfg: Config) -> bool {
         }
     }
 
+    // orphaned descendants get re-parented to master process
+    if cfg.master.init && !utils::set_subreaper() {
+        warn!("Can not mark process as child subreaper");
+    }
+
     let daemon = cfg.master.daemon;
     if daemon {
         if let Err(err) = nix::unistd::daemon(true, false) {
@@ -504,10 +944,18 @@ pub fn start(cfg: Config) -> bool {
     // command center
     let cmd = CommandCenter::start(cfg.clone());
 
+    // liveness and readiness endpoints
+    if let Some(ref addr) = cfg.master.health_address {
+        if let Err(err) = health::start(addr, cmd.clone()) {
+            error!("Can not bind health endpoints address {}: {}", addr, err);
+            return false;
+        }
+    }
+
     // start uds master server
     let _ = Master::create(|ctx| {
-        ctx.add_stream(lst.incoming().map(|s| NetStream(s)));
-        Master { cfg, cmd }
+        ctx.add_stream(lst.incoming().map(NetStream));
+        Master { cfg, cmd, audit }
     });
 
     if !daemon {
@@ -1,4 +1,8 @@
-use event::ServiceStatus;
+use serde_json as json;
+
+use event::{
+    Diagnosis, FailureCounters, ServiceStatus, Snapshot, WorkerAck, WorkerCounters,
+};
 
 /// Master command
 #[allow(non_camel_case_types)]
@@ -11,18 +15,53 @@ pub enum MasterRequest {
     Status(String),
     /// Service pids
     SPid(String),
+    /// Worker messages counters
+    Counters(String),
+    /// Worker starts and failures counters
+    Failures(String),
+    /// Clear worker starts and failures counters
+    ResetFailures(String),
+    /// Likely causes why service does not run desired number of workers
+    Diagnose(String),
     /// Start service
     Start(String),
     /// Pause service
     Pause(String),
     /// Resume service
     Resume(String),
-    /// Gracefully reload service
-    Reload(String),
-    /// Restart service
-    Restart(String),
+    /// Gracefully reload service, re-read configuration if flag is set
+    Reload(String, bool),
+    /// Restart service, re-read configuration if flag is set
+    Restart(String, bool),
+    /// Gracefully reload all services, re-read configuration file if flag is set
+    ReloadAll(bool),
     /// Gracefully stop service
     Stop(String),
+    /// Reset failed service, start service if flag is set
+    Reset(String, bool),
+    /// Gracefully stop and disable service
+    Disable(String),
+    /// Enable disabled service
+    Enable(String),
+    /// Start new service with options, service is not added to configuration file
+    AddService(json::Value),
+    /// Gracefully stop and remove service
+    RemoveService(String),
+    /// Set desired number of service workers
+    Scale(String, u16),
+    /// Temporarily override number of service workers,
+    /// revert on reload with re-read configuration if flag is set
+    OverrideNum(String, u16, bool),
+    /// Send signal with name to service workers
+    Signal(String, String),
+    /// Send custom command with name and data to service workers
+    Notify(String, String, json::Value),
+    /// Supervisor and all services state
+    Dump,
+    /// Increase supervisor epoch
+    BumpEpoch,
+    /// Pause reaping of exited workers for number of seconds, `0` resumes
+    PauseReaping(u32),
     /// Pid of the master process
     Pid,
     /// Quit process
@@ -42,6 +81,12 @@ pub enum MasterResponse {
     Pid(String),
     /// Version of the master process
     Version(String),
+    /// Supervisor and all services state
+    Snapshot(Snapshot),
+    /// Supervisor epoch
+    Epoch(u64),
+    /// Seconds until reaping of exited workers is resumed
+    ReapingPaused(u64),
 
     /// Service started
     ServiceStarted,
@@ -53,6 +98,14 @@ pub enum MasterResponse {
     ServiceStatus(ServiceStatus),
     /// Service workers pids
     ServiceWorkerPids(Vec<String>),
+    /// Service workers messages counters
+    ServiceWorkerCounters(Vec<WorkerCounters>),
+    /// Service workers starts and failures counters
+    ServiceFailures(FailureCounters),
+    /// Likely causes why service does not run desired number of workers
+    ServiceDiagnosis(Diagnosis),
+    /// Service workers results of custom command
+    ServiceWorkerAcks(Vec<WorkerAck>),
 
     /// System not ready
     ErrorNotReady,
@@ -70,4 +123,20 @@ pub enum MasterResponse {
     ErrorServiceStopped,
     /// Service is failed
     ErrorServiceFailed,
+    /// Service is quarantined because of flapping
+    ErrorServiceQuarantined,
+    /// Service is disabled
+    ErrorServiceDisabled,
+    /// Service has no workers
+    ErrorServiceNoWorkers,
+    /// Service with same name already exists
+    ErrorServiceExists,
+    /// Not all workers confirmed command
+    ErrorNotConfirmed,
+    /// New workers did not start in time, old workers keep running
+    ErrorReloadTimeout,
+    /// Can not re-read configuration
+    ErrorConfig(String),
+    /// Signal name is not known
+    ErrorUnknownSignal,
 }
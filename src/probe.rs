@@ -0,0 +1,295 @@
+// Worker readiness and liveness probes
+use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures::sync::oneshot;
+use futures::{future, Async, Future, Poll};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use tokio;
+use tokio::net::TcpStream;
+use tokio::util::FutureExt;
+
+use config::{ProbeConfig, ProbeType};
+
+/// Exit code or signal of command process
+pub type CommandStatus = Result<i32, Signal>;
+
+thread_local! {
+    /// running command processes, reaper hands over exit status.
+    /// sender is dropped if command is not waited for anymore
+    static COMMANDS: RefCell<HashMap<Pid, Option<oneshot::Sender<CommandStatus>>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Command processes that are not reaped yet
+pub fn command_pids() -> Vec<Pid> {
+    COMMANDS.with(|cmds| cmds.borrow().keys().cloned().collect())
+}
+
+/// Reaped process is command process, returns `false` if pid is unknown
+pub fn exited(pid: Pid, status: CommandStatus) -> bool {
+    match COMMANDS.with(|cmds| cmds.borrow_mut().remove(&pid)) {
+        Some(tx) => {
+            if let Some(tx) = tx {
+                let _ = tx.send(status);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Run probe once, resolves with error description if check fails
+pub fn check(probe: &ProbeConfig) -> Box<dyn Future<Item = (), Error = String>> {
+    let fut: Box<dyn Future<Item = (), Error = String>> = match probe.kind {
+        ProbeType::tcp => Box::new(address(probe).and_then(|addr| {
+            TcpStream::connect(&addr)
+                .map(|_| ())
+                .map_err(|err| format!("can not connect: {}", err))
+        })),
+        ProbeType::http => {
+            let probe = probe.clone();
+            Box::new(address(&probe).and_then(move |addr| http(addr, &probe)))
+        }
+        ProbeType::exec => match probe.command {
+            Some(ref cmd) => exec(cmd.clone()),
+            None => Box::new(future::err("command is not set".to_owned())),
+        },
+    };
+
+    with_timeout(fut, probe.timeout)
+}
+
+/// Run shell command once with timeout in seconds
+pub fn command(cmd: &str, timeout: u32) -> Box<dyn Future<Item = (), Error = String>> {
+    with_timeout(exec(cmd.to_owned()), timeout)
+}
+
+/// Run shell command once with extra environment variables
+/// and timeout in seconds
+pub fn command_env(
+    cmd: &str, env: &[(&str, &str)], timeout: u32,
+) -> Box<dyn Future<Item = (), Error = String>> {
+    let fut: Box<dyn Future<Item = (), Error = String>> = match spawn(cmd, env, false) {
+        Ok((proc, _)) => Box::new(proc),
+        Err(err) => Box::new(future::err(err)),
+    };
+    with_timeout(fut, timeout)
+}
+
+/// Run shell command once with timeout in seconds, resolves with first
+/// line of command output
+pub fn output(
+    cmd: &str, timeout: u32,
+) -> Box<dyn Future<Item = Option<String>, Error = String>> {
+    let (proc, stdout) = match spawn(cmd, &[], true) {
+        Ok(res) => res,
+        Err(err) => return Box::new(future::err(err)),
+    };
+
+    // output is read in separate thread, pipe is closed when command exits
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut out = String::new();
+        if let Some(mut stdout) = stdout {
+            let _ = stdout.read_to_string(&mut out);
+        }
+        let _ = tx.send(out);
+    });
+
+    let fut = proc
+        .join(rx.map_err(|_| "command is canceled".to_owned()))
+        .map(|(_, out)| out.lines().next().map(|s| s.trim().to_owned()));
+    with_timeout(fut, timeout)
+}
+
+fn with_timeout<F, T>(fut: F, timeout: u32) -> Box<dyn Future<Item = T, Error = String>>
+where
+    F: Future<Item = T, Error = String> + 'static,
+    T: 'static,
+{
+    let timeout = Duration::new(u64::from(timeout), 0);
+    Box::new(fut.timeout(timeout).map_err(|err| {
+        if err.is_elapsed() {
+            "timeout".to_owned()
+        } else {
+            err.into_inner().unwrap_or_else(|| "timer error".to_owned())
+        }
+    }))
+}
+
+/// Resolve probe address in separate thread, so host name changes
+/// are picked up by next check without blocking supervisor
+fn address(probe: &ProbeConfig) -> impl Future<Item = SocketAddr, Error = String> {
+    let addr = probe.address.clone();
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let res = match addr {
+            Some(addr) => addr
+                .to_socket_addrs()
+                .map_err(|err| format!("can not resolve address: {}", err))
+                .and_then(|mut addrs| {
+                    addrs
+                        .next()
+                        .ok_or_else(|| format!("can not resolve address: {}", addr))
+                }),
+            None => Err("address is not set".to_owned()),
+        };
+        let _ = tx.send(res);
+    });
+    rx.map_err(|_| "address resolution is canceled".to_owned())
+        .and_then(|res| res)
+}
+
+/// `GET` request, any `2xx` status is success
+fn http(
+    addr: SocketAddr, probe: &ProbeConfig,
+) -> impl Future<Item = (), Error = String> {
+    let req = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        probe.path,
+        probe.target()
+    );
+
+    TcpStream::connect(&addr)
+        .and_then(move |stream| tokio::io::write_all(stream, req))
+        .and_then(|(stream, _)| tokio::io::read(stream, vec![0; 64]))
+        .map_err(|err| format!("request failed: {}", err))
+        .and_then(|(_, buf, size)| {
+            let line = String::from_utf8_lossy(&buf[..size]);
+            match line.split_whitespace().nth(1) {
+                Some(code) if code.starts_with('2') => Ok(()),
+                Some(code) => Err(format!("http status {}", code)),
+                None => Err("invalid http response".to_owned()),
+            }
+        })
+}
+
+/// Run shell command, exit code `0` is success
+fn exec(cmd: String) -> Box<dyn Future<Item = (), Error = String>> {
+    match spawn(&cmd, &[], false) {
+        Ok((proc, _)) => Box::new(proc),
+        Err(err) => Box::new(future::err(err)),
+    }
+}
+
+/// Start shell command, exit status is collected by supervisor reaper
+fn spawn(
+    cmd: &str, env: &[(&str, &str)], stdout: bool,
+) -> Result<(CommandProcess, Option<std::process::ChildStdout>), String> {
+    let mut command = std::process::Command::new("/bin/sh");
+    command.arg("-c").arg(cmd);
+    for &(key, value) in env {
+        command.env(key, value);
+    }
+    if stdout {
+        command.stdout(Stdio::piped());
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|err| format!("can not run command: {}", err))?;
+
+    // child is not waited for, dropped `Child` leaves process running
+    let pid = Pid::from_raw(child.id() as i32);
+    let (tx, rx) = oneshot::channel();
+    COMMANDS.with(|cmds| cmds.borrow_mut().insert(pid, Some(tx)));
+    let proc = CommandProcess {
+        pid,
+        rx,
+        done: false,
+    };
+    Ok((proc, child.stdout.take()))
+}
+
+/// Running command process, resolves with exit status from reaper.
+/// Process is killed if it is dropped before exit, i.e. on timeout
+struct CommandProcess {
+    pid: Pid,
+    rx: oneshot::Receiver<CommandStatus>,
+    done: bool,
+}
+
+impl Future for CommandProcess {
+    type Item = ();
+    type Error = String;
+
+    fn poll(&mut self) -> Poll<(), String> {
+        let status = match self.rx.poll() {
+            Ok(Async::Ready(status)) => status,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(_) => {
+                self.done = true;
+                return Err("command is canceled".to_owned());
+            }
+        };
+        self.done = true;
+        match status {
+            Ok(0) => Ok(Async::Ready(())),
+            Ok(code) => Err(format!("command failed: exit code: {}", code)),
+            Err(sig) => Err(format!("command failed: signal: {:?}", sig)),
+        }
+    }
+}
+
+impl Drop for CommandProcess {
+    fn drop(&mut self) {
+        if !self.done {
+            warn!("Killing command process (pid:{})", self.pid);
+            let _ = kill(self.pid, Signal::SIGKILL);
+            // exit status is still collected by reaper
+            COMMANDS.with(|cmds| {
+                if let Some(tx) = cmds.borrow_mut().get_mut(&self.pid) {
+                    tx.take();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::wait::{waitpid, WaitStatus};
+
+    #[test]
+    fn test_command_status_from_reaper() {
+        let (proc, _) = spawn("exit 3", &[], false).unwrap();
+        let pid = proc.pid;
+        assert_eq!(waitpid(pid, None).unwrap(), WaitStatus::Exited(pid, 3));
+        assert!(command_pids().contains(&pid));
+
+        assert!(exited(pid, Ok(3)));
+        assert_eq!(proc.wait(), Err("command failed: exit code: 3".to_owned()));
+        assert!(command_pids().is_empty());
+    }
+
+    #[test]
+    fn test_address_resolved_by_name() {
+        let probe: ProbeConfig = ::serde_json::from_str(
+            r#"{"type": "tcp", "address": "localhost:5432"}"#,
+        ).unwrap();
+        let addr = address(&probe).wait().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), 5432);
+    }
+
+    #[test]
+    fn test_command_killed_on_drop() {
+        let (proc, _) = spawn("sleep 10", &[], false).unwrap();
+        let pid = proc.pid;
+        drop(proc);
+
+        // killed process is still reaped by supervisor
+        match waitpid(pid, None).unwrap() {
+            WaitStatus::Signaled(_, Signal::SIGKILL, _) => (),
+            status => panic!("unexpected status: {:?}", status),
+        }
+        assert!(exited(pid, Err(Signal::SIGKILL)));
+    }
+}
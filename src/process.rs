@@ -1,60 +1,312 @@
 #![allow(dead_code)]
 
 use std;
-use std::error::Error;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
+use std::os::unix::fs::DirBuilderExt;
 use std::os::unix::io::RawFd;
-use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{BufMut, BytesMut};
+use futures::Poll;
+use libc;
+use nix;
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::{close, fork, pipe, ForkResult, Pid};
 use serde_json as json;
 use tokio::codec::{Decoder, Encoder, FramedRead};
-use tokio::io::{AsyncRead, WriteHalf};
+use tokio::io::{AsyncRead, AsyncWrite, WriteHalf};
 
 use actix::prelude::*;
 
-use config::ServiceConfig;
+use config::{ProbeConfig, ProtocolDesync, ServiceConfig};
 use event::Reason;
 use exec::exec_worker;
 use io::PipeFile;
+use probe;
 use service::{self, FeService};
-use worker::{WorkerCommand, WorkerMessage};
+use utils;
+use worker::{CfgError, Peer, WorkerCommand, WorkerMessage};
 
 const HEARTBEAT: u64 = 2;
-const WORKER_TIMEOUT: i32 = 98;
+/// Seconds worker that reported `stopped` has to exit
+const STOPPED_GRACE: u64 = 1;
+/// Number of bytes of invalid worker data that get logged
+const DESYNC_LOG_BYTES: usize = 64;
+/// Maximum size of worker message or command frame
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+pub const WORKER_TIMEOUT: i32 = 98;
 pub const WORKER_INIT_FAILED: i32 = 99;
 pub const WORKER_BOOT_FAILED: i32 = 100;
 
+thread_local! {
+    /// pids of forked worker processes that are not reaped yet,
+    /// with flag if worker process is process group leader
+    static FORKED: RefCell<HashMap<Pid, bool>> = RefCell::new(HashMap::new());
+
+    /// scratch directories of forked worker processes
+    static SCRATCH: RefCell<HashMap<Pid, PathBuf>> = RefCell::new(HashMap::new());
+
+    /// supervisor epoch, sent to new worker processes with `prepare` command
+    static EPOCH: Cell<u64> = const { Cell::new(0) };
+
+    /// running workers by service name, sent with `prepare` command
+    static PEERS: RefCell<HashMap<String, Vec<Peer>>> = RefCell::new(HashMap::new());
+}
+
+/// Current supervisor epoch
+pub fn epoch() -> u64 {
+    EPOCH.with(|epoch| epoch.get())
+}
+
+/// Set supervisor epoch, running worker processes keep their epoch
+pub fn set_epoch(epoch: u64) {
+    EPOCH.with(|val| val.set(epoch))
+}
+
+/// Running workers of service
+pub fn peers(service: &str) -> Vec<Peer> {
+    PEERS.with(|peers| peers.borrow().get(service).cloned().unwrap_or_default())
+}
+
+/// Set running workers of service, used for new worker processes
+pub fn set_peers(service: &str, peers: Vec<Peer>) {
+    PEERS.with(|val| val.borrow_mut().insert(service.to_owned(), peers));
+}
+
+/// Forget running workers of removed service
+pub fn forget_peers(service: &str) {
+    PEERS.with(|val| val.borrow_mut().remove(service));
+}
+
+/// Forked worker processes that are not reaped yet
+pub fn forked_pids() -> Vec<Pid> {
+    FORKED.with(|pids| pids.borrow().keys().cloned().collect())
+}
+
+/// Forked worker process runs in its own process group
+pub fn is_group_leader(pid: Pid) -> bool {
+    FORKED.with(|pids| pids.borrow().get(&pid).cloned().unwrap_or(false))
+}
+
+/// Check if worker process exited, exit status is left for reaper
+fn has_exited(pid: Pid) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let res = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            libc::pid_t::from(pid) as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    res == 0 && info.si_signo == libc::SIGCHLD
+}
+
+/// Worker process is not reaped and did not exit yet
+fn is_alive(pid: Pid) -> bool {
+    FORKED.with(|pids| pids.borrow().contains_key(&pid)) && !has_exited(pid)
+}
+
+/// Failure of worker process that closed pipe during initialization,
+/// `None` if process exited already and reaper reports its exit status
+fn init_pipe_closed(pid: Pid) -> Option<ProcessError> {
+    if has_exited(pid) {
+        None
+    } else {
+        Some(ProcessError::FailedToStart(Some(
+            "Pipe closed during initialization".to_owned(),
+        )))
+    }
+}
+
+/// Forget reaped worker process, returns `false` if pid is unknown.
+/// Scratch directory of worker process gets removed.
+pub fn forget_pid(pid: Pid) -> bool {
+    if let Some(dir) = SCRATCH.with(|dirs| dirs.borrow_mut().remove(&pid)) {
+        remove_scratch_dir(&dir);
+    }
+    FORKED.with(|pids| pids.borrow_mut().remove(&pid).is_some())
+}
+
+/// send signal to worker process, or to whole worker process group
+fn signal(pid: Pid, process_group: bool, sig: Signal) -> nix::Result<()> {
+    if process_group {
+        kill(Pid::from_raw(-libc::pid_t::from(pid)), sig)
+    } else {
+        kill(pid, sig)
+    }
+}
+
+/// Write half of worker command pipe, pending signal is sent to worker
+/// once written commands are flushed to pipe
+struct CommandPipe {
+    io: WriteHalf<PipeFile>,
+    pid: Pid,
+    process_group: bool,
+    signal: Rc<Cell<Option<Signal>>>,
+}
+
+impl io::Write for CommandPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // writer flushes pipe after all buffered commands are written
+        self.io.flush()?;
+        if let Some(sig) = self.signal.take() {
+            let _ = signal(self.pid, self.process_group, sig);
+        }
+        Ok(())
+    }
+}
+
+impl AsyncWrite for CommandPipe {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// Create unique scratch directory for worker, owned by worker user
+fn create_scratch_dir(
+    parent: &str, idx: usize, cfg: &ServiceConfig,
+) -> Result<PathBuf, io::Error> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos()))
+        .unwrap_or(0);
+    let dir = Path::new(parent).join(format!("{}-{}-{}", cfg.name, idx, nanos));
+
+    std::fs::create_dir_all(parent)?;
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    if cfg.uid.is_some() || cfg.gid.is_some() {
+        if let Err(err) = nix::unistd::chown(dir.as_path(), cfg.uid, cfg.gid) {
+            remove_scratch_dir(&dir);
+            return Err(io::Error::other(format!(
+                "Can not change owner of scratch dir {:?}: {}",
+                dir, err
+            )));
+        }
+    }
+    Ok(dir)
+}
+
+fn remove_scratch_dir(dir: &Path) {
+    if let Err(err) = std::fs::remove_dir_all(dir) {
+        warn!("Can not remove scratch dir {:?}: {}", dir, err);
+    }
+}
+
 pub struct Process {
     idx: usize,
+    service: String,
     pid: Pid,
     state: ProcessState,
     hb: Instant,
+    /// pending `StartupTimeout` notification
+    startup_timer: Option<SpawnHandle>,
+    /// pending `StopTimeout` notification
+    stop_timer: Option<SpawnHandle>,
+    /// worker confirmed `stop` command
+    stop_confirmed: bool,
     addr: Addr<FeService>,
     timeout: Duration,
     startup_timeout: u64,
+    handshake_timeout: u64,
     shutdown_timeout: u64,
-    framed: actix::io::FramedWrite<WriteHalf<PipeFile>, TransportCodec>,
+    force_kill_signal: Signal,
+    suspend_heartbeat: bool,
+    suspended: bool,
+    process_group: bool,
+    readiness_probe: Option<ProbeConfig>,
+    liveness_probe: Option<ProbeConfig>,
+    probing: bool,
+    probe_failures: u32,
+    /// command pipe is closed by exiting worker
+    exiting: bool,
+    /// signal that is sent after pending commands are flushed
+    pending_signal: Rc<Cell<Option<Signal>>>,
+    received: BTreeMap<&'static str, u64>,
+    sent: BTreeMap<&'static str, u64>,
+    framed: actix::io::FramedWrite<CommandPipe, TransportCodec>,
 }
 
 impl Actor for Process {
     type Context = Context<Self>;
 
     fn stopping(&mut self, ctx: &mut Context<Self>) -> Running {
-        self.kill(ctx, false);
+        match self.state {
+            // stopped worker is killed by `StoppedTimeout` if needed
+            ProcessState::Stopped => (),
+            _ => self.kill(ctx, false),
+        }
         Running::Stop
     }
 }
 
 impl StreamHandler<ProcessMessage, io::Error> for Process {
     fn handle(&mut self, msg: ProcessMessage, ctx: &mut Self::Context) {
+        if let ProcessMessage::Message(ref msg) = msg {
+            *self.received.entry(msg.name()).or_insert(0) += 1;
+        }
         ctx.notify(msg);
     }
 
+    fn error(&mut self, err: io::Error, _: &mut Context<Self>) -> Running {
+        error!("Worker communication error (pid:{}): {}", self.pid, err);
+        if err.kind() == io::ErrorKind::InvalidData {
+            self.state = ProcessState::Failed;
+            self.addr.do_send(service::ProcessExited(
+                self.pid,
+                ProcessError::ProtocolDesync(format!("{}", err)),
+            ));
+        }
+        Running::Stop
+    }
+
     fn finished(&mut self, ctx: &mut Context<Self>) {
+        // pipe closed while worker is expected to be alive, notify service
+        // right away instead of waiting for reaper. service ignores
+        // exit notifications for pids that are not current anymore, so
+        // later report from reaper for same pid does not get counted twice.
+        if let Some(timer) = self.startup_timer.take() {
+            ctx.cancel_future(timer);
+        }
+        match self.state {
+            ProcessState::Preparing => {
+                // exit status of worker that crashed during application
+                // initialization is reported by reaper
+                self.state = ProcessState::Failed;
+                match init_pipe_closed(self.pid) {
+                    Some(err) => {
+                        warn!(
+                            "Worker pipe closed during initialization (pid:{})",
+                            self.pid
+                        );
+                        self.addr.do_send(service::ProcessExited(self.pid, err));
+                    }
+                    None => {
+                        warn!("Worker exited during initialization (pid:{})", self.pid)
+                    }
+                }
+            }
+            // worker that reported `stopped` gets time to exit
+            ProcessState::Stopped => return,
+            ProcessState::Starting | ProcessState::Running => {
+                warn!("Worker pipe closed unexpectedly (pid:{})", self.pid);
+                self.state = ProcessState::Failed;
+                self.addr.do_send(service::ProcessExited(
+                    self.pid,
+                    ProcessError::PipeClosed,
+                ));
+            }
+            _ => (),
+        }
         self.kill(ctx, false);
         ctx.stop();
     }
@@ -63,17 +315,24 @@ impl StreamHandler<ProcessMessage, io::Error> for Process {
 #[derive(Debug)]
 enum ProcessState {
     Starting,
+    /// worker reported `forked`, waiting for `loaded`
+    Preparing,
     Failed,
     Running,
     Stopping,
+    /// worker confirmed graceful stop, process gets killed if it does not exit
+    Stopped,
 }
 
 #[derive(PartialEq, Debug, Message)]
 pub enum ProcessMessage {
     Message(WorkerMessage),
+    HandshakeTimeout,
     StartupTimeout,
     StopTimeout,
+    StoppedTimeout,
     Heartbeat,
+    Probe,
     Kill,
 }
 
@@ -83,12 +342,14 @@ pub enum ProcessError {
     Heartbeat,
     /// Worker startup process failed, possibly application initialization failed
     FailedToStart(Option<String>),
-    /// Timeout during startup
-    StartupTimeout,
+    /// Timeout during startup, with last observed handshake phase
+    StartupTimeout(Option<String>),
+    /// Worker did not report `forked`, likely does not support protocol
+    NoHandshake,
     /// Timeout during graceful stop
     StopTimeout,
-    /// Worker configuratin error
-    ConfigError(String),
+    /// Worker configuratin error, with optional error code
+    ConfigError(Option<u32>, String),
     /// Worker init failed
     InitFailed,
     /// Worker boot failed
@@ -97,12 +358,18 @@ pub enum ProcessError {
     Signal(usize),
     /// Worker exited with code
     ExitCode(i8),
+    /// Communication pipe closed unexpectedly
+    PipeClosed,
+    /// Invalid data on communication pipe
+    ProtocolDesync(String),
+    /// Liveness probe failed
+    ProbeFailed(String),
 }
 
 impl ProcessError {
     pub fn from(code: i8) -> ProcessError {
         match code as i32 {
-            WORKER_TIMEOUT => ProcessError::StartupTimeout,
+            WORKER_TIMEOUT => ProcessError::StartupTimeout(None),
             WORKER_INIT_FAILED => ProcessError::InitFailed,
             WORKER_BOOT_FAILED => ProcessError::BootFailed,
             code => ProcessError::ExitCode(code as i8),
@@ -114,20 +381,22 @@ impl<'a> std::convert::From<&'a ProcessError> for Reason {
     fn from(ob: &'a ProcessError) -> Self {
         match *ob {
             ProcessError::Heartbeat => Reason::HeartbeatFailed,
-            ProcessError::FailedToStart(ref err) => {
-                Reason::FailedToStart(if let &Some(ref e) = err {
-                    Some(format!("{}", e))
-                } else {
-                    None
-                })
+            ProcessError::FailedToStart(ref err) => Reason::FailedToStart(err.clone()),
+            ProcessError::StartupTimeout(ref phase) => {
+                Reason::StartupTimeout(phase.clone())
             }
-            ProcessError::StartupTimeout => Reason::StartupTimeout,
+            ProcessError::NoHandshake => Reason::NoHandshake,
             ProcessError::StopTimeout => Reason::StopTimeout,
-            ProcessError::ConfigError(ref err) => Reason::WorkerError(err.clone()),
+            ProcessError::ConfigError(code, ref err) => {
+                Reason::WorkerError(code, err.clone())
+            }
             ProcessError::InitFailed => Reason::InitFailed,
             ProcessError::BootFailed => Reason::BootFailed,
             ProcessError::Signal(sig) => Reason::Signal(sig),
             ProcessError::ExitCode(code) => Reason::ExitCode(code),
+            ProcessError::PipeClosed => Reason::PipeClosed,
+            ProcessError::ProtocolDesync(ref err) => Reason::ProtocolDesync(err.clone()),
+            ProcessError::ProbeFailed(ref err) => Reason::ProbeFailed(err.clone()),
         }
     }
 }
@@ -136,6 +405,18 @@ impl Process {
     pub fn start(
         idx: usize, cfg: &ServiceConfig, addr: Addr<FeService>,
     ) -> (Pid, Option<Addr<Process>>) {
+        // do not fork worker if we can not manage it, i.e. during shutdown
+        if !utils::arbiter_available() {
+            error!("Can not start worker, arbiter is not available");
+            let pid = Pid::from_raw(-1);
+            addr.do_send(service::ProcessFailed(
+                idx,
+                pid,
+                ProcessError::FailedToStart(Some("Arbiter is not available".to_owned())),
+            ));
+            return (pid, None);
+        }
+
         // fork process and esteblish communication
         let (pid, pipe) = match Process::fork(idx, cfg) {
             Ok(res) => res,
@@ -151,48 +432,123 @@ impl Process {
             }
         };
 
+        (pid, Some(Process::manage(idx, cfg, pid, pipe, addr)))
+    }
+
+    /// start process actor for forked worker
+    fn manage(
+        idx: usize, cfg: &ServiceConfig, pid: Pid, pipe: PipeFile,
+        addr: Addr<FeService>,
+    ) -> Addr<Process> {
         let timeout = Duration::new(u64::from(cfg.timeout), 0);
         let startup_timeout = u64::from(cfg.startup_timeout);
+        let handshake_timeout = match cfg.handshake_timeout {
+            0 => startup_timeout,
+            timeout => std::cmp::min(u64::from(timeout), startup_timeout),
+        };
         let shutdown_timeout = u64::from(cfg.shutdown_timeout);
+        let force_kill_signal = cfg.force_kill_signal;
+        let suspend_heartbeat = cfg.suspend_heartbeat_on_stop;
+        let process_group = cfg.signal_group();
+        let readiness_probe = cfg.readiness_probe.clone();
+        let liveness_probe = cfg.liveness_probe.clone();
+        let service = cfg.name.clone();
+        let desync = cfg.protocol_desync;
 
-        // start Process service
-        let addr = Process::create(move |ctx| {
+        Process::create(move |ctx| {
             let (r, w) = pipe.split();
-            ctx.add_stream(FramedRead::new(r, TransportCodec));
+            ctx.add_stream(FramedRead::new(r, TransportCodec::new(pid, desync)));
             ctx.notify_later(
+                ProcessMessage::HandshakeTimeout,
+                Duration::new(handshake_timeout, 0),
+            );
+            let startup_timer = ctx.notify_later(
                 ProcessMessage::StartupTimeout,
-                Duration::new(startup_timeout as u64, 0),
+                Duration::new(startup_timeout, 0),
             );
+            let pending_signal = Rc::new(Cell::new(None));
+            let w = CommandPipe {
+                io: w,
+                pid,
+                process_group,
+                signal: pending_signal.clone(),
+            };
             Process {
                 idx,
+                service,
                 pid,
                 addr,
                 timeout,
                 startup_timeout,
+                handshake_timeout,
                 shutdown_timeout,
+                force_kill_signal,
+                suspend_heartbeat,
+                suspended: false,
+                process_group,
+                readiness_probe,
+                liveness_probe,
+                probing: false,
+                probe_failures: 0,
+                exiting: false,
+                pending_signal,
+                received: BTreeMap::new(),
+                sent: BTreeMap::new(),
                 state: ProcessState::Starting,
                 hb: Instant::now(),
-                framed: actix::io::FramedWrite::new(w, TransportCodec, ctx),
+                startup_timer: Some(startup_timer),
+                stop_timer: None,
+                stop_confirmed: false,
+                framed: actix::io::FramedWrite::new(
+                    w,
+                    TransportCodec::new(pid, desync),
+                    ctx,
+                ),
             }
-        });
-        (pid, Some(addr))
+        })
     }
 
     fn fork(idx: usize, cfg: &ServiceConfig) -> Result<(Pid, PipeFile), io::Error> {
-        let (p_read, p_write, ch_read, ch_write) = Process::create_pipes()?;
+        // scratch dir is created before fork, so worker can use it right away
+        let scratch = match cfg.scratch_dir {
+            Some(ref parent) => Some(create_scratch_dir(parent, idx, cfg)?),
+            None => None,
+        };
+        let (p_read, p_write, ch_read, ch_write) = match Process::create_pipes() {
+            Ok(pipes) => pipes,
+            Err(err) => {
+                if let Some(ref dir) = scratch {
+                    remove_scratch_dir(dir);
+                }
+                return Err(err);
+            }
+        };
 
         // fork
         let pid = match fork() {
-            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Parent { child }) => {
+                let leader = cfg.signal_group();
+                FORKED.with(|pids| pids.borrow_mut().insert(child, leader));
+                if let Some(dir) = scratch {
+                    SCRATCH.with(|dirs| dirs.borrow_mut().insert(child, dir));
+                }
+                child
+            }
             Ok(ForkResult::Child) => {
                 let _ = close(p_write);
                 let _ = close(ch_read);
-                exec_worker(idx, cfg, p_read, ch_write);
+                exec_worker(idx, cfg, scratch.as_ref(), p_read, ch_write);
                 unreachable!();
             }
             Err(err) => {
-                error!("Fork failed: {}", err.description());
-                return Err(io::Error::new(io::ErrorKind::Other, err.description()));
+                error!("Fork failed: {}", err);
+                for fd in &[p_read, p_write, ch_read, ch_write] {
+                    let _ = close(*fd);
+                }
+                if let Some(ref dir) = scratch {
+                    remove_scratch_dir(dir);
+                }
+                return Err(io::Error::other(err.to_string()));
             }
         };
 
@@ -210,30 +566,115 @@ impl Process {
             Ok((r, w)) => (r, w),
             Err(err) => {
                 error!("Can not create pipe: {}", err);
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Can not create pipe: {}", err),
-                ));
+                return Err(io::Error::other(format!("Can not create pipe: {}", err)));
             }
         };
         let (ch_read, ch_write) = match pipe() {
             Ok((r, w)) => (r, w),
             Err(err) => {
                 error!("Can not create pipe: {}", err);
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Can not create pipe: {}", err),
-                ));
+                let _ = close(p_read);
+                let _ = close(p_write);
+                return Err(io::Error::other(format!("Can not create pipe: {}", err)));
             }
         };
         Ok((p_read, p_write, ch_read, ch_write))
     }
 
+    /// send command to worker process
+    fn send(&mut self, cmd: WorkerCommand) {
+        *self.sent.entry(cmd.name()).or_insert(0) += 1;
+        self.framed.write(cmd);
+    }
+
+    /// worker is loaded and passed readiness probe
+    fn ready(&mut self, ctx: &mut Context<Self>) {
+        debug!("Worker loaded (pid:{})", self.pid);
+        self.addr.do_send(service::ProcessLoaded(self.idx, self.pid));
+
+        // start heartbeat timer
+        self.state = ProcessState::Running;
+        self.hb = Instant::now();
+        ctx.notify_later(ProcessMessage::Heartbeat, Duration::new(HEARTBEAT, 0));
+
+        self.probe_failures = 0;
+        if let Some(ref probe) = self.liveness_probe {
+            let delay = Duration::new(u64::from(probe.initial_delay), 0);
+            ctx.notify_later(ProcessMessage::Probe, delay);
+        }
+    }
+
+    /// probe for current state, readiness probe before worker is ready
+    fn probe(&self) -> Option<&ProbeConfig> {
+        match self.state {
+            ProcessState::Starting | ProcessState::Preparing => {
+                self.readiness_probe.as_ref()
+            }
+            ProcessState::Running => self.liveness_probe.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// handle probe result and schedule next check
+    fn probed(&mut self, res: Result<(), String>, ctx: &mut Context<Self>) {
+        let running = matches!(self.state, ProcessState::Running);
+        let (interval, threshold) = match self.probe() {
+            Some(probe) => (probe.interval, probe.failure_threshold),
+            None => return,
+        };
+
+        match res {
+            Ok(_) => {
+                self.probe_failures = 0;
+                if !running {
+                    self.ready(ctx);
+                    return;
+                }
+            }
+            Err(err) => {
+                self.probe_failures += 1;
+                warn!("Worker probe failed (pid:{}): {}", self.pid, err);
+                if self.probe_failures >= threshold {
+                    let err = if running {
+                        error!("Worker liveness probe failed (pid:{})", self.pid);
+                        ProcessError::ProbeFailed(err)
+                    } else {
+                        error!("Worker readiness probe failed (pid:{})", self.pid);
+                        ProcessError::FailedToStart(Some(format!(
+                            "Readiness probe failed: {}",
+                            err
+                        )))
+                    };
+                    self.addr
+                        .do_send(service::ProcessFailed(self.idx, self.pid, err));
+                    return;
+                }
+            }
+        }
+        ctx.notify_later(ProcessMessage::Probe, Duration::new(u64::from(interval), 0));
+    }
+
+    /// send signal to worker process, or to whole worker process group
+    fn signal(&self, sig: Signal) -> nix::Result<()> {
+        signal(self.pid, self.process_group, sig)
+    }
+
+    /// (re)start graceful stop timeout
+    fn stop_timeout(&mut self, ctx: &mut Context<Self>) {
+        if let Some(timer) = self.stop_timer.take() {
+            ctx.cancel_future(timer);
+        }
+        self.stop_timer = Some(ctx.notify_later(
+            ProcessMessage::StopTimeout,
+            Duration::new(self.shutdown_timeout, 0),
+        ));
+    }
+
     fn kill(&self, ctx: &mut Context<Self>, graceful: bool) {
         if graceful {
             ctx.notify_later(ProcessMessage::Kill, Duration::new(1, 0));
         } else {
-            let _ = kill(self.pid, Signal::SIGKILL);
+            let _ = self.signal(self.force_kill_signal);
             ctx.terminate();
         }
     }
@@ -241,11 +682,44 @@ impl Process {
 
 impl Drop for Process {
     fn drop(&mut self) {
-        let _ = kill(self.pid, Signal::SIGKILL);
+        let _ = self.signal(Signal::SIGKILL);
     }
 }
 
-impl actix::io::WriteHandler<io::Error> for Process {}
+impl actix::io::WriteHandler<io::Error> for Process {
+    fn error(&mut self, err: io::Error, _: &mut Self::Context) -> Running {
+        // worker exits while command is in flight, reader or reaper
+        // reports exit, so process does not need to be killed
+        self.exiting = match self.state {
+            ProcessState::Stopping | ProcessState::Stopped | ProcessState::Failed => {
+                true
+            }
+            _ => err.kind() == io::ErrorKind::BrokenPipe,
+        };
+        if self.exiting {
+            debug!("Can not send command to exiting worker (pid:{}): {}", self.pid, err);
+        } else {
+            error!("Worker communication error (pid:{}): {}", self.pid, err);
+        }
+        Running::Stop
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        match self.state {
+            // command channel is closed after `stop` command got flushed
+            // or failed, worker can be signaled now
+            // command could not be flushed, worker can be signaled now
+            ProcessState::Stopping => if let Some(sig) = self.pending_signal.take() {
+                let _ = self.signal(sig);
+            },
+            // worker that reported `stopped` gets time to exit
+            ProcessState::Stopped => (),
+            _ => if !self.exiting {
+                ctx.stop()
+            },
+        }
+    }
+}
 
 impl Handler<ProcessMessage> for Process {
     type Result = ();
@@ -255,22 +729,40 @@ impl Handler<ProcessMessage> for Process {
             ProcessMessage::Message(msg) => match msg {
                 WorkerMessage::forked => {
                     debug!("Worker forked (pid:{})", self.pid);
-                    self.framed.write(WorkerCommand::prepare);
+                    if let ProcessState::Starting = self.state {
+                        self.state = ProcessState::Preparing;
+                        self.addr.do_send(service::ProcessMessage(
+                            self.idx,
+                            self.pid,
+                            WorkerMessage::forked,
+                        ));
+                    }
+                    self.send(WorkerCommand::prepare {
+                        epoch: epoch(),
+                        idx: self.idx,
+                        peers: peers(&self.service),
+                    });
                 }
                 WorkerMessage::loaded => {
                     match self.state {
-                        ProcessState::Starting => {
-                            debug!("Worker loaded (pid:{})", self.pid);
-                            self.addr
-                                .do_send(service::ProcessLoaded(self.idx, self.pid));
-
-                            // start heartbeat timer
-                            self.state = ProcessState::Running;
-                            self.hb = Instant::now();
-                            ctx.notify_later(
-                                ProcessMessage::Heartbeat,
-                                Duration::new(HEARTBEAT, 0),
-                            );
+                        ProcessState::Starting | ProcessState::Preparing => {
+                            // worker is ready after readiness probe passes
+                            let delay = self
+                                .readiness_probe
+                                .as_ref()
+                                .map(|probe| u64::from(probe.initial_delay));
+                            match delay {
+                                Some(_) if self.probing => (),
+                                Some(delay) => {
+                                    debug!("Worker loaded, probing (pid:{})", self.pid);
+                                    self.probing = true;
+                                    ctx.notify_later(
+                                        ProcessMessage::Probe,
+                                        Duration::new(delay, 0),
+                                    );
+                                }
+                                None => self.ready(ctx),
+                            }
                         }
                         _ => {
                             warn!(
@@ -301,28 +793,96 @@ impl Handler<ProcessMessage> for Process {
                         WorkerMessage::restart,
                     ));
                 }
-                WorkerMessage::cfgerror(msg) => {
-                    error!("Worker config error: {} (pid:{})", msg, self.pid);
+                WorkerMessage::paused => {
+                    debug!("Worker paused (pid:{})", self.pid);
+                    self.addr.do_send(service::ProcessMessage(
+                        self.idx,
+                        self.pid,
+                        WorkerMessage::paused,
+                    ));
+                }
+                WorkerMessage::resumed => {
+                    debug!("Worker resumed (pid:{})", self.pid);
+                    self.addr.do_send(service::ProcessMessage(
+                        self.idx,
+                        self.pid,
+                        WorkerMessage::resumed,
+                    ));
+                }
+                msg @ WorkerMessage::ack { .. } => {
+                    self.addr
+                        .do_send(service::ProcessMessage(self.idx, self.pid, msg));
+                }
+                WorkerMessage::stopping => {
+                    // shutdown timeout counts from confirmation
+                    if let ProcessState::Stopping = self.state {
+                        if !self.stop_confirmed {
+                            info!("Worker confirmed stop (pid:{})", self.pid);
+                            self.stop_confirmed = true;
+                            self.stop_timeout(ctx);
+                        }
+                    }
+                }
+                WorkerMessage::stopped => {
+                    // worker is done, reaper reports exit status
+                    if let ProcessState::Stopping = self.state {
+                        info!("Worker finished graceful stop (pid:{})", self.pid);
+                        self.state = ProcessState::Stopped;
+                        if let Some(timer) = self.stop_timer.take() {
+                            ctx.cancel_future(timer);
+                        }
+                        ctx.notify_later(
+                            ProcessMessage::StoppedTimeout,
+                            Duration::new(STOPPED_GRACE, 0),
+                        );
+                    }
+                }
+                WorkerMessage::cfgerror(CfgError { code, message }) => {
+                    error!(
+                        "Worker config error: {} code: {:?} (pid:{})",
+                        message, code, self.pid
+                    );
                     self.addr.do_send(service::ProcessFailed(
                         self.idx,
                         self.pid,
-                        ProcessError::ConfigError(msg),
+                        ProcessError::ConfigError(code, message),
                     ));
                 }
             },
-            ProcessMessage::StartupTimeout => {
+            ProcessMessage::HandshakeTimeout => {
                 if let ProcessState::Starting = self.state {
-                    error!("Worker startup timeout after {} secs", self.startup_timeout);
+                    error!(
+                        "Worker did not report forked in {} secs, command likely \
+                         does not support fectl protocol (pid:{})",
+                        self.handshake_timeout, self.pid
+                    );
                     self.addr.do_send(service::ProcessFailed(
                         self.idx,
                         self.pid,
-                        ProcessError::StartupTimeout,
+                        ProcessError::NoHandshake,
                     ));
 
                     self.state = ProcessState::Failed;
-                    let _ = kill(self.pid, Signal::SIGKILL);
+                    let _ = self.signal(self.force_kill_signal);
+                    ctx.stop();
+                }
+            }
+            ProcessMessage::StartupTimeout => {
+                if let ProcessState::Preparing = self.state {
+                    let phase = "worker forked, application did not load";
+                    error!(
+                        "Worker startup timeout after {} secs, {} (pid:{})",
+                        self.startup_timeout, phase, self.pid
+                    );
+                    self.addr.do_send(service::ProcessFailed(
+                        self.idx,
+                        self.pid,
+                        ProcessError::StartupTimeout(Some(phase.to_owned())),
+                    ));
+
+                    self.state = ProcessState::Failed;
+                    let _ = self.signal(self.force_kill_signal);
                     ctx.stop();
-                    return;
                 }
             }
             ProcessMessage::StopTimeout => {
@@ -338,15 +898,29 @@ impl Handler<ProcessMessage> for Process {
                     ));
 
                     self.state = ProcessState::Failed;
-                    let _ = kill(self.pid, Signal::SIGKILL);
+                    let _ = self.signal(self.force_kill_signal);
+                    ctx.stop();
+                }
+            }
+            ProcessMessage::StoppedTimeout => {
+                if let ProcessState::Stopped = self.state {
+                    if is_alive(self.pid) {
+                        warn!("Worker did not exit after stop (pid:{})", self.pid);
+                        let _ = self.signal(Signal::SIGKILL);
+                    }
                     ctx.stop();
-                    return;
                 }
             }
             ProcessMessage::Heartbeat => {
                 // makes sense only in running state
                 if let ProcessState::Running = self.state {
-                    if Instant::now().duration_since(self.hb) > self.timeout {
+                    if self.suspended {
+                        // worker is stopped (i.e. by debugger), skip check
+                        ctx.notify_later(
+                            ProcessMessage::Heartbeat,
+                            Duration::new(HEARTBEAT, 0),
+                        );
+                    } else if Instant::now().duration_since(self.hb) > self.timeout {
                         // heartbeat timed out
                         error!(
                             "Worker heartbeat failed (pid:{}) after {:?} secs",
@@ -359,7 +933,7 @@ impl Handler<ProcessMessage> for Process {
                         ));
                     } else {
                         // send heartbeat to worker process and reset hearbeat timer
-                        self.framed.write(WorkerCommand::hb);
+                        self.send(WorkerCommand::hb);
                         ctx.notify_later(
                             ProcessMessage::Heartbeat,
                             Duration::new(HEARTBEAT, 0),
@@ -367,10 +941,19 @@ impl Handler<ProcessMessage> for Process {
                     }
                 }
             }
+            ProcessMessage::Probe => {
+                if let Some(probe) = self.probe().map(probe::check) {
+                    probe
+                        .into_actor(self)
+                        .then(|res, act, ctx| {
+                            act.probed(res, ctx);
+                            actix::fut::ok(())
+                        }).spawn(ctx);
+                }
+            }
             ProcessMessage::Kill => {
-                let _ = kill(self.pid, Signal::SIGKILL);
+                let _ = self.signal(self.force_kill_signal);
                 ctx.stop();
-                return;
             }
         }
     }
@@ -383,7 +966,83 @@ impl Handler<SendCommand> for Process {
     type Result = ();
 
     fn handle(&mut self, msg: SendCommand, _: &mut Context<Process>) {
-        self.framed.write(msg.0);
+        self.send(msg.0);
+    }
+}
+
+/// Send changed running workers of service, process that did not get
+/// `prepare` command yet gets them with it
+#[derive(Message)]
+pub struct UpdatePeers(pub Vec<Peer>);
+
+impl Handler<UpdatePeers> for Process {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdatePeers, _: &mut Context<Process>) {
+        match self.state {
+            ProcessState::Preparing | ProcessState::Running => {
+                self.send(WorkerCommand::peers { peers: msg.0 })
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Send signal to worker process, or to process group
+#[derive(Message)]
+pub struct SignalProcess(pub Signal);
+
+impl Handler<SignalProcess> for Process {
+    type Result = ();
+
+    fn handle(&mut self, msg: SignalProcess, _: &mut Context<Process>) {
+        if let Err(err) = self.signal(msg.0) {
+            warn!("Can not send {:?} to worker (pid:{}): {}", msg.0, self.pid, err);
+        }
+    }
+}
+
+#[derive(Message)]
+pub struct SuspendProcess(pub bool);
+
+impl Handler<SuspendProcess> for Process {
+    type Result = ();
+
+    fn handle(&mut self, msg: SuspendProcess, _: &mut Context<Process>) {
+        if self.suspend_heartbeat {
+            self.suspended = msg.0;
+            self.hb = Instant::now();
+        }
+    }
+}
+
+/// Liveness check, actor just has to process message
+#[derive(Message)]
+pub struct SelfCheck;
+
+impl Handler<SelfCheck> for Process {
+    type Result = ();
+
+    fn handle(&mut self, _: SelfCheck, _: &mut Context<Process>) {}
+}
+
+/// Counters of received worker messages and sent commands
+pub struct Counters;
+
+impl Message for Counters {
+    type Result = (Vec<(String, u64)>, Vec<(String, u64)>);
+}
+
+impl Handler<Counters> for Process {
+    type Result = MessageResult<Counters>;
+
+    fn handle(&mut self, _: Counters, _: &mut Context<Process>) -> Self::Result {
+        let counters = |map: &BTreeMap<&'static str, u64>| {
+            map.iter()
+                .map(|(name, count)| ((*name).to_owned(), *count))
+                .collect()
+        };
+        MessageResult((counters(&self.received), counters(&self.sent)))
     }
 }
 
@@ -394,7 +1053,7 @@ impl Handler<StartProcess> for Process {
     type Result = ();
 
     fn handle(&mut self, _: StartProcess, _: &mut Context<Process>) {
-        self.framed.write(WorkerCommand::start);
+        self.send(WorkerCommand::start);
     }
 }
 
@@ -405,7 +1064,7 @@ impl Handler<PauseProcess> for Process {
     type Result = ();
 
     fn handle(&mut self, _: PauseProcess, _: &mut Context<Process>) {
-        self.framed.write(WorkerCommand::pause);
+        self.send(WorkerCommand::pause);
     }
 }
 
@@ -416,7 +1075,7 @@ impl Handler<ResumeProcess> for Process {
     type Result = ();
 
     fn handle(&mut self, _: ResumeProcess, _: &mut Context<Process>) {
-        self.framed.write(WorkerCommand::resume);
+        self.send(WorkerCommand::resume);
     }
 }
 
@@ -432,15 +1091,19 @@ impl Handler<StopProcess> for Process {
             ProcessState::Running => {
                 self.state = ProcessState::Stopping;
 
-                self.framed.write(WorkerCommand::stop);
-                ctx.notify_later(
-                    ProcessMessage::StopTimeout,
-                    Duration::new(self.shutdown_timeout, 0),
-                );
-                let _ = kill(self.pid, Signal::SIGTERM);
+                // `SIGTERM` is sent after `stop` command is flushed to pipe,
+                // otherwise worker can see signal before command on busy
+                // pipe. pipe stays open for later commands, see `CommandPipe`
+                self.send(WorkerCommand::stop);
+                if self.exiting {
+                    let _ = self.signal(Signal::SIGTERM);
+                } else {
+                    self.pending_signal.set(Some(Signal::SIGTERM));
+                }
+                self.stop_timeout(ctx);
             }
             _ => {
-                let _ = kill(self.pid, Signal::SIGQUIT);
+                let _ = self.signal(Signal::SIGQUIT);
                 ctx.terminate();
             }
         }
@@ -455,38 +1118,92 @@ impl Handler<QuitProcess> for Process {
 
     fn handle(&mut self, msg: QuitProcess, ctx: &mut Context<Process>) {
         if msg.0 {
-            let _ = kill(self.pid, Signal::SIGQUIT);
+            let _ = self.signal(Signal::SIGQUIT);
             self.kill(ctx, true);
         } else {
             self.kill(ctx, false);
-            let _ = kill(self.pid, Signal::SIGKILL);
+            let _ = self.signal(self.force_kill_signal);
             ctx.terminate();
         }
     }
 }
 
-pub struct TransportCodec;
+pub struct TransportCodec {
+    pid: Pid,
+    mode: ProtocolDesync,
+}
+
+impl TransportCodec {
+    pub fn new(pid: Pid, mode: ProtocolDesync) -> TransportCodec {
+        TransportCodec { pid, mode }
+    }
+
+    // invalid data fails stream in `kill` mode, in `resync` mode
+    // bytes are skipped up to next possible message start
+    fn desync(&self, src: &mut BytesMut, err: &str) -> Result<(), io::Error> {
+        let len = std::cmp::min(src.len(), DESYNC_LOG_BYTES);
+        let bytes = String::from_utf8_lossy(&src[..len]).into_owned();
+
+        match self.mode {
+            ProtocolDesync::kill => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("protocol desync, {}: {:?}", err, bytes),
+            )),
+            ProtocolDesync::resync => {
+                // message is json object, length prefix precedes it
+                let skip = src
+                    .iter()
+                    .skip(5)
+                    .position(|b| *b == b'{')
+                    .map(|pos| pos + 1)
+                    .unwrap_or_else(|| std::cmp::max(src.len(), 5) - 4);
+                warn!(
+                    "Worker protocol desync (pid:{}), {}, skipping {} bytes: {:?}",
+                    self.pid, err, skip, bytes
+                );
+                src.split_to(skip);
+                Ok(())
+            }
+        }
+    }
+}
 
 impl Decoder for TransportCodec {
     type Item = ProcessMessage;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let size = {
-            if src.len() < 2 {
+        loop {
+            if src.len() < 4 {
                 return Ok(None);
             }
-            BigEndian::read_u16(src.as_ref()) as usize
-        };
+            let size = BigEndian::read_u32(src.as_ref()) as usize;
 
-        if src.len() >= size + 2 {
-            src.split_to(2);
-            let buf = src.split_to(size);
-            Ok(Some(ProcessMessage::Message(json::from_slice::<
-                WorkerMessage,
-            >(&buf)?)))
-        } else {
-            Ok(None)
+            // every message is json object
+            if src.len() > 4 && src[4] != b'{' {
+                self.desync(src, "invalid message start")?;
+                continue;
+            }
+
+            // desynced stream usually looks like oversized message
+            if size > MAX_FRAME {
+                let err = format!("message length {} exceeds {} bytes", size, MAX_FRAME);
+                self.desync(src, &err)?;
+                continue;
+            }
+
+            // length is checked by parsing complete message
+            if src.len() < size + 4 {
+                return Ok(None);
+            }
+
+            match json::from_slice::<WorkerMessage>(&src[4..size + 4]) {
+                Ok(msg) => {
+                    src.split_to(size + 4);
+                    return Ok(Some(ProcessMessage::Message(msg)));
+                }
+                Err(err) => self.desync(src, &format!("{}", err))?,
+            }
         }
     }
 }
@@ -500,11 +1217,302 @@ impl Encoder for TransportCodec {
     ) -> Result<(), Self::Error> {
         let msg = json::to_string(&msg).unwrap();
         let msg_ref: &[u8] = msg.as_ref();
+        if msg_ref.len() > MAX_FRAME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("command length {} exceeds {} bytes", msg_ref.len(), MAX_FRAME),
+            ));
+        }
 
-        dst.reserve(msg_ref.len() + 2);
-        dst.put_u16_be(msg_ref.len() as u16);
+        dst.reserve(msg_ref.len() + 4);
+        dst.put_u32_be(msg_ref.len() as u32);
         dst.put(msg_ref);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::process::ExitStatusExt;
+    use actix::SystemRunner;
+    use futures::Future;
+    use std::process::{Child, ExitStatus};
+    use tokio::timer::Delay;
+
+    fn codec(mode: ProtocolDesync) -> TransportCodec {
+        TransportCodec::new(Pid::from_raw(1), mode)
+    }
+
+    fn frame(msg: &WorkerMessage) -> BytesMut {
+        raw_frame(&json::to_vec(msg).unwrap())
+    }
+
+    fn raw_frame(msg: &[u8]) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(msg.len() + 4);
+        buf.put_u32_be(msg.len() as u32);
+        buf.put(msg);
+        buf
+    }
+
+    #[test]
+    fn test_large_frame_round_trip() {
+        let mut codec = codec(ProtocolDesync::kill);
+        let payload = "x".repeat(100 * 1024);
+
+        let cmd = WorkerCommand::custom {
+            id: 1,
+            name: "test".to_owned(),
+            data: json::Value::String(payload.clone()),
+        };
+        let mut buf = BytesMut::new();
+        codec.encode(cmd.clone(), &mut buf).unwrap();
+        let size = BigEndian::read_u32(buf.as_ref()) as usize;
+        assert!(size > 100 * 1024);
+        assert_eq!(size + 4, buf.len());
+        assert_eq!(json::from_slice::<WorkerCommand>(&buf[4..]).unwrap(), cmd);
+
+        // message arrives in parts
+        let mut rest = frame(&WorkerMessage::cfg_error(payload.clone()));
+        let mut buf = rest.split_to(50 * 1024);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&rest);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ProcessMessage::Message(WorkerMessage::cfg_error(payload)))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_cfgerror_message() {
+        let mut codec = codec(ProtocolDesync::kill);
+
+        let msg = br#"{"cmd": "cfgerror", "data": {"code": 78, "message": "no db"}}"#;
+        let mut buf = raw_frame(msg);
+        let err = WorkerMessage::cfgerror(CfgError {
+            code: Some(78),
+            message: "no db".to_owned(),
+        });
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ProcessMessage::Message(err))
+        );
+
+        // workers without error codes send message string
+        let mut buf = raw_frame(br#"{"cmd": "cfgerror", "data": "no db"}"#);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ProcessMessage::Message(WorkerMessage::cfg_error("no db")))
+        );
+    }
+
+    #[test]
+    fn test_partial_frame_with_trailing_whitespace() {
+        let mut codec = codec(ProtocolDesync::kill);
+
+        // object is complete before message length, rest arrives later
+        let mut rest = raw_frame(b"{\"cmd\": \"hb\"}  \n");
+        let mut buf = rest.split_to(rest.len() - 3);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&rest);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ProcessMessage::Message(WorkerMessage::hb))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_oversized_frame() {
+        let mut buf = BytesMut::new();
+        buf.put_u32_be(MAX_FRAME as u32 + 1);
+        buf.put(&b"{}"[..]);
+        assert!(codec(ProtocolDesync::kill).decode(&mut buf).is_err());
+
+        // resync mode skips bogus length
+        let mut buf = BytesMut::new();
+        buf.put_u32_be(MAX_FRAME as u32 + 1);
+        buf.put(&b"{}"[..]);
+        buf.extend_from_slice(&frame(&WorkerMessage::hb));
+        assert_eq!(
+            codec(ProtocolDesync::resync).decode(&mut buf).unwrap(),
+            Some(ProcessMessage::Message(WorkerMessage::hb))
+        );
+    }
+
+    // worker closes pipe right after `forked`
+    fn spawn_worker(script: &str) -> (std::process::Child, Pid) {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", script])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut out = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "forked");
+        let pid = Pid::from_raw(child.id() as i32);
+        (child, pid)
+    }
+
+    #[test]
+    fn test_pipe_closed_before_loaded() {
+        let (mut child, pid) = spawn_worker("printf forked; exec 1>&-; sleep 5");
+
+        // reported right away, without waiting for startup timeout
+        match init_pipe_closed(pid) {
+            Some(ProcessError::FailedToStart(Some(ref reason))) => {
+                assert_eq!(reason, "Pipe closed during initialization")
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_start_without_arbiter() {
+        let cfg = r#"{"name": "test", "num": 1, "command": "true"}"#;
+        let cfg = config::parse_service(json::from_str(cfg).unwrap()).unwrap();
+        let addr = FeService::start(0, cfg.clone());
+
+        // worker is not forked, failure is reported to service
+        let (pid, process) = Process::start(0, &cfg, addr);
+        assert_eq!(pid, Pid::from_raw(-1));
+        assert!(process.is_none());
+    }
+
+    #[test]
+    fn test_exited_before_loaded() {
+        let (mut child, pid) = spawn_worker("printf forked; exit 3");
+        let start = Instant::now();
+        while !has_exited(pid) {
+            assert!(start.elapsed() < Duration::new(5, 0));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // exit status is left for reaper
+        assert!(init_pipe_closed(pid).is_none());
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+    }
+
+    // worker process with running process actor, returned files are
+    // read end of command pipe and write end of message pipe,
+    // actor sees open pipe while they are alive
+    fn running_worker(cfg: &str, script: &str) -> (Child, Addr<Process>, File, File) {
+        let cfg = config::parse_service(json::from_str(cfg).unwrap()).unwrap();
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", &format!("{}; printf ready; exec sleep 30", script)])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut ready = [0; 5];
+        child.stdout.take().unwrap().read_exact(&mut ready).unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+        FORKED.with(|pids| pids.borrow_mut().insert(pid, false));
+
+        let (msg_r, msg_w) = pipe().unwrap();
+        let (cmd_r, cmd_w) = pipe().unwrap();
+        // service waits for unreachable dependency and does not fork workers
+        let service = r#"{"name": "test", "num": 1, "command": "true",
+            "wait_for": [{"type": "tcp", "address": "127.0.0.1:1", "interval": 60}],
+            "wait_for_timeout": 0}"#;
+        let service = config::parse_service(json::from_str(service).unwrap()).unwrap();
+        let service = FeService::start(1, service);
+        let addr = Process::manage(0, &cfg, pid, PipeFile::new(msg_r, cmd_w), service);
+        addr.do_send(ProcessMessage::Message(WorkerMessage::loaded));
+        let files = unsafe { (File::from_raw_fd(cmd_r), File::from_raw_fd(msg_w)) };
+        (child, addr, files.0, files.1)
+    }
+
+    // run system until worker process exits and `after` is done,
+    // `None` if it does not finish in time
+    fn wait_exit<F, T>(
+        sys: SystemRunner, mut child: Child, after: F,
+    ) -> Option<(ExitStatus, T)>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pid = Pid::from_raw(child.id() as i32);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let system = System::current();
+        std::thread::spawn(move || {
+            let status = child.wait().unwrap();
+            let _ = tx.send((status, after()));
+            system.stop();
+        });
+        Arbiter::spawn(Delay::new(Instant::now() + Duration::new(10, 0)).then(|_| {
+            System::current().stop();
+            Ok(())
+        }));
+        sys.run();
+        rx.try_recv().ok().or_else(|| {
+            let _ = kill(pid, Signal::SIGKILL);
+            None
+        })
+    }
+
+    #[test]
+    fn test_stopped_worker_is_killed() {
+        let sys = System::new("test");
+        let cfg = r#"{"name": "test", "num": 1, "command": "true",
+                      "shutdown_timeout": 30, "force_kill_signal": "SIGQUIT"}"#;
+        let (child, addr, _cmds, _msgs) = running_worker(cfg, "trap '' TERM");
+        addr.do_send(StopProcess);
+        addr.do_send(ProcessMessage::Message(WorkerMessage::stopping));
+        addr.do_send(ProcessMessage::Message(WorkerMessage::stopped));
+
+        // worker that does not exit after `stopped` gets `SIGKILL`,
+        // not configured force kill signal, without waiting for timeout
+        let start = Instant::now();
+        let (status, _) = wait_exit(sys, child, || ()).expect("worker is not killed");
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        assert!(start.elapsed() < Duration::new(5, 0));
+    }
+
+    #[test]
+    fn test_stop_timeout_without_stopped() {
+        let sys = System::new("test");
+        let cfg = r#"{"name": "test", "num": 1, "command": "true",
+                      "shutdown_timeout": 1}"#;
+        let (child, addr, _cmds, _msgs) = running_worker(cfg, "trap '' TERM");
+        addr.do_send(StopProcess);
+
+        let start = Instant::now();
+        let (status, _) = wait_exit(sys, child, || ()).expect("worker is not killed");
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_stop_keeps_command_pipe_open() {
+        let sys = System::new("test");
+        let cfg = r#"{"name": "test", "num": 1, "command": "true",
+                      "shutdown_timeout": 30}"#;
+        let (child, addr, mut cmds, _msgs) = running_worker(cfg, "true");
+        addr.do_send(StopProcess);
+
+        // worker gets `SIGTERM` after `stop` command, later commands
+        // are still delivered
+        let (status, received) = wait_exit(sys, child, move || {
+            addr.do_send(SendCommand(WorkerCommand::hb));
+            let mut received = Vec::new();
+            while received.last() != Some(&"hb") {
+                let mut size = [0; 4];
+                cmds.read_exact(&mut size).unwrap();
+                let mut msg = vec![0; BigEndian::read_u32(&size) as usize];
+                cmds.read_exact(&mut msg).unwrap();
+                received.push(json::from_slice::<WorkerCommand>(&msg).unwrap().name());
+            }
+            received
+        }).expect("worker is not stopped");
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+        assert_eq!(received, vec!["stop", "hb"]);
+    }
+}
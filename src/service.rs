@@ -1,17 +1,36 @@
 #![allow(dead_code)]
 
+use libc;
+use nix::sys::signal::Signal;
 use nix::unistd::Pid;
 use std;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
 
 use actix::prelude::*;
-use actix::Response;
-use futures::Future;
-
-use config::ServiceConfig;
-use event::{Event, Reason};
-use process::ProcessError;
-use worker::{Worker, WorkerMessage};
+use actix::{MailboxError, Response};
+use futures::sync::oneshot;
+use futures::{future, Future};
+
+use cgroup;
+use cmd;
+use config::{DependencyTimeout, ServiceConfig, WorkerReload};
+use event::{
+    Diagnosis, FailureCounters, NumOverride, Reason, ServiceSnapshot, ServiceStatus,
+    State, WorkerAck, WorkerCounters, WorkerDiagnosis,
+};
+use probe;
+use process::{self, ProcessError};
+use serde_json as json;
+use utils;
+use webhook::{self, WebhookEvent};
+use worker::{Peer, Worker, WorkerCommand, WorkerMessage};
+
+/// Time in seconds for `version_cmd` to finish
+const VERSION_CMD_TIMEOUT: u32 = 10;
+
+/// Time in seconds for `on_flap_cmd` to finish, hung command is killed
+const ON_FLAP_CMD_TIMEOUT: u32 = 60;
 
 /// Service state
 enum ServiceState {
@@ -56,6 +75,22 @@ pub enum ServiceOperationError {
     Running,
     Stopped,
     Failed,
+    /// Not all workers confirmed pause or resume command
+    NotConfirmed,
+    /// Service is quarantined because of flapping, needs `reset`
+    Quarantined,
+    /// Service is disabled, needs `enable`
+    Disabled,
+    /// Service has no workers to run, needs `scale`
+    NoWorkers,
+}
+
+/// Reload progress for services with `reload_swap_delay`
+#[derive(PartialEq)]
+enum SwapState {
+    Idle,
+    Stopping,
+    Waiting,
 }
 
 #[derive(Clone, Debug)]
@@ -70,12 +105,74 @@ pub enum ReloadStatus {
     Success,
     Failed,
     Stopping,
+    /// New workers did not start during `reload_timeout`
+    Timeout,
+}
+
+/// Custom command that waits for workers results
+struct PendingAck {
+    /// worker index and process pid
+    waiting: Vec<(usize, Pid)>,
+    results: Vec<WorkerAck>,
+    tx: oneshot::Sender<Vec<WorkerAck>>,
+    timeout: SpawnHandle,
+}
+
+impl PendingAck {
+    /// resolve command, workers that did not respond are reported without result
+    fn finish(mut self, workers: &[Worker]) {
+        for (idx, pid) in self.waiting {
+            self.results
+                .push((workers[idx].name(), format!("{}", pid), None, None));
+        }
+        let _ = self.tx.send(self.results);
+    }
 }
 
 pub struct FeService {
     name: String,
+    cfg: ServiceConfig,
     state: ServiceState,
     paused: bool,
+    pause_task: Option<(actix::Condition<bool>, SpawnHandle)>,
+    swap: SwapState,
+    reload_timeout: Option<SpawnHandle>,
+    /// worker reload requests received while service is paused
+    deferred: Vec<(usize, Pid, WorkerMessage)>,
+    /// restarts of failed running workers within `flap_window`
+    flaps: VecDeque<Instant>,
+    quarantined: bool,
+    disabled: bool,
+    /// output of `version_cmd`, updated on reload
+    version: Option<String>,
+    /// custom commands waiting for workers results
+    acks: HashMap<u64, PendingAck>,
+    /// readiness requests waiting for `min_healthy` running workers
+    quorum_waiters: Vec<oneshot::Sender<bool>>,
+    next_ack: u64,
+    /// staggered worker starts that are not done yet,
+    /// generation invalidates scheduled starts
+    stagger_pending: usize,
+    stagger_gen: u64,
+    /// workers start is delayed until `wait_for` dependencies are reachable
+    waiting: bool,
+    /// last `wait_for` dependency that was not reachable
+    blocked_by: Option<String>,
+    /// modification times of `watch_files` and time of last change
+    watched: HashMap<String, Option<SystemTime>>,
+    files_changed: Option<Instant>,
+    /// mode of reload in progress and reload requested during it
+    reload_graceful: bool,
+    queued_reload: Option<(bool, Option<ServiceConfig>, actix::Condition<ReloadStatus>)>,
+    /// desired number of workers, `num` or set with `Scale` command.
+    /// Workers above desired number are stopped, but not removed.
+    desired: usize,
+    /// workers added by increase of desired number, not started yet
+    scaled_up: HashSet<usize>,
+    /// desired number set with `OverrideNum` command
+    num_override: Option<NumOverride>,
+    /// running workers, last sent to worker processes
+    peers: Vec<Peer>,
     workers: Vec<Worker>,
 }
 
@@ -87,21 +184,61 @@ impl FeService {
             for idx in 0..num as usize {
                 workers.push(Worker::new(idx, cfg.clone(), ctx.address()));
             }
-
-            FeService {
-                name: cfg.name.clone(),
-                state: ServiceState::Starting(actix::Condition::default()),
-                paused: false,
-                workers,
-            }
+            FeService::new(cfg, workers)
         })
     }
 
+    fn new(cfg: ServiceConfig, workers: Vec<Worker>) -> FeService {
+        // service with `num = 0` is defined, but disabled
+        let num = workers.len();
+        let disabled = num == 0;
+        let state = if disabled {
+            ServiceState::Stopped
+        } else {
+            ServiceState::Starting(actix::Condition::default())
+        };
+
+        FeService {
+            name: cfg.name.clone(),
+            state,
+            paused: false,
+            pause_task: None,
+            swap: SwapState::Idle,
+            reload_timeout: None,
+            deferred: Vec::new(),
+            flaps: VecDeque::new(),
+            quarantined: false,
+            disabled,
+            version: None,
+            acks: HashMap::new(),
+            quorum_waiters: Vec::new(),
+            next_ack: 0,
+            stagger_pending: 0,
+            stagger_gen: 0,
+            waiting: false,
+            blocked_by: None,
+            watched: HashMap::new(),
+            files_changed: None,
+            reload_graceful: true,
+            queued_reload: None,
+            desired: num,
+            scaled_up: HashSet::new(),
+            num_override: None,
+            peers: Vec::new(),
+            workers,
+            cfg,
+        }
+    }
+
     fn check_loading_workers(&mut self, restart_stopped: bool) -> (bool, bool) {
         let mut in_process = false;
         let mut failed = false;
 
-        for worker in &mut self.workers {
+        for (idx, worker) in self.workers.iter_mut().enumerate() {
+            // workers above desired number do not affect service state
+            if restart_stopped && idx >= self.desired {
+                continue;
+            }
             if worker.is_failed() {
                 failed = true;
             } else if worker.is_stopped() {
@@ -117,8 +254,330 @@ impl FeService {
         (failed, in_process)
     }
 
+    // check if all workers confirmed pause or resume command
+    fn pause_confirmed(&self) -> bool {
+        let paused = self.paused;
+        self.workers.iter().all(|w| w.pause_confirmed(paused))
+    }
+
+    // wait until all workers confirm pause or resume command,
+    // resolves with `false` if workers do not confirm during `timeout`
+    fn wait_pause_confirmed(
+        &mut self, ctx: &mut Context<Self>,
+    ) -> impl Future<Item = (), Error = ServiceOperationError> {
+        if let Some((task, handle)) = self.pause_task.take() {
+            ctx.cancel_future(handle);
+            task.set(false);
+        }
+
+        let mut task = actix::Condition::default();
+        let rx = task.wait();
+        if self.pause_confirmed() {
+            task.set(true);
+        } else {
+            let timeout = Duration::new(u64::from(self.cfg.timeout), 0);
+            let handle = ctx.run_later(timeout, |act, _| {
+                if let Some((task, _)) = act.pause_task.take() {
+                    warn!("Not all workers confirmed command: {:?}", act.name);
+                    for worker in &mut act.workers {
+                        worker.pause_timeout();
+                    }
+                    task.set(false);
+                }
+            });
+            self.pause_task = Some((task, handle));
+        }
+
+        rx.map_err(|_| ServiceOperationError::Failed)
+            .and_then(|confirmed| {
+                if confirmed {
+                    Ok(())
+                } else {
+                    Err(ServiceOperationError::NotConfirmed)
+                }
+            })
+    }
+
+    // resolve pause waiter if all workers confirmed command
+    fn check_pause_confirmed(&mut self, ctx: &mut Context<Self>) {
+        if self.pause_task.is_some() && self.pause_confirmed() {
+            if let Some((task, handle)) = self.pause_task.take() {
+                ctx.cancel_future(handle);
+                task.set(true);
+            }
+        }
+    }
+
+    // apply operation to all workers, with `start_stagger` delay between workers
+    fn stagger<F>(&mut self, ctx: &mut Context<Self>, op: F)
+    where
+        F: Fn(&mut Worker) + 'static,
+    {
+        self.cancel_stagger();
+        let num = std::cmp::min(self.desired, self.workers.len());
+        if self.cfg.start_stagger == 0 && self.cfg.start_stagger_jitter == 0 {
+            for worker in self.workers.iter_mut().take(num) {
+                op(worker);
+            }
+            return;
+        }
+
+        let op = std::rc::Rc::new(op);
+        let gen = self.stagger_gen;
+        let mut delay = 0;
+        for idx in 0..num {
+            if idx == 0 {
+                op(&mut self.workers[idx]);
+                continue;
+            }
+            delay += u64::from(self.cfg.start_stagger)
+                + u64::from(utils::jitter(self.cfg.start_stagger_jitter));
+            self.stagger_pending += 1;
+            let op = op.clone();
+            ctx.run_later(Duration::from_millis(delay), move |act, ctx| {
+                if act.stagger_gen == gen {
+                    act.stagger_pending -= 1;
+                    op(&mut act.workers[idx]);
+                    act.update(ctx);
+                }
+            });
+        }
+    }
+
+    // drop scheduled staggered starts and dependencies wait
+    fn cancel_stagger(&mut self) {
+        self.stagger_gen += 1;
+        self.stagger_pending = 0;
+        self.waiting = false;
+        self.blocked_by = None;
+    }
+
+    // start workers, if service has `wait_for` dependencies,
+    // workers start after all dependencies are reachable
+    fn start_workers(&mut self, ctx: &mut Context<Self>, reason: Reason) {
+        if self.cfg.wait_for.is_empty() {
+            self.stagger(ctx, move |worker| worker.start(reason.clone()));
+            return;
+        }
+        info!("Service {:?} is waiting for dependencies", self.name);
+        self.cancel_stagger();
+        self.waiting = true;
+
+        let timeout = self.cfg.wait_for_timeout;
+        let deadline = if timeout == 0 {
+            None
+        } else {
+            Some(Instant::now() + Duration::new(u64::from(timeout), 0))
+        };
+        let gen = self.stagger_gen;
+        self.wait_dependencies(ctx, gen, deadline, reason);
+    }
+
+    // check all dependencies, repeat until dependencies are reachable
+    // or `wait_for_timeout` expires
+    fn wait_dependencies(
+        &mut self, ctx: &mut Context<Self>, gen: u64, deadline: Option<Instant>,
+        reason: Reason,
+    ) {
+        let checks: Vec<_> = self
+            .cfg
+            .wait_for
+            .iter()
+            .map(|dep| {
+                let target = dep.target();
+                probe::check(dep).map_err(move |err| (target, err))
+            }).collect();
+        let interval = self.cfg.wait_for.iter().map(|dep| dep.interval).min();
+        let interval = Duration::new(u64::from(interval.unwrap_or(1)), 0);
+
+        future::join_all(checks)
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                // service got stopped or started again
+                if act.stagger_gen != gen {
+                    return actix::fut::ok(());
+                }
+                match res {
+                    Ok(_) => {
+                        info!("Dependencies of service {:?} are reachable", act.name);
+                        act.waiting = false;
+                        act.blocked_by = None;
+                        act.stagger(ctx, move |worker| worker.start(reason.clone()));
+                        act.update(ctx);
+                    }
+                    Err((dep, err)) => {
+                        if deadline.is_none_or(|d| Instant::now() < d) {
+                            debug!(
+                                "Dependency {:?} of service {:?} failed: {}",
+                                dep, act.name, err
+                            );
+                            act.blocked_by = Some(dep);
+                            ctx.run_later(interval, move |act, ctx| {
+                                if act.stagger_gen == gen {
+                                    act.wait_dependencies(ctx, gen, deadline, reason);
+                                }
+                            });
+                        } else {
+                            act.dependency_timeout(ctx, dep, &err, reason);
+                        }
+                    }
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    // dependency is not reachable within `wait_for_timeout`,
+    // service fails or workers start anyway, see `on_dep_timeout`
+    fn dependency_timeout(
+        &mut self, ctx: &mut Context<Self>, dep: String, err: &str, reason: Reason,
+    ) {
+        match self.cfg.on_dep_timeout {
+            DependencyTimeout::fail => {
+                error!(
+                    "Dependency {:?} of service {:?} is not reachable: {}",
+                    dep, self.name, err
+                );
+                self.blocked_by = Some(dep);
+                self.dependencies_failed(ctx);
+            }
+            DependencyTimeout::proceed => {
+                warn!(
+                    "Dependency {:?} of service {:?} is not reachable: {}, \
+                     starting workers anyway",
+                    dep, self.name, err
+                );
+                self.waiting = false;
+                self.blocked_by = None;
+                self.stagger(ctx, move |worker| worker.start(reason.clone()));
+                self.update(ctx);
+            }
+        }
+    }
+
+    // dependencies are not reachable within `wait_for_timeout`
+    fn dependencies_failed(&mut self, ctx: &mut Context<Self>) {
+        self.waiting = false;
+        let state = std::mem::replace(&mut self.state, ServiceState::Failed);
+        match state {
+            ServiceState::Starting(task) => {
+                task.set(StartStatus::Failed);
+                self.webhook("service_failed", None, Reason::DependencyTimeout);
+                self.retry_failed(ctx);
+            }
+            state => self.state = state,
+        }
+        self.check_quorum();
+    }
+
+    // set desired number of workers, missing workers are created
+    fn set_desired(&mut self, ctx: &mut Context<Self>, num: u16) {
+        let num = num as usize;
+        self.scaled_up.extend(self.desired..num);
+        self.desired = num;
+        while self.workers.len() < self.desired {
+            let idx = self.workers.len();
+            self.workers
+                .push(Worker::new(idx, self.cfg.clone(), ctx.address()));
+        }
+    }
+
+    // bring number of workers to desired number, workers that are
+    // starting or stopping already are not touched. only workers added
+    // by scale up are started, queued and held restarts are left
+    // to `start_pending`
+    fn reconcile(&mut self) {
+        let active = matches!(
+            self.state,
+            ServiceState::Running
+                | ServiceState::Starting(_)
+                | ServiceState::Reloading(_)
+        );
+        let start = active
+            && self.stagger_pending == 0
+            && !self.waiting
+            && self.swap == SwapState::Idle;
+
+        let now = Instant::now();
+        let desired = self.desired;
+        if active {
+            self.scaled_up.retain(|idx| *idx < desired);
+        } else {
+            // all workers get started with service
+            self.scaled_up.clear();
+        }
+
+        for (idx, worker) in self.workers.iter_mut().enumerate() {
+            if idx >= self.desired {
+                if !worker.is_stopped() {
+                    worker.stop(Reason::Scaled);
+                }
+            } else if worker.is_idle() {
+                if start {
+                    let queued = worker.is_pending() || worker.is_held(now);
+                    if !queued && self.scaled_up.remove(&idx) {
+                        worker.start(Reason::Scaled);
+                    }
+                } else if !active && !worker.is_stopped() {
+                    // new worker of stopped service
+                    worker.stop(Reason::Scaled);
+                }
+            }
+        }
+    }
+
+    // start queued worker restarts that are not held at time `now`,
+    // respect `restart_concurrency` limit
+    fn start_pending(&mut self, now: Instant) {
+        match self.state {
+            ServiceState::Running
+            | ServiceState::Starting(_)
+            | ServiceState::Reloading(_) => (),
+            _ => return,
+        }
+
+        // `0` is unlimited
+        let limit = self.cfg.restart_concurrency as usize;
+        let mut starting = self.workers.iter().filter(|w| w.is_starting()).count();
+        for worker in &mut self.workers {
+            if limit != 0 && starting >= limit {
+                break;
+            }
+            if worker.is_pending() && !worker.is_held(now) {
+                worker.start_pending();
+                starting += 1;
+            }
+        }
+    }
+
+    // clear failure state, queued worker restarts are started again
+    fn reset(&mut self) {
+        for worker in &mut self.workers {
+            worker.reset();
+        }
+        self.quarantined = false;
+        self.flaps.clear();
+        self.blocked_by = None;
+        if let ServiceState::Failed = self.state {
+            self.state = ServiceState::Stopped;
+        }
+        self.start_pending(Instant::now());
+    }
+
+    // start held worker restarts after `hold_failed` time
+    fn wake_held(&self, ctx: &mut Context<Self>) {
+        let now = Instant::now();
+        if self.workers.iter().any(|worker| worker.is_held(now)) {
+            let hold = Duration::new(u64::from(self.cfg.hold_failed), 0);
+            ctx.run_later(hold, |act, ctx| act.update(ctx));
+        }
+    }
+
     // update internal state
-    fn update(&mut self) {
+    fn update(&mut self, ctx: &mut Context<Self>) {
+        self.reconcile();
+        self.start_pending(Instant::now());
+        self.check_pause_confirmed(ctx);
+
         let state = std::mem::replace(&mut self.state, ServiceState::Failed);
 
         match state {
@@ -127,6 +586,7 @@ impl FeService {
 
                 // if we have failed workers, stop all and change service state to failed
                 if failed {
+                    self.cancel_stagger();
                     if in_process {
                         for worker in &mut self.workers {
                             if !(worker.is_stopped() || worker.is_failed()) {
@@ -137,6 +597,8 @@ impl FeService {
                     } else {
                         task.set(StartStatus::Failed);
                         self.state = ServiceState::Failed;
+                        self.webhook("service_failed", None, Reason::SomeWorkersFailed);
+                        self.retry_failed(ctx);
                     }
                 } else {
                     if !in_process {
@@ -148,10 +610,25 @@ impl FeService {
                 }
             }
             ServiceState::Reloading(task) => {
+                // swap reload, wait until all old workers get stopped
+                if self.swap != SwapState::Idle {
+                    if self.swap == SwapState::Stopping {
+                        let (_, in_process) = self.check_loading_workers(false);
+                        if !in_process {
+                            self.swap = SwapState::Waiting;
+                            self.start_swapped(ctx);
+                        }
+                    }
+                    self.state = ServiceState::Reloading(task);
+                    return;
+                }
+
                 let (failed, in_process) = self.check_loading_workers(true);
+                let in_process = in_process || self.stagger_pending > 0;
 
                 // if we have failed workers, stop all and change service state to failed
                 if failed {
+                    self.cancel_stagger();
                     if in_process {
                         for worker in &mut self.workers {
                             if !(worker.is_stopped() || worker.is_failed()) {
@@ -160,11 +637,15 @@ impl FeService {
                         }
                         self.state = ServiceState::Reloading(task);
                     } else {
+                        self.cancel_reload_timeout(ctx);
                         task.set(ReloadStatus::Failed);
                         self.state = ServiceState::Failed;
+                        self.webhook("service_failed", None, Reason::SomeWorkersFailed);
+                        self.retry_failed(ctx);
                     }
                 } else {
                     if !in_process {
+                        self.cancel_reload_timeout(ctx);
                         task.set(ReloadStatus::Success);
                         self.state = ServiceState::Running;
                     } else {
@@ -184,6 +665,460 @@ impl FeService {
             }
             state => self.state = state,
         }
+        self.update_peers();
+        self.check_quorum();
+        self.start_queued_reload(ctx);
+    }
+
+    // send running workers to worker processes if they changed
+    fn update_peers(&mut self) {
+        let peers: Vec<_> = self
+            .workers
+            .iter()
+            .filter_map(|w| {
+                w.pid().map(|pid| Peer {
+                    idx: w.idx,
+                    pid: libc::pid_t::from(pid),
+                })
+            }).collect();
+        if peers == self.peers {
+            return;
+        }
+        process::set_peers(&self.name, peers.clone());
+        for worker in &self.workers {
+            for (_, addr) in worker.processes() {
+                addr.do_send(process::UpdatePeers(peers.clone()));
+            }
+        }
+        self.peers = peers;
+    }
+
+    fn cancel_reload_timeout(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.reload_timeout.take() {
+            ctx.cancel_future(handle);
+        }
+    }
+
+    // abort reload if new workers do not start during `reload_timeout`,
+    // old workers keep running, workers without old process start again
+    fn start_reload_timeout(&mut self, ctx: &mut Context<Self>) {
+        self.cancel_reload_timeout(ctx);
+        if self.cfg.reload_timeout == 0 {
+            return;
+        }
+        let timeout = Duration::new(u64::from(self.cfg.reload_timeout), 0);
+        self.reload_timeout = Some(ctx.run_later(timeout, |act, ctx| {
+            act.reload_timeout = None;
+            act.abort_reload(ctx);
+        }));
+    }
+
+    fn abort_reload(&mut self, ctx: &mut Context<Self>) {
+        let task = match std::mem::replace(&mut self.state, ServiceState::Failed) {
+            ServiceState::Reloading(task) => task,
+            state => {
+                self.state = state;
+                return;
+            }
+        };
+        warn!("Reload timeout, abort reload: {:?}", self.name);
+        task.set(ReloadStatus::Timeout);
+        self.webhook("reload_failed", None, Reason::ReloadTimeout);
+
+        // swap reload, old workers are stopped already, swap continues
+        if self.swap != SwapState::Idle {
+            self.state = ServiceState::Reloading(actix::Condition::default());
+            return;
+        }
+
+        self.cancel_stagger();
+        let mut running = true;
+        for worker in &mut self.workers {
+            running &= worker.abort_reload(Reason::ReloadTimeout);
+        }
+        if running {
+            self.state = ServiceState::Running;
+            self.start_queued_reload(ctx);
+        } else {
+            // some workers have no old process, start them as on service start
+            self.state = ServiceState::Starting(actix::Condition::default());
+            self.start_workers(ctx, Reason::ReloadTimeout);
+            self.update(ctx);
+        }
+    }
+
+    // start failed service again after `retry_failed_interval`
+    fn retry_failed(&mut self, ctx: &mut Context<Self>) {
+        if self.cfg.retry_failed_interval == 0 {
+            return;
+        }
+        let interval = Duration::new(u64::from(self.cfg.retry_failed_interval), 0);
+        ctx.run_later(interval, |act, ctx| {
+            // service could be started or stopped manually in the meantime
+            if let ServiceState::Failed = act.state {
+                info!("Retrying failed service: {:?}", act.name);
+                act.paused = false;
+                act.state = ServiceState::Starting(actix::Condition::default());
+                for worker in &mut act.workers {
+                    worker.reset();
+                }
+                act.start_workers(ctx, Reason::RetryFailed);
+                act.update(ctx);
+            }
+        });
+    }
+
+    // create service cgroup and apply limits
+    fn setup_cgroup(&self) {
+        if let Some(ref cfg) = self.cfg.cgroup {
+            if let Err(err) = cgroup::setup(cfg, &self.name) {
+                error!("Can not setup cgroup for {:?}: {}", self.name, err);
+            }
+        }
+    }
+
+    // run `version_cmd` in separate thread and remember first line of its output
+    fn read_version(&mut self, ctx: &mut Context<Self>) {
+        let cmd = match self.cfg.version_cmd {
+            Some(ref cmd) => cmd.clone(),
+            None => {
+                self.version = None;
+                return;
+            }
+        };
+
+        probe::output(&cmd, VERSION_CMD_TIMEOUT)
+            .into_actor(self)
+            .then(move |res, act, _| {
+                // configuration changed while command was running
+                if act.cfg.version_cmd.as_ref() != Some(&cmd) {
+                    return actix::fut::ok(());
+                }
+                match res {
+                    Ok(version) => {
+                        if let Some(ref version) = version {
+                            info!("Service {:?} version: {}", act.name, version);
+                        }
+                        act.version = version;
+                    }
+                    Err(err) => {
+                        error!("version_cmd for {:?} failed: {}", act.name, err);
+                        act.version = None;
+                    }
+                }
+                actix::fut::ok(())
+            }).spawn(ctx);
+    }
+
+    /// message counters of worker processes
+    fn counters(&self) -> impl Future<Item = Vec<WorkerCounters>, Error = ()> {
+        let mut counters = Vec::new();
+        for worker in &self.workers {
+            for (pid, addr) in worker.processes() {
+                let name = worker.name();
+                counters.push(addr.send(process::Counters).then(move |res| {
+                    Ok::<_, ()>(res.ok().map(|(received, sent)| {
+                        (name, format!("{}", pid), received, sent)
+                    }))
+                }));
+            }
+        }
+
+        future::join_all(counters)
+            .map(|res| res.into_iter().flatten().collect())
+    }
+
+    /// reload service if any of `watch_files` got changed,
+    /// reload is delayed until files stop changing
+    fn watch_files(&mut self, ctx: &mut Context<Self>) {
+        let mut changed = false;
+        for path in &self.cfg.watch_files {
+            let mtime = utils::mtime(path);
+            if let Some(prev) = self.watched.insert(path.clone(), mtime) {
+                changed |= prev != mtime;
+            }
+        }
+        if changed {
+            self.files_changed = Some(Instant::now());
+            return;
+        }
+
+        let delay = Duration::new(cmd::WATCH_CONFIG_DELAY, 0);
+        if self.files_changed.is_some_and(|changed| changed.elapsed() >= delay) {
+            self.files_changed = None;
+            if let ServiceState::Running = self.state {
+                info!("Watched files changed, reloading service {:?}", self.name);
+                ctx.address().do_send(Reload(true, None));
+            }
+        }
+    }
+
+    /// number of workers with running process
+    fn actual(&self) -> usize {
+        self.workers.iter().filter(|w| w.pid().is_some()).count()
+    }
+
+    /// number of running workers required for readiness
+    fn quorum(&self) -> usize {
+        match self.cfg.min_healthy as usize {
+            0 => self.desired,
+            min => std::cmp::min(min, self.desired),
+        }
+    }
+
+    /// service is starting or running with `min_healthy` running workers
+    fn quorum_met(&self) -> bool {
+        match self.state {
+            ServiceState::Running | ServiceState::Starting(_) => {
+                self.actual() >= self.quorum()
+            }
+            _ => false,
+        }
+    }
+
+    // resolve readiness requests, once quorum is met or service
+    // fails or stops
+    fn check_quorum(&mut self) {
+        if self.quorum_waiters.is_empty() {
+            return;
+        }
+        let met = match self.state {
+            ServiceState::Running | ServiceState::Starting(_) => {
+                if self.quorum_met() {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            ServiceState::Reloading(_) => None,
+            _ => Some(false),
+        };
+        if let Some(met) = met {
+            for tx in self.quorum_waiters.drain(..) {
+                let _ = tx.send(met);
+            }
+        }
+    }
+
+    /// service status as reported to client
+    fn status(&self) -> &'static str {
+        match self.state {
+            ServiceState::Running => match (self.paused, self.pause_confirmed()) {
+                (true, true) => "paused",
+                (true, false) => "pausing",
+                (false, true) => "running",
+                (false, false) => "resuming",
+            },
+            ServiceState::Stopped | ServiceState::Stopping(_) if self.disabled => {
+                "disabled"
+            }
+            ServiceState::Stopped | ServiceState::Stopping(_) if self.quarantined => {
+                "quarantined"
+            }
+            ServiceState::Starting(_) if self.waiting => "waiting for dependency",
+            _ => self.state.description(),
+        }
+    }
+
+    /// service status with `wait_for` dependency that blocks workers start
+    fn status_detail(&self) -> String {
+        match self.blocked_by {
+            Some(ref dep) => format!("{} (blocked by {})", self.status(), dep),
+            None => self.status().to_owned(),
+        }
+    }
+
+    // record worker result of custom command
+    fn ack(
+        &mut self, ctx: &mut Context<Self>, idx: usize, pid: Pid, id: u64, ok: bool,
+        detail: Option<String>,
+    ) {
+        let done = match self.acks.get_mut(&id) {
+            Some(pending) => {
+                match pending.waiting.iter().position(|w| *w == (idx, pid)) {
+                    Some(pos) => {
+                        pending.waiting.remove(pos);
+                    }
+                    None => return,
+                }
+                let name = self.workers[idx].name();
+                pending
+                    .results
+                    .push((name, format!("{}", pid), Some(ok), detail));
+                pending.waiting.is_empty()
+            }
+            None => {
+                debug!("Unexpected ack from worker (pid:{}): {}", pid, id);
+                return;
+            }
+        };
+        if done {
+            if let Some(pending) = self.acks.remove(&id) {
+                ctx.cancel_future(pending.timeout);
+                pending.finish(&self.workers);
+            }
+        }
+    }
+
+    // quarantine service if workers restart too often
+    fn check_flapping(&mut self, ctx: &mut Context<Self>) {
+        if self.cfg.flap_restarts == 0 || self.quarantined {
+            return;
+        }
+        let now = Instant::now();
+        let window = Duration::new(u64::from(self.cfg.flap_window), 0);
+        while self
+            .flaps
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            self.flaps.pop_front();
+        }
+        self.flaps.push_back(now);
+
+        if self.flaps.len() >= self.cfg.flap_restarts as usize {
+            self.quarantine(ctx);
+        }
+    }
+
+    /// post lifecycle event to `webhook_url`
+    fn webhook(&self, event: &'static str, worker: Option<String>, reason: Reason) {
+        if let Some(ref url) = self.cfg.webhook_url {
+            let ev = WebhookEvent::new(&self.name, event, worker, reason);
+            webhook::post(url, &self.cfg.webhook_headers, &ev);
+        }
+    }
+
+    // stop flapping service, service can not be started until `reset`
+    fn quarantine(&mut self, ctx: &mut Context<Self>) {
+        error!("Service is flapping, quarantine: {:?}", self.name);
+        self.quarantined = true;
+        self.flaps.clear();
+        self.webhook("quarantined", None, Reason::Flapping);
+
+        // same runner as exec probes, command is reaped by supervisor
+        if let Some(ref cmd) = self.cfg.on_flap_cmd {
+            let name = self.name.clone();
+            let env = [("FECTL_SRV_NAME", self.name.as_str())];
+            probe::command_env(cmd, &env, ON_FLAP_CMD_TIMEOUT)
+                .into_actor(self)
+                .then(move |res, _, _| {
+                    if let Err(err) = res {
+                        error!("on_flap_cmd for {:?} failed: {}", name, err);
+                    }
+                    actix::fut::ok(())
+                }).spawn(ctx);
+        }
+
+        let state = std::mem::replace(&mut self.state, ServiceState::Stopped);
+        match state {
+            ServiceState::Starting(task) => task.set(StartStatus::Failed),
+            ServiceState::Reloading(task) => {
+                self.cancel_reload_timeout(ctx);
+                task.set(ReloadStatus::Failed);
+            }
+            ServiceState::Running => (),
+            state => {
+                self.state = state;
+                return;
+            }
+        }
+
+        self.paused = false;
+        self.deferred.clear();
+        self.swap = SwapState::Idle;
+        self.cancel_stagger();
+        self.cancel_queued_reload();
+        self.state = ServiceState::Stopping(actix::Condition::default());
+        for worker in &mut self.workers {
+            worker.stop(Reason::Flapping);
+        }
+    }
+
+    fn start_reload(
+        &mut self, ctx: &mut Context<Self>, graceful: bool, cfg: Option<ServiceConfig>,
+        task: actix::Condition<ReloadStatus>,
+    ) {
+        debug!("Reloading service: {:?}", self.name);
+        self.paused = false;
+        self.deferred.clear();
+        self.reload_graceful = graceful;
+        self.state = ServiceState::Reloading(task);
+
+        if let Some(cfg) = cfg {
+            info!("Using re-read configuration for service: {:?}", self.name);
+            for worker in &mut self.workers {
+                worker.set_config(cfg.clone());
+            }
+            self.cfg = cfg;
+            self.setup_cgroup();
+
+            let num = self.cfg.num;
+            if let Some(mut o) = self.num_override.take() {
+                if o.revert_on_reload {
+                    info!(
+                        "Worker count override of service {:?} is reverted to {}",
+                        self.name, num
+                    );
+                    self.set_desired(ctx, num);
+                } else {
+                    o.configured = num;
+                    self.num_override = Some(o);
+                }
+            }
+        }
+        self.read_version(ctx);
+
+        if self.cfg.reload_swap_delay > 0 {
+            // stop old workers first, new workers start after delay
+            self.swap = SwapState::Stopping;
+            for worker in &mut self.workers {
+                if graceful {
+                    worker.stop(Reason::ConsoleRequest);
+                } else {
+                    worker.quit(Reason::ConsoleRequest);
+                }
+            }
+            self.update(ctx);
+        } else {
+            self.stagger(ctx, move |worker| {
+                worker.reload(graceful, Reason::ConsoleRequest)
+            });
+        }
+        self.start_reload_timeout(ctx);
+    }
+
+    // start reload that was requested during previous reload
+    fn start_queued_reload(&mut self, ctx: &mut Context<Self>) {
+        match self.state {
+            ServiceState::Running | ServiceState::Failed => (),
+            _ => return,
+        }
+        if let Some((graceful, cfg, task)) = self.queued_reload.take() {
+            self.start_reload(ctx, graceful, cfg, task);
+        }
+    }
+
+    // service is stopping, queued reload does not run
+    fn cancel_queued_reload(&mut self) {
+        if let Some((_, _, task)) = self.queued_reload.take() {
+            task.set(ReloadStatus::Stopping);
+        }
+    }
+
+    // start new workers after `reload_swap_delay`
+    fn start_swapped(&mut self, ctx: &mut Context<Self>) {
+        let delay = Duration::new(u64::from(self.cfg.reload_swap_delay), 0);
+        ctx.run_later(delay, |act, ctx| {
+            if act.swap != SwapState::Waiting {
+                return;
+            }
+            act.swap = SwapState::Idle;
+            if let ServiceState::Reloading(_) = act.state {
+                act.stagger(ctx, |worker| worker.start(Reason::ConsoleRequest));
+                act.update(ctx);
+            }
+        });
     }
 
     fn message(&mut self, pid: Pid, message: WorkerMessage) {
@@ -196,11 +1131,33 @@ impl FeService {
 impl Actor for FeService {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Context<Self>) {
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        self.setup_cgroup();
+        self.read_version(ctx);
+
         // start workers
-        for worker in &mut self.workers {
-            worker.start(Reason::Initial);
+        if self.disabled {
+            info!("Service {:?} has no workers, service is disabled", self.name);
+        } else {
+            self.start_workers(ctx, Reason::Initial);
         }
+
+        // watch service files
+        self.watch_files(ctx);
+        ctx.run_interval(Duration::new(1, 0), |act, ctx| act.watch_files(ctx));
+
+        // force-clear workers stuck in stopping state
+        ctx.run_interval(Duration::new(1, 0), |act, ctx| {
+            for worker in &act.workers {
+                if let Some(pid) = worker.kill_stuck() {
+                    error!(
+                        "Worker is stuck in stopping state, killed (pid:{})",
+                        pid
+                    );
+                    ctx.notify(ProcessExited(pid, ProcessError::StopTimeout));
+                }
+            }
+        });
     }
 }
 
@@ -210,9 +1167,30 @@ pub struct ProcessMessage(pub usize, pub Pid, pub WorkerMessage);
 impl Handler<ProcessMessage> for FeService {
     type Result = ();
 
-    fn handle(&mut self, msg: ProcessMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: ProcessMessage, ctx: &mut Context<Self>) {
+        if let WorkerMessage::ack { id, ok, ref detail } = msg.2 {
+            self.ack(ctx, msg.0, msg.1, id, ok, detail.clone());
+            return;
+        }
+
+        // operator paused service, check worker reload policy
+        let reload = matches!(msg.2, WorkerMessage::reload | WorkerMessage::restart);
+        if reload && self.paused {
+            match self.cfg.worker_reload {
+                WorkerReload::allow => (),
+                WorkerReload::defer => {
+                    info!("Service is paused, defer worker request (pid:{})", msg.1);
+                    self.deferred.push((msg.0, msg.1, msg.2));
+                    return;
+                }
+                WorkerReload::deny => {
+                    warn!("Service is paused, ignore worker request (pid:{})", msg.1);
+                    return;
+                }
+            }
+        }
         self.workers[msg.0].message(msg.1, &msg.2);
-        self.update();
+        self.update(ctx);
     }
 }
 
@@ -223,10 +1201,32 @@ impl Handler<ProcessFailed> for FeService {
     type Result = ();
 
     fn handle(&mut self, msg: ProcessFailed, ctx: &mut Context<Self>) {
+        // failed process is fenced by reload generation, worker can get
+        // reloaded and process pid reused before delayed failure is processed
+        let generation = match self.workers[msg.0].generation(msg.1) {
+            Some(generation) => generation,
+            None => return,
+        };
+
         // TODO: delay failure processing, needs better approach
-        ctx.run_later(Duration::new(5, 0), move |act, _| {
-            act.workers[msg.0].exited(msg.1, &msg.2);
-            act.update();
+        ctx.run_later(Duration::new(5, 0), move |act, ctx| {
+            if act.workers[msg.0].generation(msg.1) != Some(generation) {
+                debug!(
+                    "Ignoring failure of process of old generation (pid:{})",
+                    msg.1
+                );
+                return;
+            }
+            let failed = act.workers[msg.0].is_failed();
+            if act.workers[msg.0].exited(msg.1, &msg.2) {
+                act.check_flapping(ctx);
+            }
+            if !failed && act.workers[msg.0].is_failed() {
+                let name = act.workers[msg.0].name();
+                act.webhook("worker_failed", Some(name), (&msg.2).into());
+            }
+            act.wake_held(ctx);
+            act.update(ctx);
         });
     }
 }
@@ -237,23 +1237,155 @@ pub struct ProcessLoaded(pub usize, pub Pid);
 impl Handler<ProcessLoaded> for FeService {
     type Result = ();
 
-    fn handle(&mut self, msg: ProcessLoaded, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: ProcessLoaded, ctx: &mut Context<Self>) {
         self.workers[msg.0].loaded(msg.1);
-        self.update();
+        self.update(ctx);
     }
 }
 
-#[derive(Message)]
+#[derive(Message, Clone)]
 pub struct ProcessExited(pub Pid, pub ProcessError);
 
 impl Handler<ProcessExited> for FeService {
     type Result = ();
 
-    fn handle(&mut self, msg: ProcessExited, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: ProcessExited, ctx: &mut Context<Self>) {
+        let mut restarted = false;
+        let mut failed = Vec::new();
+        for worker in &mut self.workers {
+            let is_failed = worker.is_failed();
+            restarted |= worker.exited(msg.0, &msg.1);
+            if !is_failed && worker.is_failed() {
+                failed.push(worker.name());
+            }
+        }
+        for name in failed {
+            self.webhook("worker_failed", Some(name), (&msg.1).into());
+        }
+        if restarted {
+            self.check_flapping(ctx);
+        }
+        self.wake_held(ctx);
+        self.update(ctx);
+    }
+}
+
+#[derive(Message, Clone)]
+pub struct ProcessSuspended(pub Pid, pub bool);
+
+impl Handler<ProcessSuspended> for FeService {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProcessSuspended, _: &mut Context<Self>) {
+        for worker in &mut self.workers {
+            worker.suspended(msg.0, msg.1);
+        }
+    }
+}
+
+/// Check that service and worker process actors are responsive,
+/// resolves to `false` if some process actor does not respond in time
+pub struct SelfCheck(pub Duration);
+
+impl Message for SelfCheck {
+    type Result = Result<bool, ()>;
+}
+
+impl Handler<SelfCheck> for FeService {
+    type Result = Response<bool, ()>;
+
+    fn handle(&mut self, msg: SelfCheck, _: &mut Context<Self>) -> Self::Result {
+        let checks: Vec<_> = self
+            .workers
+            .iter()
+            .flat_map(|worker| worker.processes())
+            .map(|(pid, addr)| {
+                addr.send(process::SelfCheck).timeout(msg.0).then(move |res| {
+                    match res {
+                        // process actor is stopped, worker exit is not processed yet
+                        Ok(_) | Err(MailboxError::Closed) => Ok(true),
+                        Err(MailboxError::Timeout) => {
+                            error!("Worker process actor is stuck (pid:{})", pid);
+                            Ok(false)
+                        }
+                    }
+                })
+            }).collect();
+
+        Response::async(future::join_all(checks).map(|res| res.iter().all(|ok| *ok)))
+    }
+}
+
+/// Worker processes message counters
+pub struct Counters;
+
+impl Message for Counters {
+    type Result = Result<Vec<WorkerCounters>, ()>;
+}
+
+impl Handler<Counters> for FeService {
+    type Result = Response<Vec<WorkerCounters>, ()>;
+
+    fn handle(&mut self, _: Counters, _: &mut Context<Self>) -> Self::Result {
+        Response::async(self.counters())
+    }
+}
+
+/// Worker process starts and failures, summed over workers
+pub struct Failures;
+
+impl Message for Failures {
+    type Result = FailureCounters;
+}
+
+impl Handler<Failures> for FeService {
+    type Result = MessageResult<Failures>;
+
+    fn handle(&mut self, _: Failures, _: &mut Context<Self>) -> Self::Result {
+        let mut failures = FailureCounters::default();
+        for worker in &self.workers {
+            failures.add(&worker.failures);
+        }
+        MessageResult(failures)
+    }
+}
+
+/// Clear worker failure counters, restart counters are not affected
+pub struct ResetFailures;
+
+impl Message for ResetFailures {
+    type Result = ();
+}
+
+impl Handler<ResetFailures> for FeService {
+    type Result = ();
+
+    fn handle(&mut self, _: ResetFailures, _: &mut Context<Self>) {
         for worker in &mut self.workers {
-            worker.exited(msg.0, &msg.1);
+            worker.failures = FailureCounters::default();
         }
-        self.update();
+    }
+}
+
+/// Send signal to running workers, resolves with pids of signaled workers
+pub struct SignalWorkers(pub Signal);
+
+impl Message for SignalWorkers {
+    type Result = Vec<String>;
+}
+
+impl Handler<SignalWorkers> for FeService {
+    type Result = MessageResult<SignalWorkers>;
+
+    fn handle(&mut self, msg: SignalWorkers, _: &mut Context<Self>) -> Self::Result {
+        info!("Sending {:?} to workers of service {:?}", msg.0, self.name);
+        let pids = self
+            .workers
+            .iter()
+            .filter_map(|worker| worker.signal(msg.0))
+            .map(|pid| format!("{}", pid))
+            .collect();
+        MessageResult(pids)
     }
 }
 
@@ -282,30 +1414,169 @@ impl Handler<Pids> for FeService {
 pub struct Status;
 
 impl Message for Status {
-    type Result = Result<(String, Vec<(String, Vec<Event>)>), ()>;
+    type Result = Result<ServiceStatus, ()>;
 }
 
 impl Handler<Status> for FeService {
-    type Result = Result<(String, Vec<(String, Vec<Event>)>), ()>;
+    type Result = Result<ServiceStatus, ()>;
 
     fn handle(&mut self, _: Status, _: &mut Context<Self>) -> Self::Result {
-        let mut events: Vec<(String, Vec<Event>)> = Vec::new();
+        let mut events = Vec::new();
         for worker in &self.workers {
-            events.push((
-                format!("worker({})", worker.idx + 1),
-                Vec::from(&worker.events),
-            ));
+            events.push((worker.name(), Vec::from(&worker.events)));
         }
+        Ok((
+            self.status_detail(),
+            self.version.clone(),
+            (self.desired, self.actual()),
+            events,
+            self.cfg.labels.clone(),
+            self.num_override,
+        ))
+    }
+}
 
-        let status = match self.state {
-            ServiceState::Running => if self.paused {
-                "paused"
-            } else {
-                "running"
-            },
-            _ => self.state.description(),
+/// Service state, configuration and workers counters for diagnostics
+pub struct Dump;
+
+impl Message for Dump {
+    type Result = Result<ServiceSnapshot, ()>;
+}
+
+impl Handler<Dump> for FeService {
+    type Result = Response<ServiceSnapshot, ()>;
+
+    fn handle(&mut self, _: Dump, _: &mut Context<Self>) -> Self::Result {
+        // state is captured at once, counters are collected from
+        // the same worker processes right after
+        let snapshot = ServiceSnapshot {
+            name: self.name.clone(),
+            status: self.status_detail(),
+            version: self.version.clone(),
+            desired: self.desired,
+            actual: self.actual(),
+            num_override: self.num_override,
+            labels: self.cfg.labels.clone(),
+            config: self
+                .cfg
+                .fields()
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+            workers: self.workers.iter().map(|w| w.snapshot()).collect(),
+            counters: Vec::new(),
+            cgroup: self
+                .cfg
+                .cgroup
+                .as_ref()
+                .map(|cfg| cgroup::usage(cfg, &self.name)),
         };
-        Ok((status.to_owned(), events))
+
+        Response::async(self.counters().map(move |counters| {
+            let mut snapshot = snapshot;
+            snapshot.counters = counters;
+            snapshot
+        }))
+    }
+}
+
+/// Likely causes why service does not run desired number of workers
+pub struct Diagnose;
+
+impl Message for Diagnose {
+    type Result = Result<Diagnosis, ()>;
+}
+
+impl Handler<Diagnose> for FeService {
+    type Result = Result<Diagnosis, ()>;
+
+    fn handle(&mut self, _: Diagnose, _: &mut Context<Self>) -> Self::Result {
+        let workers: Vec<_> = self
+            .workers
+            .iter()
+            .map(|w| w.snapshot())
+            .map(|w| {
+                let failures: Vec<_> = w
+                    .events
+                    .into_iter()
+                    .filter(|ev| {
+                        matches!(
+                            ev.state,
+                            State::Failed | State::RestartFailed | State::ReloadFailed
+                        )
+                    }).collect();
+                WorkerDiagnosis {
+                    last_failure: failures.last().map(|ev| ev.reason.clone()),
+                    stderr: w.failed.map(|f| f.stderr).unwrap_or_default(),
+                    failures,
+                    name: w.name,
+                    state: w.state,
+                    pid: w.pid,
+                    restarts: w.restarts,
+                    max_restarts: self.cfg.restarts,
+                    held: w.held,
+                }
+            }).collect();
+
+        // service level causes first, they explain state of all workers
+        let mut causes = Vec::new();
+        if self.disabled {
+            causes.push("service is disabled, use enable command".to_owned());
+        }
+        if self.quarantined {
+            causes.push(
+                "service is quarantined because of flapping, use reset command"
+                    .to_owned(),
+            );
+        }
+        match self.blocked_by {
+            Some(ref dep) => causes.push(format!(
+                "workers start is blocked by `wait_for` dependency {}",
+                dep
+            )),
+            None if self.waiting => causes.push(
+                "workers start waits for `wait_for` dependencies".to_owned(),
+            ),
+            None => (),
+        }
+        if self.paused {
+            causes.push("service is paused, use resume command".to_owned());
+        }
+        if let ServiceState::Failed = self.state {
+            causes.push("service failed, use reset command".to_owned());
+        }
+        for w in &workers {
+            if w.state == "failed" {
+                causes.push(format!(
+                    "{} failed after {} of {} restarts, last failure: {:?}",
+                    w.name, w.restarts, w.max_restarts, w.last_failure
+                ));
+            } else if let Some(held) = w.held {
+                causes.push(format!(
+                    "{} restart is held for {}s, last failure: {:?}",
+                    w.name, held, w.last_failure
+                ));
+            }
+        }
+        let actual = self.actual();
+        if causes.is_empty() && actual < self.desired {
+            causes.push(format!("{} of {} workers are running", actual, self.desired));
+        }
+
+        Ok(Diagnosis {
+            causes,
+            actual,
+            workers,
+            service: self.name.clone(),
+            status: self.status().to_owned(),
+            desired: self.desired,
+            disabled: self.disabled,
+            quarantined: self.quarantined,
+            paused: self.paused,
+            waiting_for_dependency: self.waiting,
+            blocked_by: self.blocked_by.clone(),
+            quorum_met: self.quorum_met(),
+        })
     }
 }
 
@@ -319,20 +1590,25 @@ impl Message for Start {
 impl Handler<Start> for FeService {
     type Result = Response<StartStatus, ServiceOperationError>;
 
-    fn handle(&mut self, _: Start, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, _: Start, ctx: &mut Context<Self>) -> Self::Result {
         match self.state {
             ServiceState::Starting(ref mut task) => {
                 Response::async(task.wait().map_err(|_| ServiceOperationError::Failed))
             }
+            ServiceState::Stopped if self.disabled => {
+                Response::reply(Err(ServiceOperationError::Disabled))
+            }
+            ServiceState::Stopped if self.quarantined => {
+                Response::reply(Err(ServiceOperationError::Quarantined))
+            }
             ServiceState::Failed | ServiceState::Stopped => {
                 debug!("Starting service: {:?}", self.name);
                 let mut task = actix::Condition::default();
                 let rx = task.wait();
                 self.paused = false;
+                self.deferred.clear();
                 self.state = ServiceState::Starting(task);
-                for worker in &mut self.workers {
-                    worker.start(Reason::ConsoleRequest);
-                }
+                self.start_workers(ctx, Reason::ConsoleRequest);
                 Response::async(rx.map_err(|_| ServiceOperationError::Failed))
             }
             _ => Response::reply(Err(self.state.error())),
@@ -340,6 +1616,29 @@ impl Handler<Start> for FeService {
     }
 }
 
+/// Reset service command, clears workers restart counters and quarantine,
+/// failed service is marked as stopped
+pub struct Reset;
+
+impl Message for Reset {
+    type Result = Result<(), ServiceOperationError>;
+}
+
+impl Handler<Reset> for FeService {
+    type Result = Result<(), ServiceOperationError>;
+
+    fn handle(&mut self, _: Reset, _: &mut Context<Self>) -> Self::Result {
+        match self.state {
+            ServiceState::Running | ServiceState::Failed | ServiceState::Stopped => {
+                debug!("Reset service: {:?}", self.name);
+                self.reset();
+                Ok(())
+            }
+            _ => Err(self.state.error()),
+        }
+    }
+}
+
 /// Pause service command
 pub struct Pause;
 
@@ -348,9 +1647,9 @@ impl Message for Pause {
 }
 
 impl Handler<Pause> for FeService {
-    type Result = Result<(), ServiceOperationError>;
+    type Result = Response<(), ServiceOperationError>;
 
-    fn handle(&mut self, _: Pause, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, _: Pause, ctx: &mut Context<Self>) -> Self::Result {
         match self.state {
             ServiceState::Running => {
                 debug!("Pause service: {:?}", self.name);
@@ -358,9 +1657,9 @@ impl Handler<Pause> for FeService {
                     worker.pause(Reason::ConsoleRequest);
                 }
                 self.paused = true;
-                Ok(())
+                Response::async(self.wait_pause_confirmed(ctx))
             }
-            _ => Err(self.state.error()),
+            _ => Response::reply(Err(self.state.error())),
         }
     }
 }
@@ -373,9 +1672,9 @@ impl Message for Resume {
 }
 
 impl Handler<Resume> for FeService {
-    type Result = Result<(), ServiceOperationError>;
+    type Result = Response<(), ServiceOperationError>;
 
-    fn handle(&mut self, _: Resume, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, _: Resume, ctx: &mut Context<Self>) -> Self::Result {
         match self.state {
             ServiceState::Running => {
                 debug!("Resume service: {:?}", self.name);
@@ -383,15 +1682,83 @@ impl Handler<Resume> for FeService {
                     worker.resume(Reason::ConsoleRequest);
                 }
                 self.paused = false;
-                Ok(())
+
+                // requests from workers that are not running anymore are ignored
+                let deferred = std::mem::take(&mut self.deferred);
+                for (idx, pid, msg) in deferred {
+                    self.workers[idx].message(pid, &msg);
+                }
+                self.update(ctx);
+                Response::async(self.wait_pause_confirmed(ctx))
             }
-            _ => Err(self.state.error()),
+            _ => Response::reply(Err(self.state.error())),
+        }
+    }
+}
+
+/// Send custom command to all running workers, resolves with workers results.
+///
+/// Workers that do not respond during `timeout` are reported without result.
+pub struct Notify(pub String, pub json::Value);
+
+impl Message for Notify {
+    type Result = Result<Vec<WorkerAck>, ServiceOperationError>;
+}
+
+impl Handler<Notify> for FeService {
+    type Result = Response<Vec<WorkerAck>, ServiceOperationError>;
+
+    fn handle(&mut self, msg: Notify, ctx: &mut Context<Self>) -> Self::Result {
+        match self.state {
+            ServiceState::Running => {
+                debug!("Notify service: {:?} {:?}", self.name, msg.0);
+                self.next_ack += 1;
+                let id = self.next_ack;
+                let cmd = WorkerCommand::custom {
+                    id,
+                    name: msg.0,
+                    data: msg.1,
+                };
+
+                let mut waiting = Vec::new();
+                for worker in &self.workers {
+                    if let Some(pid) = worker.notify(cmd.clone()) {
+                        waiting.push((worker.idx, pid));
+                    }
+                }
+                if waiting.is_empty() {
+                    return Response::reply(Ok(Vec::new()));
+                }
+
+                let (tx, rx) = oneshot::channel();
+                let timeout = Duration::new(u64::from(self.cfg.timeout), 0);
+                let timeout = ctx.run_later(timeout, move |act, _| {
+                    if let Some(pending) = act.acks.remove(&id) {
+                        warn!("Not all workers confirmed command: {:?}", act.name);
+                        pending.finish(&act.workers);
+                    }
+                });
+                self.acks.insert(
+                    id,
+                    PendingAck {
+                        waiting,
+                        tx,
+                        timeout,
+                        results: Vec::new(),
+                    },
+                );
+                Response::async(rx.map_err(|_| ServiceOperationError::Failed))
+            }
+            _ => Response::reply(Err(self.state.error())),
         }
     }
 }
 
 /// Reload service
-pub struct Reload(pub bool);
+///
+/// Second field contains re-read service configuration, if it is `None`
+/// workers get reloaded with current configuration.
+pub struct Reload(pub bool, pub Option<ServiceConfig>);
 
 impl Message for Reload {
     type Result = Result<ReloadStatus, ServiceOperationError>;
@@ -400,20 +1767,41 @@ impl Message for Reload {
 impl Handler<Reload> for FeService {
     type Result = Response<ReloadStatus, ServiceOperationError>;
 
-    fn handle(&mut self, msg: Reload, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: Reload, ctx: &mut Context<Self>) -> Self::Result {
         match self.state {
             ServiceState::Reloading(ref mut task) => {
-                Response::async(task.wait().map_err(|_| ServiceOperationError::Failed))
+                // same request joins reload in progress, other requests
+                // run after current reload, reloads never overlap
+                if msg.1.is_none()
+                    && msg.0 == self.reload_graceful
+                    && self.queued_reload.is_none()
+                {
+                    info!("Reload in progress, request is coalesced: {:?}", self.name);
+                    return Response::async(
+                        task.wait().map_err(|_| ServiceOperationError::Failed),
+                    );
+                }
+                info!("Reload in progress, request is queued: {:?}", self.name);
+                let (graceful, cfg, mut task) = match self.queued_reload.take() {
+                    Some((graceful, cfg, task)) => {
+                        (graceful && msg.0, msg.1.or(cfg), task)
+                    }
+                    None => (msg.0, msg.1, actix::Condition::default()),
+                };
+                let rx = task.wait();
+                self.queued_reload = Some((graceful, cfg, task));
+                Response::async(rx.map_err(|_| ServiceOperationError::Failed))
+            }
+            ServiceState::Stopped if self.disabled => {
+                Response::reply(Err(ServiceOperationError::Disabled))
+            }
+            ServiceState::Stopped if self.quarantined => {
+                Response::reply(Err(ServiceOperationError::Quarantined))
             }
             ServiceState::Running | ServiceState::Failed | ServiceState::Stopped => {
-                debug!("Reloading service: {:?}", self.name);
                 let mut task = actix::Condition::default();
                 let rx = task.wait();
-                self.paused = false;
-                self.state = ServiceState::Reloading(task);
-                for worker in &mut self.workers {
-                    worker.reload(msg.0, Reason::ConsoleRequest);
-                }
+                self.start_reload(ctx, msg.0, msg.1, task);
                 Response::async(rx.map_err(|_| ServiceOperationError::Failed))
             }
             _ => Response::reply(Err(self.state.error())),
@@ -421,6 +1809,135 @@ impl Handler<Reload> for FeService {
     }
 }
 
+/// Set desired number of workers, service starts or stops workers
+pub struct Scale(pub u16);
+
+impl Message for Scale {
+    type Result = Result<(), ServiceOperationError>;
+}
+
+impl Handler<Scale> for FeService {
+    type Result = Result<(), ServiceOperationError>;
+
+    fn handle(&mut self, msg: Scale, ctx: &mut Context<Self>) -> Self::Result {
+        info!("Scale service {:?} to {} workers", self.name, msg.0);
+        self.num_override = None;
+        self.set_desired(ctx, msg.0);
+        self.update(ctx);
+        Ok(())
+    }
+}
+
+/// Override desired number of workers until `Scale` command or,
+/// if flag is set, until next reload with re-read configuration
+pub struct OverrideNum(pub u16, pub bool);
+
+impl Message for OverrideNum {
+    type Result = Result<(), ServiceOperationError>;
+}
+
+impl Handler<OverrideNum> for FeService {
+    type Result = Result<(), ServiceOperationError>;
+
+    fn handle(&mut self, msg: OverrideNum, ctx: &mut Context<Self>) -> Self::Result {
+        info!(
+            "Override worker count of service {:?} to {} (configured {})",
+            self.name, msg.0, self.cfg.num
+        );
+        self.num_override = Some(NumOverride {
+            num: msg.0,
+            configured: self.cfg.num,
+            revert_on_reload: msg.1,
+        });
+        self.set_desired(ctx, msg.0);
+        self.update(ctx);
+        Ok(())
+    }
+}
+
+/// Service runs `min_healthy` workers, disabled service is always ready
+pub struct Ready;
+
+impl Message for Ready {
+    type Result = Result<bool, ()>;
+}
+
+impl Handler<Ready> for FeService {
+    type Result = Result<bool, ()>;
+
+    fn handle(&mut self, _: Ready, _: &mut Context<Self>) -> Self::Result {
+        Ok(self.quorum_met() || self.disabled)
+    }
+}
+
+/// Service is failed or quarantined and has no worker processes,
+/// failed service with scheduled retry is not failed
+pub struct IsFailed;
+
+impl Message for IsFailed {
+    type Result = Result<bool, ()>;
+}
+
+impl Handler<IsFailed> for FeService {
+    type Result = Result<bool, ()>;
+
+    fn handle(&mut self, _: IsFailed, _: &mut Context<Self>) -> Self::Result {
+        let failed = match self.state {
+            ServiceState::Failed => self.cfg.retry_failed_interval == 0,
+            ServiceState::Stopped => self.quarantined,
+            _ => false,
+        };
+        Ok(failed && self.actual() == 0)
+    }
+}
+
+/// Wait until service runs `min_healthy` workers, resolves with `false`
+/// if service fails or stops before that. Disabled service is ready.
+pub struct WaitQuorum;
+
+impl Message for WaitQuorum {
+    type Result = Result<bool, ()>;
+}
+
+impl Handler<WaitQuorum> for FeService {
+    type Result = Response<bool, ()>;
+
+    fn handle(&mut self, _: WaitQuorum, _: &mut Context<Self>) -> Self::Result {
+        match self.state {
+            ServiceState::Running | ServiceState::Starting(_)
+                if self.quorum_met() =>
+            {
+                Response::reply(Ok(true))
+            }
+            ServiceState::Stopped if self.disabled => Response::reply(Ok(true)),
+            ServiceState::Running
+            | ServiceState::Starting(_)
+            | ServiceState::Reloading(_) => {
+                let (tx, rx) = oneshot::channel();
+                self.quorum_waiters.push(tx);
+                Response::async(rx.map_err(|_| ()))
+            }
+            _ => Response::reply(Ok(false)),
+        }
+    }
+}
+
+/// Stop service actor of removed service, workers have to be stopped
+pub struct Shutdown;
+
+impl Message for Shutdown {
+    type Result = ();
+}
+
+impl Handler<Shutdown> for FeService {
+    type Result = ();
+
+    fn handle(&mut self, _: Shutdown, ctx: &mut Context<Self>) {
+        self.cancel_stagger();
+        ctx.stop();
+    }
+}
+
 /// Stop service command
 pub struct Stop(pub bool, pub Reason);
 
@@ -431,7 +1948,9 @@ impl Message for Stop {
 impl Handler<Stop> for FeService {
     type Result = Response<(), ()>;
 
-    fn handle(&mut self, msg: Stop, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: Stop, ctx: &mut Context<Self>) -> Self::Result {
+        self.cancel_stagger();
+        self.cancel_queued_reload();
         let state = std::mem::replace(&mut self.state, ServiceState::Stopped);
 
         match state {
@@ -440,6 +1959,12 @@ impl Handler<Stop> for FeService {
                 return Response::reply(Err(()));
             }
             ServiceState::Stopping(mut task) => {
+                // escalate graceful stop
+                if !msg.0 {
+                    for worker in &mut self.workers {
+                        worker.quit(msg.1.clone());
+                    }
+                }
                 let rx = task.wait();
                 self.state = ServiceState::Stopping(task);
                 return Response::async(rx.map(|_| ()).map_err(|_| ()));
@@ -448,6 +1973,7 @@ impl Handler<Stop> for FeService {
                 task.set(StartStatus::Stopping);
             }
             ServiceState::Reloading(task) => {
+                self.cancel_reload_timeout(ctx);
                 task.set(ReloadStatus::Stopping);
             }
             ServiceState::Running => (),
@@ -457,6 +1983,8 @@ impl Handler<Stop> for FeService {
         let mut task = actix::Condition::default();
         let rx = task.wait();
         self.paused = false;
+        self.deferred.clear();
+        self.swap = SwapState::Idle;
         self.state = ServiceState::Stopping(task);
         for worker in &mut self.workers {
             if msg.0 {
@@ -465,8 +1993,204 @@ impl Handler<Stop> for FeService {
                 worker.quit(msg.1.clone());
             }
         }
-        self.update();
+        self.update(ctx);
 
         Response::async(rx.map(|_| ()).map_err(|_| ()))
     }
 }
+
+/// Disable service command, gracefully stops service.
+///
+/// Disabled service can not be started or reloaded until `Enable` command.
+pub struct Disable;
+
+impl Message for Disable {
+    type Result = Result<(), ()>;
+}
+
+impl Handler<Disable> for FeService {
+    type Result = Response<(), ()>;
+
+    fn handle(&mut self, _: Disable, ctx: &mut Context<Self>) -> Self::Result {
+        debug!("Disable service: {:?}", self.name);
+        self.disabled = true;
+        match self.state {
+            ServiceState::Failed | ServiceState::Stopped => {
+                self.state = ServiceState::Stopped;
+                Response::reply(Ok(()))
+            }
+            _ => <Self as Handler<Stop>>::handle(
+                self,
+                Stop(true, Reason::ConsoleRequest),
+                ctx,
+            ),
+        }
+    }
+}
+
+/// Enable disabled service, service stays stopped.
+///
+/// Service without workers can not be enabled, it has to be scaled first.
+pub struct Enable;
+
+impl Message for Enable {
+    type Result = Result<(), ServiceOperationError>;
+}
+
+impl Handler<Enable> for FeService {
+    type Result = Result<(), ServiceOperationError>;
+
+    fn handle(&mut self, _: Enable, _: &mut Context<Self>) -> Self::Result {
+        if self.desired == 0 {
+            return Err(ServiceOperationError::NoWorkers);
+        }
+        debug!("Enable service: {:?}", self.name);
+        self.disabled = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config;
+
+    fn service(cfg: &str) -> FeService {
+        let cfg = config::parse_service(json::from_str(cfg).unwrap()).unwrap();
+        let addr = FeService::start(0, cfg.clone());
+        let workers = (0..cfg.num as usize)
+            .map(|idx| Worker::new(idx, cfg.clone(), addr.clone()))
+            .collect();
+        FeService::new(cfg, workers)
+    }
+
+    #[test]
+    fn test_held_worker_restarts() {
+        let mut srv = service(
+            r#"{"name": "test", "num": 1, "command": "true", "hold_failed": 1}"#,
+        );
+        assert_eq!(srv.cfg.restart_concurrency, 0);
+
+        // fork fails without arbiter, failed start is held
+        let now = Instant::now();
+        srv.workers[0].start(Reason::None);
+        let pid = Pid::from_raw(-1);
+        srv.workers[0].exited(pid, &ProcessError::ExitCode(1));
+        assert!(srv.workers[0].is_held(now));
+
+        srv.start_pending(now);
+        assert!(srv.workers[0].is_pending());
+        assert!(!srv.workers[0].is_starting());
+
+        // restart starts after `hold_failed`
+        let later = now + Duration::new(2, 0);
+        assert!(!srv.workers[0].is_held(later));
+        srv.start_pending(later);
+        assert!(!srv.workers[0].is_pending());
+        assert!(srv.workers[0].is_starting());
+    }
+
+    #[test]
+    fn test_reset_keeps_pending_restart() {
+        let mut srv = service(
+            r#"{"name": "test", "num": 1, "command": "true", "hold_failed": 1}"#,
+        );
+        srv.state = ServiceState::Running;
+
+        srv.workers[0].start(Reason::None);
+        let pid = Pid::from_raw(-1);
+        srv.workers[0].exited(pid, &ProcessError::ExitCode(1));
+        assert!(srv.workers[0].is_pending());
+
+        srv.reset();
+        assert!(srv.workers[0].is_pending());
+
+        srv.start_pending(Instant::now() + Duration::new(2, 0));
+        assert!(srv.workers[0].is_starting());
+    }
+
+    #[test]
+    fn test_reload_generation_turnover() {
+        let mut srv = service(r#"{"name": "test", "num": 2, "command": "true"}"#);
+        let (worker, other) = srv.workers.split_at_mut(1);
+        let (worker, other) = (&mut worker[0], &mut other[0]);
+
+        // fork fails without arbiter, every process gets same pid
+        let pid = Pid::from_raw(-1);
+        worker.start(Reason::None);
+        worker.loaded(pid);
+        assert_eq!(worker.generation(pid), Some(0));
+
+        worker.reload(true, Reason::None);
+        assert_eq!(worker.generation(pid), Some(1));
+
+        // new process dies during reload, replacement gets new generation
+        worker.exited(pid, &ProcessError::ExitCode(1));
+        assert_eq!(worker.generation(pid), Some(2));
+
+        // failure restart of running worker gets new generation
+        other.start(Reason::None);
+        other.loaded(pid);
+        other.exited(pid, &ProcessError::ExitCode(1));
+        assert!(other.is_starting());
+        assert_eq!(other.generation(pid), Some(1));
+    }
+
+    #[test]
+    fn test_failures_counted_on_failure_exit() {
+        let mut srv = service(r#"{"name": "test", "num": 1, "command": "true"}"#);
+        let worker = &mut srv.workers[0];
+        let pid = Pid::from_raw(-1);
+
+        worker.start(Reason::None);
+        worker.loaded(pid);
+        worker.exited(pid, &ProcessError::ExitCode(0));
+        assert!(worker.failures.exit_codes.is_empty());
+
+        worker.exited(pid, &ProcessError::ExitCode(1));
+        assert_eq!(worker.failures.exit_codes.get(&1), Some(&1));
+
+        // stopped process exits on request
+        worker.loaded(pid);
+        worker.stop(Reason::None);
+        worker.exited(pid, &ProcessError::Signal(15));
+        assert!(worker.failures.signals.is_empty());
+    }
+
+    #[test]
+    fn test_success_exit_of_running_worker() {
+        let mut srv = service(
+            r#"{"name": "test", "num": 1, "command": "true", "hold_failed": 1,
+                "success_exit_codes": [3]}"#,
+        );
+        let worker = &mut srv.workers[0];
+        let pid = Pid::from_raw(-1);
+
+        // restart is not held and is not counted for flapping
+        worker.start(Reason::None);
+        worker.loaded(pid);
+        assert!(!worker.exited(pid, &ProcessError::ExitCode(3)));
+        assert!(!worker.is_held(Instant::now()));
+        assert!(!worker.is_pending());
+        assert!(worker.is_starting());
+        assert!(worker.failures.exit_codes.is_empty());
+    }
+
+    #[test]
+    fn test_abort_reload_keeps_starting_worker() {
+        let mut srv = service(r#"{"name": "test", "num": 2, "command": "true"}"#);
+        let pid = Pid::from_raw(-1);
+
+        // reloading worker gets old process back
+        srv.workers[0].start(Reason::None);
+        srv.workers[0].loaded(pid);
+        srv.workers[0].reload(true, Reason::None);
+        assert!(srv.workers[0].abort_reload(Reason::ReloadTimeout));
+        assert!(srv.workers[0].is_running());
+
+        // worker without old process keeps starting
+        srv.workers[1].start(Reason::None);
+        assert!(!srv.workers[1].abort_reload(Reason::ReloadTimeout));
+        assert!(srv.workers[1].is_starting());
+    }
+}
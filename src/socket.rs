@@ -1,5 +1,4 @@
 use std;
-use std::error::Error;
 use std::io;
 use std::net::TcpListener;
 use std::os::unix::io::AsRawFd;
@@ -12,6 +11,8 @@ use serde_json as json;
 use addrinfo;
 use config::{Proto, SocketConfig};
 
+/// Listening socket, file descriptor is inherited by worker processes
+#[allow(dead_code)]
 pub struct Socket {
     pub name: String,
     pub listener: TcpListener,
@@ -28,7 +29,7 @@ impl Socket {
             format!("FECTL_FD_{}", name),
             format!(
                 "{},FAMILY:{},SOCKETTYPE:{},PROTO:{}",
-                fd.to_string(),
+                fd,
                 info.family.to_int(),
                 info.socktype.to_int(),
                 info.protocol.to_int()
@@ -71,10 +72,7 @@ impl Socket {
             )?;
             let addrs: Vec<addrinfo::AddrInfo> = lookup.collect();
             if addrs.is_empty() {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "getaddrinfo() returned empty list",
-                ));
+                return Err(io::Error::other("getaddrinfo() returned empty list"));
             }
 
             // start listen
@@ -128,16 +126,16 @@ impl Socket {
                         println!(
                             "Can not bind to address: \"{}\" {:?}",
                             addr.sockaddr,
-                            err.description()
+                            err.to_string()
                         );
                     }
                 }
             }
             if !found {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Can not start listener for `{}` service", sock.name),
-                ));
+                return Err(io::Error::other(format!(
+                    "Can not start listener for `{}` service",
+                    sock.name
+                )));
             }
         }
         Ok(services)
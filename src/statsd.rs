@@ -0,0 +1,95 @@
+// Lifecycle metrics pushed to StatsD agent over UDP, counters are sent
+// on each event, gauges are flushed periodically by command center
+use std::cell::RefCell;
+use std::net::{SocketAddr, UdpSocket};
+
+use config::MasterConfig;
+
+struct StatsdClient {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    prefix: String,
+    tags: bool,
+}
+
+thread_local! {
+    /// metrics are dropped if StatsD output is not configured
+    static CLIENT: RefCell<Option<StatsdClient>> = const { RefCell::new(None) };
+}
+
+/// Open UDP socket for `statsd_address`
+pub fn init(cfg: &MasterConfig) {
+    let addr = match cfg.statsd_address {
+        Some(addr) => addr,
+        None => return,
+    };
+    let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = match UdpSocket::bind(local) {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("Can not create StatsD socket: {}", err);
+            return;
+        }
+    };
+    // slow agent must not stall supervision
+    let _ = socket.set_nonblocking(true);
+    info!("Sending metrics to StatsD agent at {}", addr);
+
+    CLIENT.with(|client| {
+        *client.borrow_mut() = Some(StatsdClient {
+            socket,
+            addr,
+            prefix: cfg.statsd_prefix.clone(),
+            tags: cfg.statsd_tags,
+        })
+    });
+}
+
+/// StatsD output is configured
+pub fn enabled() -> bool {
+    CLIENT.with(|client| client.borrow().is_some())
+}
+
+/// Increment counter of service
+pub fn count(service: &str, metric: &str) {
+    send(service, metric, 1, "c");
+}
+
+/// Set gauge of service
+pub fn gauge(service: &str, metric: &str, value: usize) {
+    send(service, metric, value, "g");
+}
+
+fn send(service: &str, metric: &str, value: usize, kind: &str) {
+    CLIENT.with(|client| {
+        if let Some(ref client) = *client.borrow() {
+            let line = client.line(service, metric, value, kind);
+            if let Err(err) = client.socket.send_to(line.as_ref(), client.addr) {
+                debug!("Can not send metric to StatsD agent: {}", err);
+            }
+        }
+    });
+}
+
+impl StatsdClient {
+    /// service is DogStatsD tag, otherwise part of metric name
+    fn line(&self, service: &str, metric: &str, value: usize, kind: &str) -> String {
+        let mut name = self.prefix.clone();
+        if !self.tags {
+            if !name.is_empty() {
+                name.push('.');
+            }
+            name.push_str(service);
+        }
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.push_str(metric);
+
+        if self.tags {
+            format!("{}:{}|{}|#service:{}", name, value, kind, service)
+        } else {
+            format!("{}:{}|{}", name, value, kind)
+        }
+    }
+}
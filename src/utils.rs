@@ -1,8 +1,15 @@
+use std;
 use std::env;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixDatagram;
 use std::path::Path;
+use std::time::SystemTime;
 
+use libc;
 use nix::unistd::Pid;
+use tokio::executor::{DefaultExecutor, Executor};
 
 /// find file in `PATH` environ
 pub(crate) fn find_path(name: &str) -> Option<String> {
@@ -14,7 +21,7 @@ pub(crate) fn find_path(name: &str) -> Option<String> {
     env::var_os("PATH").and_then(|paths| {
         env::split_paths(&paths)
             .filter_map(|dir| {
-                let full_path = dir.join(&path);
+                let full_path = dir.join(path);
                 if full_path.is_file() {
                     Some(full_path.to_string_lossy().as_ref().to_owned())
                 } else {
@@ -38,6 +45,219 @@ pub fn get_env_vars(all: bool) -> Vec<CString> {
     env
 }
 
+/// Parts of environment variable names that likely hold secrets
+const SECRET_MARKERS: &[&str] = &[
+    "SECRET", "PASSWORD", "PASSWD", "TOKEN", "KEY", "CREDENTIAL", "AUTH",
+];
+
+/// Environment variable name looks like it holds secret
+pub fn is_secret_env(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_MARKERS.iter().any(|marker| name.contains(marker))
+}
+
+/// Value of environment variable for logs and configuration dump,
+/// value of variable with secret-looking name is hidden
+pub fn redact_env<'a>(name: &str, value: &'a str) -> &'a str {
+    if is_secret_env(name) {
+        "<redacted>"
+    } else {
+        value
+    }
+}
+
 pub fn str(pid: Pid) -> Option<String> {
     Some(format!("{}", pid))
 }
+
+/// Check if actix arbiter is available in current thread, worker actors
+/// and timers are spawned on executor of arbiter
+pub fn arbiter_available() -> bool {
+    !matches!(DefaultExecutor::current().status(), Err(ref err) if err.is_shutdown())
+}
+
+/// Send notification to systemd, `NOTIFY_SOCKET` environ is used
+pub fn sd_notify(state: &str) -> io::Result<()> {
+    match env::var_os("NOTIFY_SOCKET") {
+        // abstract namespace sockets are not supported
+        Some(ref path) if !path.to_string_lossy().starts_with('@') => {
+            let sock = UnixDatagram::unbound()?;
+            sock.send_to(state.as_ref(), path)?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Modification time of file
+pub fn mtime<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Last `num` lines of text file, only end of large file is read
+pub fn tail<P: AsRef<Path>>(path: P, num: usize) -> io::Result<Vec<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path)?;
+    let size = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(size.saturating_sub(16 * 1024)))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let text = String::from_utf8_lossy(&buf);
+    let lines: Vec<_> = text.lines().collect();
+    let start = lines.len().saturating_sub(num);
+    Ok(lines[start..].iter().map(|line| (*line).to_owned()).collect())
+}
+
+/// Local host name
+pub fn hostname() -> Option<String> {
+    let mut buf = [0 as libc::c_char; 256];
+    unsafe {
+        if libc::gethostname(buf.as_mut_ptr(), buf.len() - 1) != 0 {
+            return None;
+        }
+        Some(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+    }
+}
+
+/// Match host name against pattern, `*` matches any sequence of characters
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern.eq_ignore_ascii_case(host),
+        Some(pos) => {
+            let (prefix, rest) = (&pattern[..pos], &pattern[pos + 1..]);
+            match host.get(..prefix.len()) {
+                Some(head) if head.eq_ignore_ascii_case(prefix) => {
+                    let host = &host[prefix.len()..];
+                    (0..=host.len())
+                        .filter(|idx| host.is_char_boundary(*idx))
+                        .any(|idx| host_matches(rest, &host[idx..]))
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Number of open file descriptors of current process
+pub fn open_fds() -> Option<usize> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else {
+        "/dev/fd"
+    };
+    // directory listing itself uses one descriptor
+    std::fs::read_dir(dir)
+        .ok()
+        .map(|entries| entries.count().saturating_sub(1))
+}
+
+/// Random value in `0..=max` range, not suitable for cryptography
+pub fn jitter(max: u32) -> u32 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    if max == 0 {
+        return 0;
+    }
+    let val = RandomState::new().build_hasher().finish();
+    (val % (u64::from(max) + 1)) as u32
+}
+
+/// Mark current process as child subreaper, Linux only
+#[cfg(target_os = "linux")]
+pub fn set_subreaper() -> bool {
+    unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_subreaper() -> bool {
+    false
+}
+
+/// Check if process has any child processes, exit status is not collected
+pub fn has_children() -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let res = unsafe {
+        libc::waitid(
+            libc::P_ALL,
+            0,
+            &mut info,
+            libc::WEXITED | libc::WSTOPPED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    // `ECHILD` means there are no children left
+    !(res == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ECHILD))
+}
+
+/// Pid and uid of unix socket peer, Linux only
+#[cfg(target_os = "linux")]
+pub fn peer_cred(fd: RawFd) -> Option<(i32, u32)> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res == 0 {
+        Some((cred.pid, cred.uid))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peer_cred(_: RawFd) -> Option<(i32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{fork, ForkResult};
+
+    // runs in forked process, exit code is result of check
+    fn last_child_exited() -> i32 {
+        match fork() {
+            Ok(ForkResult::Child) => unsafe { libc::_exit(0) },
+            Ok(ForkResult::Parent { child }) => {
+                // exited but not reaped child is still a child
+                if !has_children() {
+                    return 2;
+                }
+                match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, 0)) if !has_children() => 0,
+                    _ => 3,
+                }
+            }
+            Err(_) => 4,
+        }
+    }
+
+    #[test]
+    fn test_has_children_last_child_exited() {
+        // forked process does not see children of other test threads
+        match fork().unwrap() {
+            ForkResult::Child => {
+                let code = if has_children() { 1 } else { last_child_exited() };
+                unsafe { libc::_exit(code) }
+            }
+            ForkResult::Parent { child } => {
+                assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+            }
+        }
+    }
+}
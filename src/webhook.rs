@@ -0,0 +1,193 @@
+// Service lifecycle notifications, posted to service `webhook_url`
+use std;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json as json;
+
+use event::{now_rfc3339, Reason};
+
+/// Timeout for connect, write and read of one webhook request
+const WEBHOOK_TIMEOUT: u64 = 5;
+
+/// Number of attempts to deliver event
+const WEBHOOK_ATTEMPTS: u64 = 3;
+
+/// Number of events waiting for delivery, new events are dropped when full
+const WEBHOOK_QUEUE: usize = 64;
+
+/// Prepared request: url, address and http request
+type Request = (String, String, String);
+
+thread_local! {
+    /// queue of delivery thread, thread is started with first event
+    static QUEUE: RefCell<Option<SyncSender<Request>>> = const { RefCell::new(None) };
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookEvent {
+    pub service: String,
+    /// `worker_failed`, `service_failed` or `quarantined`
+    pub event: &'static str,
+    pub worker: Option<String>,
+    pub reason: Reason,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    /// wall-clock time in RFC 3339 format
+    pub time: String,
+}
+
+impl WebhookEvent {
+    pub fn new(
+        service: &str, event: &'static str, worker: Option<String>, reason: Reason,
+    ) -> WebhookEvent {
+        WebhookEvent {
+            event,
+            worker,
+            reason,
+            service: service.to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            time: now_rfc3339(),
+        }
+    }
+}
+
+/// Check that url is supported, only plain `http` is supported
+pub fn validate(url: &str) -> Result<(), String> {
+    parse_url(url).map(|_| ())
+}
+
+/// Post event from delivery thread, so slow endpoint does not stall supervision.
+/// Failed request is retried with growing delay.
+pub fn post(url: &str, headers: &BTreeMap<String, String>, ev: &WebhookEvent) {
+    let (addr, host, path) = match parse_url(url) {
+        Ok(res) => res,
+        Err(err) => {
+            error!("Invalid webhook url {:?}: {}", url, err);
+            return;
+        }
+    };
+    let body = json::to_string(ev).unwrap();
+
+    let mut req = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        path,
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        req.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    req.push_str("\r\n");
+    req.push_str(&body);
+
+    QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        if queue.is_none() {
+            let (tx, rx) = sync_channel(WEBHOOK_QUEUE);
+            if let Err(err) = std::thread::Builder::new()
+                .name("webhook".to_owned())
+                .spawn(move || deliver(&rx))
+            {
+                error!("Can not start webhook thread: {}", err);
+                return;
+            }
+            *queue = Some(tx);
+        }
+        let res = queue
+            .as_ref()
+            .map(|tx| tx.try_send((url.to_owned(), addr, req)));
+        match res {
+            Some(Err(TrySendError::Full(_))) => {
+                error!("Webhook queue is full, event to {:?} is dropped", url)
+            }
+            Some(Err(TrySendError::Disconnected(_))) => {
+                error!("Webhook thread is gone, event to {:?} is dropped", url);
+                *queue = None;
+            }
+            _ => (),
+        }
+    });
+}
+
+/// deliver queued events one by one
+fn deliver(rx: &Receiver<Request>) {
+    for (url, addr, req) in rx.iter() {
+        let mut delivered = false;
+        for attempt in 1..=WEBHOOK_ATTEMPTS {
+            match request(&addr, &req) {
+                Ok(_) => {
+                    delivered = true;
+                    break;
+                }
+                Err(err) => {
+                    warn!("Webhook {:?} attempt {} failed: {}", url, attempt, err)
+                }
+            }
+            if attempt < WEBHOOK_ATTEMPTS {
+                std::thread::sleep(Duration::new(attempt, 0));
+            }
+        }
+        if !delivered {
+            error!("Can not deliver webhook event to {:?}", url);
+        }
+    }
+}
+
+/// split url to address, host and path
+fn parse_url(url: &str) -> Result<(String, String, String), String> {
+    if !url.starts_with("http://") {
+        return Err("only http:// urls are supported".to_owned());
+    }
+    let url = &url[7..];
+    let (host, path) = match url.find('/') {
+        Some(pos) => (&url[..pos], &url[pos..]),
+        None => (url, "/"),
+    };
+    if host.is_empty() {
+        return Err("host is not set".to_owned());
+    }
+    let addr = if host.rfind(':').is_some_and(|pos| !host[pos..].contains(']')) {
+        host.to_owned()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((addr, host.to_owned(), path.to_owned()))
+}
+
+/// any `2xx` status is success
+fn request(addr: &str, req: &str) -> Result<(), String> {
+    let timeout = Duration::new(WEBHOOK_TIMEOUT, 0);
+    let addr = addr
+        .to_socket_addrs()
+        .map_err(|err| format!("can not resolve address: {}", err))?
+        .next()
+        .ok_or_else(|| "can not resolve address".to_owned())?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)
+        .map_err(|err| format!("can not connect: {}", err))?;
+    let _ = stream.set_read_timeout(Some(timeout));
+    let _ = stream.set_write_timeout(Some(timeout));
+    stream
+        .write_all(req.as_ref())
+        .map_err(|err| format!("request failed: {}", err))?;
+
+    let mut buf = [0; 64];
+    let size = stream
+        .read(&mut buf)
+        .map_err(|err| format!("request failed: {}", err))?;
+    let line = String::from_utf8_lossy(&buf[..size]);
+    match line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(code) => Err(format!("http status {}", code)),
+        None => Err("invalid http response".to_owned()),
+    }
+}
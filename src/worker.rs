@@ -1,26 +1,78 @@
 use std;
-use std::time::{Duration, Instant};
+use std::cell::Cell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use actix::prelude::*;
+use libc;
+use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
+use serde_json as json;
 
-use config::ServiceConfig;
-use event::{Events, Reason, State};
+use cgroup;
+use config::{ServiceConfig, SignalDelivery};
+use event::{
+    now_rfc3339, Event, Events, FailedWorker, FailureCounters, Reason, State,
+    WorkerSnapshot,
+};
 use process::{self, Process, ProcessError};
 use service::FeService;
-use utils::str;
+use statsd;
+use utils::{self, str};
+
+/// Number of output lines recorded for crashed worker
+const FAILED_OUTPUT_LINES: usize = 20;
+
+/// Index and pid of running worker process of service
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub struct Peer {
+    pub idx: usize,
+    pub pid: i32,
+}
 
 #[allow(non_camel_case_types)]
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(tag = "cmd", content = "data")]
 pub enum WorkerCommand {
-    prepare,
+    /// forked process prepares worker execution, with supervisor epoch,
+    /// worker index and running workers of service
+    prepare {
+        epoch: u64,
+        idx: usize,
+        peers: Vec<Peer>,
+    },
+    /// running workers of service changed
+    peers { peers: Vec<Peer> },
     start,
     pause,
     resume,
     stop,
     /// master heartbeat
     hb,
+    /// worker sends signal with `name` to itself, i.e. `SIGUSR1`
+    signal { name: String },
+    /// application specific command, interpreted by worker,
+    /// worker confirms command with `ack` message with same `id`
+    custom {
+        id: u64,
+        name: String,
+        data: json::Value,
+    },
+}
+
+impl WorkerCommand {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WorkerCommand::prepare { .. } => "prepare",
+            WorkerCommand::peers { .. } => "peers",
+            WorkerCommand::start => "start",
+            WorkerCommand::pause => "pause",
+            WorkerCommand::resume => "resume",
+            WorkerCommand::stop => "stop",
+            WorkerCommand::hb => "hb",
+            WorkerCommand::signal { .. } => "signal",
+            WorkerCommand::custom { .. } => "custom",
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -35,10 +87,92 @@ pub enum WorkerMessage {
     reload,
     /// worker requests restart
     restart,
-    /// worker configuration error
-    cfgerror(String),
+    /// worker configuration error, with optional error code
+    cfgerror(CfgError),
     /// heartbeat
     hb,
+    /// worker confirms `pause` command
+    paused,
+    /// worker confirms `resume` command
+    resumed,
+    /// worker result of `custom` command
+    ack {
+        id: u64,
+        ok: bool,
+        #[serde(default)]
+        detail: Option<String>,
+    },
+    /// worker confirms `stop` command, graceful shutdown is started
+    stopping,
+    /// worker finished graceful shutdown, process is about to exit
+    stopped,
+}
+
+impl WorkerMessage {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WorkerMessage::forked => "forked",
+            WorkerMessage::loaded => "loaded",
+            WorkerMessage::reload => "reload",
+            WorkerMessage::restart => "restart",
+            WorkerMessage::cfgerror { .. } => "cfgerror",
+            WorkerMessage::hb => "hb",
+            WorkerMessage::paused => "paused",
+            WorkerMessage::resumed => "resumed",
+            WorkerMessage::ack { .. } => "ack",
+            WorkerMessage::stopping => "stopping",
+            WorkerMessage::stopped => "stopped",
+        }
+    }
+
+    /// configuration error without error code
+    pub fn cfg_error<T: Into<String>>(message: T) -> WorkerMessage {
+        WorkerMessage::cfgerror(CfgError {
+            code: None,
+            message: message.into(),
+        })
+    }
+}
+
+/// Worker configuration error, workers that do not report error code
+/// send message string only
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(from = "CfgErrorData")]
+pub struct CfgError {
+    pub code: Option<u32>,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CfgErrorData {
+    Message(String),
+    Error {
+        #[serde(default)]
+        code: Option<u32>,
+        message: String,
+    },
+}
+
+impl From<CfgErrorData> for CfgError {
+    fn from(data: CfgErrorData) -> CfgError {
+        match data {
+            CfgErrorData::Message(message) => CfgError {
+                code: None,
+                message,
+            },
+            CfgErrorData::Error { code, message } => CfgError { code, message },
+        }
+    }
+}
+
+/// Worker pause state, confirmed by worker process
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum PauseState {
+    Running,
+    Pausing,
+    Paused,
+    Resuming,
 }
 
 enum WorkerState {
@@ -49,6 +183,8 @@ enum WorkerState {
     Running(ProcessInfo),
     StoppingOld(ProcessInfo, ProcessInfo),
     Stopping(ProcessInfo),
+    /// new and old processes of reload that got aborted by stop
+    StoppingAll(ProcessInfo, ProcessInfo),
     Failed,
     Stopped,
 }
@@ -56,15 +192,40 @@ enum WorkerState {
 struct ProcessInfo {
     pid: Pid,
     addr: Option<Addr<Process>>,
+    /// reload generation of worker at process start
+    generation: u64,
+    /// time of first stop or quit request
+    stopping: Cell<Option<Instant>>,
 }
 
 impl ProcessInfo {
+    fn new(pid: Pid, addr: Option<Addr<Process>>, generation: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            addr,
+            generation,
+            stopping: Cell::new(None),
+        }
+    }
+    fn stopping(&self) {
+        if self.stopping.get().is_none() {
+            self.stopping.set(Some(Instant::now()));
+        }
+    }
+    fn stuck(&self, max: Duration) -> bool {
+        match self.stopping.get() {
+            Some(time) => time.elapsed() > max,
+            None => false,
+        }
+    }
     fn stop(&self) {
+        self.stopping();
         if let Some(ref addr) = self.addr {
             addr.do_send(process::StopProcess);
         }
     }
     fn quit(&self, graceful: bool) {
+        self.stopping();
         if let Some(ref addr) = self.addr {
             addr.do_send(process::QuitProcess(graceful));
         }
@@ -84,6 +245,21 @@ impl ProcessInfo {
             addr.do_send(process::ResumeProcess);
         }
     }
+    fn suspend(&self, suspended: bool) {
+        if let Some(ref addr) = self.addr {
+            addr.do_send(process::SuspendProcess(suspended));
+        }
+    }
+    fn send(&self, cmd: WorkerCommand) {
+        if let Some(ref addr) = self.addr {
+            addr.do_send(process::SendCommand(cmd));
+        }
+    }
+    fn signal(&self, sig: Signal) {
+        if let Some(ref addr) = self.addr {
+            addr.do_send(process::SignalProcess(sig));
+        }
+    }
 }
 
 pub struct Worker {
@@ -91,9 +267,18 @@ pub struct Worker {
     cfg: ServiceConfig,
     state: WorkerState,
     pub events: Events,
+    pub failures: FailureCounters,
     pub restore_from_fail: bool,
     started: Instant,
     restarts: u16,
+    /// incremented on reload or restart of running process, processes of
+    /// old generation are told apart from new ones during swap
+    generation: u64,
+    pending: Option<Reason>,
+    /// restart of crashed process is delayed until this time
+    hold: Option<Instant>,
+    failed: Option<FailedWorker>,
+    pause: PauseState,
     addr: Addr<FeService>,
 }
 
@@ -105,25 +290,113 @@ impl Worker {
             addr,
             state: WorkerState::Initial,
             events: Events::new(50),
+            failures: FailureCounters::default(),
             started: Instant::now(),
             restore_from_fail: false,
             restarts: 0,
+            generation: 0,
+            pending: None,
+            hold: None,
+            failed: None,
+            pause: PauseState::Running,
         }
     }
 
+    /// set configuration for new worker processes
+    pub fn set_config(&mut self, cfg: ServiceConfig) {
+        self.cfg = cfg;
+    }
+
     pub fn start(&mut self, reason: Reason) {
         let id = self.idx;
+        self.pending = None;
+        self.hold = None;
+        self.pause = PauseState::Running;
         match self.state {
             WorkerState::Initial | WorkerState::Stopped | WorkerState::Failed => {
                 debug!("Starting worker process id: {:?}", id);
-                let (pid, addr) = Process::start(self.idx, &self.cfg, self.addr.clone());
-                self.state = WorkerState::Starting(ProcessInfo { pid, addr });
+                let (pid, addr) = self.spawn();
+                let info = ProcessInfo::new(pid, addr, self.generation);
+                self.state = WorkerState::Starting(info);
                 self.events.add(State::Starting, reason, str(pid));
             }
             _ => (),
         }
     }
 
+    /// fork new worker process
+    fn spawn(&mut self) -> (Pid, Option<Addr<Process>>) {
+        self.failures.starts += 1;
+        statsd::count(&self.cfg.name, "starts");
+        Process::start(self.idx, &self.cfg, self.addr.clone())
+    }
+
+    /// start worker after failure, if service limits restart concurrency
+    /// or crashed process is held, start is queued until service starts it
+    /// with `start_pending`
+    fn restart(&mut self, reason: Reason) {
+        statsd::count(&self.cfg.name, "restarts");
+        self.generation += 1;
+        if self.cfg.restart_concurrency > 0 || self.hold.is_some() {
+            self.pending = Some(reason);
+        } else {
+            self.start(reason);
+        }
+    }
+
+    /// start queued worker restart
+    pub fn start_pending(&mut self) {
+        if let Some(reason) = self.pending.take() {
+            self.start(reason);
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// restart of crashed process is delayed by `hold_failed`,
+    /// checked at time `now`
+    pub fn is_held(&self, now: Instant) -> bool {
+        self.hold.is_some_and(|hold| now < hold)
+    }
+
+    /// record details of crashed process and delay its restart
+    fn hold(&mut self, pid: Pid, err: &ProcessError) {
+        if self.cfg.hold_failed == 0 {
+            return;
+        }
+        let output = |path: &Option<String>| {
+            path.as_ref()
+                .and_then(|path| utils::tail(path, FAILED_OUTPUT_LINES).ok())
+                .unwrap_or_default()
+        };
+        let failed = FailedWorker {
+            pid: format!("{}", pid),
+            reason: err.into(),
+            time: now_rfc3339(),
+            stdout: output(&self.cfg.stdout),
+            stderr: output(&self.cfg.stderr),
+            cgroup: self
+                .cfg
+                .cgroup
+                .as_ref()
+                .map(|cfg| cgroup::usage(cfg, &self.cfg.name)),
+        };
+        self.failed = Some(failed);
+
+        let hold = Duration::new(u64::from(self.cfg.hold_failed), 0);
+        self.hold = Some(Instant::now() + hold);
+        info!(
+            "Holding failed worker for {} seconds before restart (pid:{})",
+            self.cfg.hold_failed, pid
+        );
+    }
+
+    pub fn is_starting(&self) -> bool {
+        matches!(self.state, WorkerState::Starting(_))
+    }
+
     pub fn loaded(&mut self, pid: Pid) {
         let state = std::mem::replace(&mut self.state, WorkerState::Initial);
 
@@ -168,10 +441,7 @@ impl Worker {
     }
 
     pub fn is_running(&self) -> bool {
-        match self.state {
-            WorkerState::Running(_) => true,
-            _ => false,
-        }
+        matches!(self.state, WorkerState::Running(_))
     }
 
     pub fn is_failed(&self) -> bool {
@@ -182,13 +452,129 @@ impl Worker {
         }
     }
 
+    /// worker has no process and is not failed
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, WorkerState::Initial | WorkerState::Stopped)
+    }
+
     pub fn is_stopped(&self) -> bool {
+        matches!(self.state, WorkerState::Stopped)
+    }
+
+    pub fn name(&self) -> String {
+        format!("worker({})", self.idx + 1)
+    }
+
+    /// worker process actors
+    pub fn processes(&self) -> Vec<(Pid, Addr<Process>)> {
+        let mut processes = Vec::new();
+        {
+            let mut add = |info: &ProcessInfo| {
+                if let Some(ref addr) = info.addr {
+                    processes.push((info.pid, addr.clone()));
+                }
+            };
+            match self.state {
+                WorkerState::Starting(ref process)
+                | WorkerState::Running(ref process)
+                | WorkerState::Stopping(ref process) => add(process),
+                WorkerState::Reloading(ref process, ref old_proc)
+                | WorkerState::Restarting(ref process, ref old_proc)
+                | WorkerState::StoppingOld(ref process, ref old_proc)
+                | WorkerState::StoppingAll(ref process, ref old_proc) => {
+                    add(process);
+                    add(old_proc);
+                }
+                _ => (),
+            }
+        }
+        processes
+    }
+
+    /// reload generation of worker process
+    pub fn generation(&self, pid: Pid) -> Option<u64> {
+        match self.state {
+            WorkerState::Starting(ref process)
+            | WorkerState::Running(ref process)
+            | WorkerState::Stopping(ref process) if process.pid == pid => {
+                Some(process.generation)
+            }
+            WorkerState::Reloading(ref process, ref old_proc)
+            | WorkerState::Restarting(ref process, ref old_proc)
+            | WorkerState::StoppingOld(ref process, ref old_proc)
+            | WorkerState::StoppingAll(ref process, ref old_proc) => {
+                if process.pid == pid {
+                    Some(process.generation)
+                } else if old_proc.pid == pid {
+                    Some(old_proc.generation)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// pid belongs to current or old worker process
+    fn has_pid(&self, pid: Pid) -> bool {
         match self.state {
-            WorkerState::Stopped => true,
+            WorkerState::Starting(ref process)
+            | WorkerState::Running(ref process)
+            | WorkerState::Stopping(ref process) => process.pid == pid,
+            WorkerState::Reloading(ref process, ref old_proc)
+            | WorkerState::Restarting(ref process, ref old_proc)
+            | WorkerState::StoppingOld(ref process, ref old_proc)
+            | WorkerState::StoppingAll(ref process, ref old_proc) => {
+                process.pid == pid || old_proc.pid == pid
+            }
             _ => false,
         }
     }
 
+    /// unexpected exit of current worker process or process that did not
+    /// stop in time, old process of reload and stopped processes exit on request
+    fn is_failure(&self, pid: Pid, err: &ProcessError) -> bool {
+        if let ProcessError::StopTimeout = *err {
+            return self.has_pid(pid);
+        }
+        let current = match self.state {
+            WorkerState::Starting(ref process)
+            | WorkerState::Running(ref process)
+            | WorkerState::Reloading(ref process, _)
+            | WorkerState::Restarting(ref process, _)
+            | WorkerState::StoppingOld(ref process, _) => process.pid == pid,
+            _ => false,
+        };
+        current && !self.is_success(err)
+    }
+
+    fn count_failure(&mut self, err: &ProcessError) {
+        let failures = &mut self.failures;
+        match *err {
+            ProcessError::FailedToStart(_)
+            | ProcessError::NoHandshake
+            | ProcessError::ConfigError(..)
+            | ProcessError::InitFailed
+            | ProcessError::BootFailed => failures.failed_starts += 1,
+            ProcessError::StartupTimeout(_) | ProcessError::StopTimeout => {
+                failures.timeouts += 1
+            }
+            ProcessError::Heartbeat => failures.heartbeat_failures += 1,
+            ProcessError::ProbeFailed(_) => failures.probe_failures += 1,
+            ProcessError::PipeClosed => failures.pipe_closed += 1,
+            ProcessError::ProtocolDesync(_) => failures.protocol_desyncs += 1,
+            ProcessError::Signal(sig) => *failures.signals.entry(sig).or_insert(0) += 1,
+            ProcessError::ExitCode(code) => {
+                *failures.exit_codes.entry(code).or_insert(0) += 1
+            }
+        }
+
+        statsd::count(&self.cfg.name, "failures");
+        if let ProcessError::Heartbeat = *err {
+            statsd::count(&self.cfg.name, "heartbeat_failures");
+        }
+    }
+
     pub fn pid(&self) -> Option<Pid> {
         match self.state {
             WorkerState::Running(ref process) => Some(process.pid),
@@ -197,14 +583,67 @@ impl Worker {
         }
     }
 
+    fn state_name(&self) -> &'static str {
+        match self.state {
+            WorkerState::Initial => "initial",
+            WorkerState::Starting(_) => "starting",
+            WorkerState::Reloading(..) => "reloading",
+            WorkerState::Restarting(..) => "restarting",
+            WorkerState::Running(_) => "running",
+            WorkerState::StoppingOld(..) => "stopping-old",
+            WorkerState::Stopping(_) | WorkerState::StoppingAll(..) => "stopping",
+            WorkerState::Failed => "failed",
+            WorkerState::Stopped => "stopped",
+        }
+    }
+
+    /// worker state for diagnostics
+    pub fn snapshot(&self) -> WorkerSnapshot {
+        let pid = self.pid().map(|pid| format!("{}", pid));
+        let events: Vec<Event> = Vec::from(&self.events);
+
+        // running since last `Running` event of current process
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let uptime = events
+            .iter()
+            .rev()
+            .find(|ev| match ev.state {
+                State::Running => pid.is_some() && ev.pid == pid,
+                _ => false,
+            }).map(|ev| now.saturating_sub(ev.timestamp));
+
+        let now = Instant::now();
+        let held = match self.hold {
+            Some(hold) if now < hold => Some((hold - now).as_secs()),
+            _ => None,
+        };
+
+        WorkerSnapshot {
+            pid,
+            uptime,
+            held,
+            name: self.name(),
+            state: self.state_name().to_owned(),
+            restarts: self.restarts,
+            reason: events.last().map(|ev| ev.reason.clone()),
+            failed: self.failed.clone(),
+            events,
+        }
+    }
+
     pub fn reload(&mut self, graceful: bool, reason: Reason) {
         let state = std::mem::replace(&mut self.state, WorkerState::Initial);
+        self.pause = PauseState::Running;
 
         match state {
             WorkerState::Running(process) => {
                 // start new worker
-                let (pid, addr) = Process::start(self.idx, &self.cfg, self.addr.clone());
-                let info = ProcessInfo { pid, addr };
+                self.generation += 1;
+                let (pid, addr) = self.spawn();
+                let info = ProcessInfo::new(pid, addr, self.generation);
 
                 if graceful {
                     info!("Reloading worker: (pid:{})", process.pid);
@@ -227,6 +666,7 @@ impl Worker {
 
     pub fn stop(&mut self, reason: Reason) {
         let state = std::mem::replace(&mut self.state, WorkerState::Initial);
+        self.pending = None;
 
         match state {
             WorkerState::Initial | WorkerState::Stopped | WorkerState::Failed => {
@@ -241,34 +681,72 @@ impl Worker {
             WorkerState::Stopping(process) => {
                 self.state = WorkerState::Stopping(process)
             }
+            WorkerState::StoppingAll(process, old_proc) => {
+                self.state = WorkerState::StoppingAll(process, old_proc)
+            }
             WorkerState::StoppingOld(process, old_proc) => {
                 old_proc.quit(true);
                 process.stop();
                 self.events.add(State::Stopping, reason, str(process.pid));
-                self.state = WorkerState::Stopping(process);
+                self.state = WorkerState::StoppingAll(process, old_proc);
             }
             WorkerState::Running(process) => {
                 process.stop();
                 self.events.add(State::Stopping, reason, str(process.pid));
                 self.state = WorkerState::Stopping(process);
             }
-            WorkerState::Reloading(process, old_proc) => {
+            WorkerState::Reloading(process, old_proc)
+            | WorkerState::Restarting(process, old_proc) => {
+                // stop takes precedence over reload, new process is not
+                // loaded yet, so both processes have to exit
                 process.quit(true);
                 old_proc.stop();
                 self.events.add(State::Stopping, reason, str(old_proc.pid));
-                self.state = WorkerState::Stopping(old_proc);
+                self.state = WorkerState::StoppingAll(process, old_proc);
             }
-            WorkerState::Restarting(process, old_proc) => {
-                process.quit(true);
-                old_proc.stop();
-                self.events.add(State::Stopping, reason, str(old_proc.pid));
-                self.state = WorkerState::Stopping(old_proc);
+        }
+    }
+
+    /// stop new process of unfinished reload and keep old process running,
+    /// returns `false` if worker has no running process, starting process
+    /// without old process keeps starting
+    pub fn abort_reload(&mut self, reason: Reason) -> bool {
+        let state = std::mem::replace(&mut self.state, WorkerState::Initial);
+        self.pending = None;
+
+        match state {
+            WorkerState::Reloading(process, old_proc)
+            | WorkerState::Restarting(process, old_proc) => {
+                process.quit(false);
+                self.events
+                    .add(State::ReloadFailed, reason, str(process.pid));
+                self.state = WorkerState::Running(old_proc);
+                true
+            }
+            state => {
+                let running = matches!(
+                    state,
+                    WorkerState::Running(_) | WorkerState::StoppingOld(_, _)
+                );
+                self.state = state;
+                running
             }
         }
     }
 
+    /// clear restart counter, failed worker is marked as stopped
+    /// clear failure counters, queued restart is kept
+    pub fn reset(&mut self) {
+        self.restarts = 0;
+        if let WorkerState::Failed = self.state {
+            self.state = WorkerState::Stopped;
+            self.events.add(State::Stopped, Reason::ConsoleRequest, None);
+        }
+    }
+
     pub fn quit(&mut self, reason: Reason) {
         let state = std::mem::replace(&mut self.state, WorkerState::Initial);
+        self.pending = None;
 
         match state {
             WorkerState::Initial | WorkerState::Stopped | WorkerState::Failed => {
@@ -281,6 +759,7 @@ impl Worker {
                 self.state = WorkerState::Stopping(process);
             }
             WorkerState::Stopping(process) => {
+                process.quit(true);
                 self.state = WorkerState::Stopping(process)
             }
             WorkerState::StoppingOld(process, old_proc) => {
@@ -288,29 +767,44 @@ impl Worker {
                 process.quit(true);
                 self.events
                     .add(State::StoppingOld, reason, str(process.pid));
-                self.state = WorkerState::Stopping(process);
+                self.state = WorkerState::StoppingAll(process, old_proc);
+            }
+            WorkerState::StoppingAll(process, old_proc) => {
+                process.quit(true);
+                old_proc.quit(true);
+                self.state = WorkerState::StoppingAll(process, old_proc);
             }
             WorkerState::Running(process) => {
                 process.quit(true);
                 self.events.add(State::Stopping, reason, str(process.pid));
                 self.state = WorkerState::Stopping(process);
             }
-            WorkerState::Reloading(process, old_proc) => {
-                process.quit(true);
-                old_proc.quit(true);
-                self.events.add(State::Stopping, reason, str(old_proc.pid));
-                self.state = WorkerState::Stopping(old_proc);
-            }
-            WorkerState::Restarting(process, old_proc) => {
+            WorkerState::Reloading(process, old_proc)
+            | WorkerState::Restarting(process, old_proc) => {
                 process.quit(true);
                 old_proc.quit(true);
                 self.events.add(State::Stopping, reason, str(old_proc.pid));
-                self.state = WorkerState::Stopping(old_proc);
+                self.state = WorkerState::StoppingAll(process, old_proc);
             }
         }
     }
 
     pub fn message(&mut self, pid: Pid, message: &WorkerMessage) {
+        // startup progress of new worker process
+        if let WorkerMessage::forked = *message {
+            match self.state {
+                WorkerState::Starting(ref process)
+                | WorkerState::Reloading(ref process, _)
+                | WorkerState::Restarting(ref process, _)
+                    if process.pid == pid =>
+                {
+                    self.events.add(State::Preparing, Reason::None, str(pid));
+                }
+                _ => (),
+            }
+            return;
+        }
+
         let reload = match self.state {
             WorkerState::Running(ref process) => process.pid == pid,
             _ => false,
@@ -320,6 +814,12 @@ impl Worker {
             match *message {
                 WorkerMessage::reload => self.reload(true, Reason::WorkerRequest),
                 WorkerMessage::restart => self.reload(false, Reason::WorkerRequest),
+                WorkerMessage::paused if self.pause == PauseState::Pausing => {
+                    self.pause = PauseState::Paused;
+                }
+                WorkerMessage::resumed if self.pause == PauseState::Resuming => {
+                    self.pause = PauseState::Running;
+                }
                 _ => (),
             }
         }
@@ -328,6 +828,7 @@ impl Worker {
     pub fn pause(&mut self, reason: Reason) {
         if let WorkerState::Running(ref process) = self.state {
             process.pause();
+            self.pause = PauseState::Pausing;
             self.events.add(State::Paused, reason, str(process.pid));
         }
     }
@@ -335,12 +836,167 @@ impl Worker {
     pub fn resume(&mut self, reason: Reason) {
         if let WorkerState::Running(ref process) = self.state {
             process.resume();
+            self.pause = PauseState::Resuming;
             self.events.add(State::Running, reason, str(process.pid));
         }
     }
 
-    pub fn exited(&mut self, pid: Pid, err: &ProcessError) {
+    /// send command to running worker process, returns pid of process
+    pub fn notify(&self, cmd: WorkerCommand) -> Option<Pid> {
+        match self.state {
+            WorkerState::Running(ref process)
+            | WorkerState::StoppingOld(ref process, _) => {
+                process.send(cmd);
+                Some(process.pid)
+            }
+            _ => None,
+        }
+    }
+
+    /// send signal to running worker process, directly or with `signal` command
+    pub fn signal(&self, sig: Signal) -> Option<Pid> {
+        match self.state {
+            WorkerState::Running(ref process)
+            | WorkerState::StoppingOld(ref process, _) => {
+                match self.cfg.signal_delivery {
+                    SignalDelivery::kill => process.signal(sig),
+                    SignalDelivery::protocol => process.send(WorkerCommand::signal {
+                        name: format!("{:?}", sig),
+                    }),
+                }
+                Some(process.pid)
+            }
+            _ => None,
+        }
+    }
+
+    /// worker process did not confirm pause or resume command in time,
+    /// command is considered applied
+    pub fn pause_timeout(&mut self) {
+        let pid = match self.state {
+            WorkerState::Running(ref process) => process.pid,
+            _ => return,
+        };
+        match self.pause {
+            PauseState::Pausing => {
+                warn!("Worker did not confirm pause, considered paused (pid:{})", pid);
+                self.pause = PauseState::Paused;
+            }
+            PauseState::Resuming => {
+                warn!("Worker did not confirm resume, considered running (pid:{})", pid);
+                self.pause = PauseState::Running;
+            }
+            _ => (),
+        }
+    }
+
+    /// check if worker process confirmed pause or resume command,
+    /// worker without running process has nothing to confirm
+    pub fn pause_confirmed(&self, paused: bool) -> bool {
+        match self.state {
+            WorkerState::Running(_) => if paused {
+                self.pause == PauseState::Paused
+            } else {
+                self.pause == PauseState::Running
+            },
+            _ => true,
+        }
+    }
+
+    /// worker process got stopped or continued by signal
+    pub fn suspended(&mut self, pid: Pid, suspended: bool) {
+        match self.state {
+            WorkerState::Starting(ref process)
+            | WorkerState::Running(ref process)
+            | WorkerState::Stopping(ref process)
+                if process.pid == pid =>
+            {
+                process.suspend(suspended);
+            }
+            WorkerState::Reloading(ref process, ref old_proc)
+            | WorkerState::Restarting(ref process, ref old_proc)
+            | WorkerState::StoppingOld(ref process, ref old_proc)
+            | WorkerState::StoppingAll(ref process, ref old_proc) => {
+                if process.pid == pid {
+                    process.suspend(suspended);
+                } else if old_proc.pid == pid {
+                    old_proc.suspend(suspended);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// exit code that is configured as successful exit
+    fn is_success(&self, err: &ProcessError) -> bool {
+        match *err {
+            ProcessError::ExitCode(0) => true,
+            ProcessError::ExitCode(code) => {
+                self.cfg.success_exit_codes.contains(&(code as u8))
+            }
+            _ => false,
+        }
+    }
+
+    /// exit code or startup error that prevents worker restart
+    fn no_restart(&self, err: &ProcessError) -> bool {
+        match *err {
+            ProcessError::ExitCode(code) => {
+                self.cfg.no_restart_exit_codes.contains(&(code as u8))
+            }
+            ProcessError::NoHandshake => !self.cfg.restart_no_handshake,
+            _ => false,
+        }
+    }
+
+    /// Force kill process that stays in stopping state longer than
+    /// `max_stopping_time`, returns pid of killed process
+    pub fn kill_stuck(&self) -> Option<Pid> {
+        let max = Duration::new(u64::from(self.cfg.max_stopping_time()), 0);
+        let process = match self.state {
+            WorkerState::Starting(ref process)
+            | WorkerState::Running(ref process)
+            | WorkerState::Stopping(ref process) => Some(process),
+            WorkerState::Reloading(ref process, ref old_proc)
+            | WorkerState::Restarting(ref process, ref old_proc)
+            | WorkerState::StoppingOld(ref process, ref old_proc)
+            | WorkerState::StoppingAll(ref process, ref old_proc) => {
+                if old_proc.stuck(max) {
+                    Some(old_proc)
+                } else {
+                    Some(process)
+                }
+            }
+            _ => None,
+        };
+
+        match process {
+            Some(process) if process.stuck(max) => {
+                // failed fork leaves placeholder pid, failure is not processed yet
+                if libc::pid_t::from(process.pid) <= 0 || process.addr.is_none() {
+                    return None;
+                }
+                process.quit(false);
+                let pid = if self.cfg.signal_group() {
+                    Pid::from_raw(-libc::pid_t::from(process.pid))
+                } else {
+                    process.pid
+                };
+                let _ = kill(pid, Signal::SIGKILL);
+                Some(process.pid)
+            }
+            _ => None,
+        }
+    }
+
+    /// process exit, returns `true` if running worker failed and got restarted
+    pub fn exited(&mut self, pid: Pid, err: &ProcessError) -> bool {
+        // exit of same process can be reported twice, count current processes only
+        if self.is_failure(pid, err) {
+            self.count_failure(err);
+        }
         let state = std::mem::replace(&mut self.state, WorkerState::Initial);
+        let mut restarted = false;
 
         match state {
             WorkerState::Running(process) => {
@@ -348,12 +1004,27 @@ impl Worker {
                     self.state = WorkerState::Running(process);
                 } else {
                     match *err {
-                        ProcessError::StartupTimeout => {
+                        ProcessError::StartupTimeout(_) => {
                             self.state = WorkerState::Running(process);
                             self.events.add(State::Running, err.into(), str(pid));
                             self.restore_from_fail = true;
                             self.reload(false, Reason::ReloadAftreTimeout);
-                            return;
+                            return false;
+                        }
+                        _ if self.no_restart(err) => {
+                            process.quit(false);
+                            error!("Worker exited, restart is disabled (pid:{})", pid);
+                            self.state = WorkerState::Failed;
+                            self.events.add(State::Failed, err.into(), str(pid));
+                        }
+                        _ if self.is_success(err) => {
+                            // worker exited on purpose, i.e. to recycle itself,
+                            // restart is not delayed and is not a failure
+                            process.quit(false);
+                            self.started = Instant::now();
+                            self.state = WorkerState::Initial;
+                            self.events.add(State::Stopped, err.into(), str(pid));
+                            self.restart(Reason::RestartExitedRunningWorker);
                         }
                         _ => {
                             // kill worker
@@ -363,7 +1034,9 @@ impl Worker {
                             self.started = Instant::now();
                             self.state = WorkerState::Initial;
                             self.events.add(State::Stopped, err.into(), str(pid));
-                            self.start(Reason::RestartFailedRunningWorker);
+                            self.hold(pid, err);
+                            self.restart(Reason::RestartFailedRunningWorker);
+                            restarted = true;
                         }
                     }
                 }
@@ -380,7 +1053,7 @@ impl Worker {
                         //    self.events.add(State::Failed, Reason::from(err), str(pid));
                         //    return
                         //}
-                        ProcessError::ExitCode(0) => {
+                        _ if self.is_success(err) => {
                             // check for fast restart
                             let now = Instant::now();
                             if now.duration_since(self.started) > Duration::new(10, 0) {
@@ -395,13 +1068,14 @@ impl Worker {
 
                     self.events.add(State::Failed, Reason::from(err), str(pid));
 
-                    if self.restarts < self.cfg.restarts {
+                    if self.restarts < self.cfg.restarts && !self.no_restart(err) {
                         // just in case
                         process.quit(false);
 
                         // start new worker
                         self.state = WorkerState::Initial;
-                        self.start(Reason::RestartFailedStartingWorker);
+                        self.hold(pid, err);
+                        self.restart(Reason::RestartFailedStartingWorker);
                     } else {
                         error!("Can not start worker (pid:{})", process.pid);
                         self.state = WorkerState::Failed;
@@ -423,7 +1097,7 @@ impl Worker {
                         //    self.state = WorkerState::Running(old_proc);
                         //    return
                         //}
-                        ProcessError::ExitCode(0) => {
+                        _ if self.is_success(err) => {
                             // check for fast restart
                             let now = Instant::now();
                             if now.duration_since(self.started) > Duration::new(3, 0) {
@@ -438,11 +1112,11 @@ impl Worker {
 
                     self.events.add(State::ReloadFailed, err.into(), str(pid));
 
-                    if self.restarts < self.cfg.restarts {
+                    if self.restarts < self.cfg.restarts && !self.no_restart(err) {
                         // start new worker
-                        let (pid, addr) =
-                            Process::start(self.idx, &self.cfg, self.addr.clone());
-                        let info = ProcessInfo { pid, addr };
+                        self.generation += 1;
+                        let (pid, addr) = self.spawn();
+                        let info = ProcessInfo::new(pid, addr, self.generation);
                         self.state = WorkerState::Reloading(info, old_proc);
                     } else {
                         error!(
@@ -482,7 +1156,7 @@ impl Worker {
                         //    self.state = WorkerState::Running(old_proc);
                         //    return
                         //},
-                        ProcessError::ExitCode(0) => {
+                        _ if self.is_success(err) => {
                             // check for fast restart
                             let now = Instant::now();
                             if now.duration_since(self.started) > Duration::new(3, 0) {
@@ -499,11 +1173,11 @@ impl Worker {
 
                     self.events.add(State::RestartFailed, err.into(), str(pid));
 
-                    if self.restarts < self.cfg.restarts {
+                    if self.restarts < self.cfg.restarts && !self.no_restart(err) {
                         // start new worker
-                        let (pid, addr) =
-                            Process::start(self.idx, &self.cfg, self.addr.clone());
-                        let info = ProcessInfo { pid, addr };
+                        self.generation += 1;
+                        let (pid, addr) = self.spawn();
+                        let info = ProcessInfo::new(pid, addr, self.generation);
                         self.state = WorkerState::Restarting(info, old_proc);
                     } else {
                         error!(
@@ -535,7 +1209,8 @@ impl Worker {
                     self.restarts += 1;
                     self.state = WorkerState::Initial;
                     self.events.add(State::Failed, err.into(), str(pid));
-                    self.start(Reason::NewProcessDied);
+                    self.hold(pid, err);
+                    self.restart(Reason::NewProcessDied);
                 } else if old_proc.pid == pid {
                     self.restore_from_fail = false;
                     self.events.add(State::Stopped, Reason::None, str(pid));
@@ -554,7 +1229,20 @@ impl Worker {
                     self.state = WorkerState::Stopping(process);
                 }
             }
+            WorkerState::StoppingAll(process, old_proc) => {
+                // worker is stopped after both processes exit
+                if process.pid == pid {
+                    self.events.add(State::Stopped, err.into(), str(pid));
+                    self.state = WorkerState::Stopping(old_proc);
+                } else if old_proc.pid == pid {
+                    self.events.add(State::Stopped, err.into(), str(pid));
+                    self.state = WorkerState::Stopping(process);
+                } else {
+                    self.state = WorkerState::StoppingAll(process, old_proc);
+                }
+            }
             state => self.state = state,
         }
+        restarted
     }
 }